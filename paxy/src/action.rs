@@ -9,11 +9,19 @@ pub fn handle_action(console_input: CliTemplate) -> Result<(), Error> {
     if let Some(entity) = console_input.entity {
         match entity {
             EntitySubcommand::Package(package_subcommand) => {
-                package::handle_package_action(package_subcommand).context(PackageSnafu)?;
+                package::handle_package_action(package_subcommand, &console_input.global_args)
+                    .context(PackageSnafu)?;
             }
             EntitySubcommand::Repository(repository_subcommand) => {
-                repository::handle_repository_action(repository_subcommand)
-                    .context(RepositorySnafu)?;
+                repository::handle_repository_action(
+                    repository_subcommand,
+                    &console_input.global_args,
+                )
+                .context(RepositorySnafu)?;
+            }
+            EntitySubcommand::Generation(generation_subcommand) => {
+                generation::handle_generation_action(generation_subcommand)
+                    .context(GenerationSnafu)?;
             }
         }
     }
@@ -34,6 +42,10 @@ pub enum Error {
     #[non_exhaustive]
     #[snafu(display("Could not complete repository action:\n  {source}"))]
     Repository { source: repository::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("Could not complete generation action:\n  {source}"))]
+    Generation { source: generation::Error },
 }
 
 // endregion: ERRORS
@@ -48,6 +60,7 @@ use crate::app::ui::console_template::cli::CliTemplate;
 
 // region: EXTERNAL-SUBMODULES
 
+pub mod generation;
 pub mod package;
 pub mod repository;
 
@@ -0,0 +1,57 @@
+//! Handles generation related actions.
+
+pub fn handle_generation_action(generation_subcommand: GenerationSubcommand) -> Result<(), Error> {
+    match generation_subcommand {
+        GenerationSubcommand::List(generation_list_arguments) => {
+            list::handle_generation_list_action(generation_list_arguments)
+                .context(GenerationListSnafu {})?
+        }
+        GenerationSubcommand::Rollback(generation_rollback_arguments) => {
+            rollback::handle_generation_rollback_action(generation_rollback_arguments)
+                .context(GenerationRollbackSnafu {})?
+        }
+        GenerationSubcommand::Prune(generation_prune_arguments) => {
+            prune::handle_generation_prune_action(generation_prune_arguments)
+                .context(GenerationPruneSnafu {})?
+        }
+    }
+
+    Ok(())
+}
+
+// region: ERRORS
+
+#[derive(Debug, Snafu)]
+#[snafu(visibility(pub(crate)))]
+#[non_exhaustive]
+pub enum Error {
+    #[non_exhaustive]
+    #[snafu(display("Could not list:\n  {source}"))]
+    GenerationList { source: list::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("Could not roll back:\n  {source}"))]
+    GenerationRollback { source: rollback::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("Could not prune:\n  {source}"))]
+    GenerationPrune { source: prune::Error },
+}
+
+// endregion: ERRORS
+
+// region: IMPORTS
+
+use snafu::{ResultExt, Snafu};
+
+use crate::app::ui::console_template::cli::GenerationSubcommand;
+
+// endregion: IMPORTS
+
+// region: MODULES
+
+pub mod list;
+pub mod prune;
+pub mod rollback;
+
+// endregion: MODULES
@@ -0,0 +1,96 @@
+//! Lists the generations recorded in the generation history, oldest first.
+
+pub fn handle_generation_list_action(
+    generation_list_arguments: GenerationListArguments,
+) -> Result<(), Error> {
+    let history_filepath = History::filepath(&data::paxy_home());
+    let history = History::load(&history_filepath).context(HistorySnafu {})?;
+    let current_number = history
+        .current()
+        .map(|generation| generation.number);
+
+    if history
+        .generations
+        .is_empty()
+    {
+        tracing::info!(
+            "{} No generations have been recorded yet.",
+            console::Emoji("📭", "")
+        );
+    }
+    for generation in history
+        .generations
+        .iter()
+    {
+        let is_current = Some(generation.number) == current_number;
+        tracing::info!(
+            "{} {} {} {} {}",
+            console::Emoji("🗂️", ""),
+            format!("#{}", generation.number).green(),
+            generation.description,
+            format!(
+                "[{} package(s), {}]",
+                generation
+                    .packages
+                    .len(),
+                ui::format_unix_timestamp(generation.created_at)
+            )
+            .dimmed(),
+            if is_current { "(current)" } else { "" }
+        );
+        if generation_list_arguments.with_packages {
+            for (name, state) in generation
+                .packages
+                .iter()
+            {
+                tracing::info!("    {} {}", name, state.version);
+            }
+        }
+        tracing::info!(
+            target: "PLAIN",
+            "{} {} {}",
+            generation.number,
+            generation.created_at,
+            generation.description
+        );
+    }
+    tracing::info!(
+        target: "JSON",
+        "{}",
+        serde_json::to_string(&history.generations).context(SerializeJsonSnafu {})?
+    );
+
+    Ok(())
+}
+
+// region: ERRORS
+
+#[derive(Debug, Snafu)]
+#[snafu(visibility(pub(crate)))]
+#[non_exhaustive]
+pub enum Error {
+    #[non_exhaustive]
+    #[snafu(display("in the generation history: {source}"))]
+    History { source: generation::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("could not serialize the generations to JSON: {source}"))]
+    SerializeJson { source: serde_json::Error },
+}
+
+// endregion: ERRORS
+
+// region: IMPORTS
+
+use owo_colors::OwoColorize;
+use snafu::{ResultExt, Snafu};
+
+use crate::{
+    app::ui::{self, console_template::cli::GenerationListArguments},
+    data::{
+        self,
+        generation::{self, History},
+    },
+};
+
+// endregion: IMPORTS
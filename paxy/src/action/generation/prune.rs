@@ -0,0 +1,89 @@
+//! Deletes the oldest generations from the generation history, keeping a given
+//! number of the most recent ones, and then removes the builds from the cache
+//! that none of the remaining generations hold. Pruned generations can no
+//! longer be rolled back to.
+
+pub fn handle_generation_prune_action(
+    generation_prune_arguments: GenerationPruneArguments,
+) -> Result<(), Error> {
+    let (removed_numbers, removed_build_count) =
+        prune_generations(&data::paxy_home(), generation_prune_arguments.kept_count)?;
+
+    if removed_numbers.is_empty() {
+        tracing::info!(
+            "{} There are no generations to prune.",
+            console::Emoji("✅", "")
+        );
+    } else {
+        tracing::info!(
+            "{} Pruned {} generation(s), from #{} to #{}",
+            console::Emoji("🧹", ""),
+            removed_numbers.len(),
+            removed_numbers[0],
+            removed_numbers[removed_numbers.len() - 1],
+        );
+    }
+    if removed_build_count > 0 {
+        tracing::info!(
+            "{} Removed {} cached build(s)",
+            console::Emoji("🗑️", ""),
+            removed_build_count
+        );
+    }
+    for number in removed_numbers.iter() {
+        tracing::info!(target: "PLAIN", "{}", number);
+    }
+
+    Ok(())
+}
+
+/// Prunes all but the given number of most recent generations, and the
+/// cached builds only they held. Returns the numbers of the generations
+/// removed and how many builds were removed.
+pub fn prune_generations(paxy_home: &Path, kept_count: usize) -> Result<(Vec<u64>, usize), Error> {
+    let history_filepath = History::filepath(paxy_home);
+    let mut history = History::load(&history_filepath).context(HistorySnafu {})?;
+    let removed_numbers = history.prune(kept_count);
+    if !removed_numbers.is_empty() {
+        history
+            .save(&history_filepath)
+            .context(HistorySnafu {})?;
+    }
+    let removed_build_count = cache::prune(paxy_home, &history).context(CacheSnafu {})?;
+
+    Ok((removed_numbers, removed_build_count))
+}
+
+// region: ERRORS
+
+#[derive(Debug, Snafu)]
+#[snafu(visibility(pub(crate)))]
+#[non_exhaustive]
+pub enum Error {
+    #[non_exhaustive]
+    #[snafu(display("in the generation history: {source}"))]
+    History { source: generation::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("could not prune the cache of built packages: {source}"))]
+    Cache { source: std::io::Error },
+}
+
+// endregion: ERRORS
+
+// region: IMPORTS
+
+use std::path::Path;
+
+use snafu::{ResultExt, Snafu};
+
+use crate::{
+    app::ui::console_template::cli::GenerationPruneArguments,
+    data::{
+        self,
+        cache,
+        generation::{self, History},
+    },
+};
+
+// endregion: IMPORTS
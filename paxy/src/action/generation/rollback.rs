@@ -0,0 +1,387 @@
+//! Restores the installed packages of an earlier generation. Packages that
+//! are not part of it are uninstalled, and the others are installed at the
+//! version and with the features they had then - from the cache when a build
+//! of them is there, and from the repositories otherwise. The rollback runs as
+//! a single transaction and is itself recorded as a new generation, so that it
+//! can be undone in turn.
+
+pub fn handle_generation_rollback_action(
+    generation_rollback_arguments: GenerationRollbackArguments,
+) -> Result<(), Error> {
+    let config = config::load_conf();
+    let paxy_home = data::paxy_home();
+    let mut transaction = Transaction::begin(&paxy_home).context(TransactionSnafu {})?;
+    let mut database = Database::load(&Database::filepath(&paxy_home)).context(DatabaseSnafu {})?;
+    let history = History::load(&History::filepath(&paxy_home)).context(HistorySnafu {})?;
+    let number = generation_rollback_arguments.generation_number;
+    let generation = history
+        .get(number)
+        .context(GenerationNotFoundSnafu { number })?;
+
+    let plan = RollbackPlan::new(&database, generation);
+    if plan.is_empty() {
+        tracing::info!(
+            "{} The installed packages already match generation {}",
+            console::Emoji("✅", ""),
+            number
+        );
+        return Ok(());
+    }
+
+    let catalog = Catalog::new(&paxy_home).context(CatalogSnafu {})?;
+    roll_back(
+        &plan,
+        &catalog,
+        &config,
+        &paxy_home,
+        &mut database,
+        &mut transaction,
+    )?;
+    transaction
+        .commit(&database, &format!("roll back to generation {number}"))
+        .context(TransactionSnafu {})?;
+
+    tracing::info!(
+        "{} Rolled back to generation {}",
+        console::Emoji("⏪", ""),
+        number
+    );
+
+    Ok(())
+}
+
+/// What has to change to get from the installed packages to those of a
+/// generation.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RollbackPlan {
+    /// Names of the installed packages that are not part of the generation.
+    pub removals: Vec<String>,
+    /// Packages of the generation that are missing or installed differently.
+    pub installs: Vec<(String, PackageState)>,
+}
+
+impl RollbackPlan {
+    pub fn new(database: &Database, generation: &Generation) -> Self {
+        let removals = database
+            .packages
+            .keys()
+            .filter(|name| {
+                !generation
+                    .packages
+                    .contains_key(*name)
+            })
+            .cloned()
+            .collect();
+        let installs = generation
+            .packages
+            .iter()
+            .filter(|(name, state)| {
+                database
+                    .get(name)
+                    .is_none_or(|package| &PackageState::of(package) != *state)
+            })
+            .map(|(name, state)| (name.clone(), state.clone()))
+            .collect();
+
+        Self { removals, installs }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.removals
+            .is_empty()
+            && self
+                .installs
+                .is_empty()
+    }
+}
+
+/// Carries out a rollback plan as part of a transaction, updating the
+/// database to match.
+pub fn roll_back(
+    plan: &RollbackPlan,
+    catalog: &Catalog,
+    config: &Config,
+    paxy_home: &Path,
+    database: &mut Database,
+    transaction: &mut Transaction,
+) -> Result<(), Error> {
+    for name in plan.removals.iter() {
+        let Some(package) = database
+            .get(name)
+            .cloned()
+        else {
+            continue;
+        };
+        let uninstall_plan = UninstallPlan::new(&package).context(UninstallSnafu {})?;
+        uninstall::uninstall_package(&package, &uninstall_plan, transaction)
+            .context(UninstallSnafu {})?;
+        database.remove(name);
+        tracing::info!(
+            "{} Uninstalled {} {}",
+            console::Emoji("🗑️", ""),
+            package.name.green(),
+            package.version
+        );
+    }
+
+    for (name, state) in plan.installs.iter() {
+        let install_location = config.install_location(state.install_type);
+        let installation = match cache::lookup(
+            paxy_home,
+            name,
+            &state.version,
+            state.install_type,
+            &state.features,
+        ) {
+            Some(artifact) => install::install_cached_package(
+                &artifact,
+                &state.features,
+                install_location,
+                database,
+                transaction,
+            ),
+            None => {
+                // Prefer the repository the package came from
+                let candidate = catalog
+                    .candidates(name)
+                    .context(CatalogSnafu {})?
+                    .into_iter()
+                    .filter(|candidate| candidate.version == state.version)
+                    .min_by_key(|candidate| candidate.repository != state.repository)
+                    .context(ArtifactUnavailableSnafu {
+                        name,
+                        version: state
+                            .version
+                            .clone(),
+                    })?;
+                install::install_package(
+                    &candidate.manifest_filepath,
+                    &state.features,
+                    state.install_type,
+                    install_location,
+                    paxy_home,
+                    database,
+                    transaction,
+                )
+            }
+        }
+        .context(InstallSnafu {})?;
+        tracing::info!(
+            "{} Installed {} {}",
+            console::Emoji("📦", ""),
+            name.green(),
+            state.version
+        );
+        database.insert(
+            installation.into_installed_package(
+                state
+                    .repository
+                    .clone(),
+                state.install_type,
+            ),
+        );
+    }
+
+    Ok(())
+}
+
+// region: ERRORS
+
+#[derive(Debug, Snafu)]
+#[snafu(visibility(pub(crate)))]
+#[non_exhaustive]
+pub enum Error {
+    #[non_exhaustive]
+    #[snafu(display("there is no generation {number}. See `paxy generation list`"))]
+    GenerationNotFound { number: u64 },
+
+    #[non_exhaustive]
+    #[snafu(display(
+        "'{name}' {version} is neither in the cache nor in any repository, so it cannot be \
+         installed again"
+    ))]
+    ArtifactUnavailable { name: String, version: Version },
+
+    #[non_exhaustive]
+    #[snafu(display("in the installed-package database: {source}"))]
+    Database { source: installed::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("in the generation history: {source}"))]
+    History { source: generation::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("in the repositories: {source}"))]
+    Catalog { source: resolver::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("in the transaction: {source}"))]
+    Transaction { source: transaction::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("{source}"))]
+    Install { source: install::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("{source}"))]
+    Uninstall { source: uninstall::Error },
+}
+
+// endregion: ERRORS
+
+// region: IMPORTS
+
+use std::path::Path;
+
+use owo_colors::OwoColorize;
+use semver::Version;
+use snafu::{OptionExt, ResultExt, Snafu};
+
+use crate::{
+    action::package::{
+        install,
+        resolver::{self, Catalog},
+        transaction::{self, Transaction},
+        uninstall::{self, UninstallPlan},
+    },
+    app::ui::console_template::cli::GenerationRollbackArguments,
+    data::{
+        self,
+        cache,
+        config::{self, Config},
+        generation::{self, Generation, History, PackageState},
+        installed::{self, Database},
+    },
+};
+
+// endregion: IMPORTS
+
+// region: TESTS
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeSet, fs, path::PathBuf};
+
+    use super::*;
+    use crate::data::{config::InstallType, repository::tests::write_manifest};
+
+    /// Writes a package into a repository directory, installing a file named
+    /// after it that holds its version.
+    fn write_package(repository_dirpath: &Path, name: &str, version: &str) -> PathBuf {
+        let manifest_filepath = write_manifest(
+            repository_dirpath,
+            name,
+            version,
+            &format!(
+                r#"
+[[sources]]
+path = "src"
+
+[[install]]
+copy = {{ from = "{name}", to = "bin/{name}" }}
+"#
+            ),
+        );
+        let source_dirpath = manifest_filepath.with_file_name("src");
+        fs::create_dir_all(&source_dirpath).unwrap();
+        fs::write(source_dirpath.join(name), version).unwrap();
+        manifest_filepath
+    }
+
+    #[test]
+    fn roll_back_to_an_earlier_generation_from_the_cache() {
+        let temporary_dirpath = tempfile::tempdir().unwrap();
+        let paxy_home = temporary_dirpath
+            .path()
+            .join(".paxy");
+        let prefix = temporary_dirpath
+            .path()
+            .join("prefix");
+        let config = Config {
+            repositories: None,
+            system_install_location: prefix.clone(),
+            user_install_location: prefix.clone(),
+            default_install_type: InstallType::User,
+            repository_index_url: None,
+        };
+        let mut database = Database::default();
+        let install = |packages: &[(&str, &str)], database: &mut Database| {
+            let mut transaction = Transaction::begin(&paxy_home).unwrap();
+            for (name, version) in packages {
+                let manifest_filepath = write_package(temporary_dirpath.path(), name, version);
+                let installation = install::install_package(
+                    &manifest_filepath,
+                    &BTreeSet::new(),
+                    InstallType::User,
+                    &prefix,
+                    &paxy_home,
+                    database,
+                    &mut transaction,
+                )
+                .unwrap();
+                database.insert(installation.into_installed_package(None, InstallType::User));
+                // Only the cached build remains to install it from again
+                fs::remove_dir_all(
+                    manifest_filepath
+                        .parent()
+                        .unwrap(),
+                )
+                .unwrap();
+            }
+            transaction
+                .commit(database, "install")
+                .unwrap();
+        };
+        install(&[("hello", "1.0.0")], &mut database);
+        install(&[("hello", "2.0.0"), ("extra", "1.0.0")], &mut database);
+
+        let history = History::load(&History::filepath(&paxy_home)).unwrap();
+        let plan = RollbackPlan::new(
+            &database,
+            history
+                .get(1)
+                .unwrap(),
+        );
+        assert_eq!(plan.removals, ["extra"]);
+        assert_eq!(plan.installs.len(), 1);
+
+        let catalog = Catalog::new(&paxy_home).unwrap();
+        let mut transaction = Transaction::begin(&paxy_home).unwrap();
+        roll_back(
+            &plan,
+            &catalog,
+            &config,
+            &paxy_home,
+            &mut database,
+            &mut transaction,
+        )
+        .unwrap();
+        transaction
+            .commit(&database, "roll back to generation 1")
+            .unwrap();
+
+        assert_eq!(
+            fs::read_to_string(prefix.join("bin/hello")).unwrap(),
+            "1.0.0"
+        );
+        assert!(!prefix
+            .join("bin/extra")
+            .exists());
+        let history = History::load(&History::filepath(&paxy_home)).unwrap();
+        assert_eq!(
+            history
+                .current()
+                .map(|generation| generation.number),
+            Some(3)
+        );
+        assert!(RollbackPlan::new(
+            &database,
+            history
+                .get(1)
+                .unwrap()
+        )
+        .is_empty());
+    }
+}
+
+// endregion: TESTS
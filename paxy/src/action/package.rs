@@ -1,6 +1,9 @@
 //! Handles package related actions.
 
-pub fn handle_package_action(package_subcommand: PackageSubcommand) -> Result<(), Error> {
+pub fn handle_package_action<G: GlobalArguments>(
+    package_subcommand: PackageSubcommand,
+    global_arguments: G,
+) -> Result<(), Error> {
     use crate::app::ui::console_template::cli::*;
 
     match package_subcommand {
@@ -16,16 +19,32 @@ pub fn handle_package_action(package_subcommand: PackageSubcommand) -> Result<()
                 .context(PackageInstallSnafu {})?
         }
         PackageSubcommand::Update(package_update_arguments) => {
-            update::handle_package_update_action(package_update_arguments)
+            update::handle_package_update_action(package_update_arguments, global_arguments)
                 .context(PackageUpdateSnafu {})?
         }
         PackageSubcommand::Uninstall(package_uninstall_arguments) => {
-            uninstall::handle_package_uninstall_action(package_uninstall_arguments)
-                .context(PackageUninstallSnafu {})?
+            uninstall::handle_package_uninstall_action(
+                package_uninstall_arguments,
+                global_arguments,
+            )
+            .context(PackageUninstallSnafu {})?
         }
         PackageSubcommand::Downgrade(package_downgrade_arguments) => {
-            downgrade::handle_package_downgrade_action(package_downgrade_arguments)
-                .context(PackageDowngradeSnafu {})?
+            downgrade::handle_package_downgrade_action(
+                package_downgrade_arguments,
+                global_arguments,
+            )
+            .context(PackageDowngradeSnafu {})?
+        }
+        PackageSubcommand::Pin(package_pin_arguments) => {
+            pin::handle_package_pin_action(package_pin_arguments).context(PackagePinSnafu {})?
+        }
+        PackageSubcommand::Unpin(package_unpin_arguments) => {
+            unpin::handle_package_unpin_action(package_unpin_arguments)
+                .context(PackageUnpinSnafu {})?
+        }
+        PackageSubcommand::Owns(package_owns_arguments) => {
+            owns::handle_package_owns_action(package_owns_arguments).context(PackageOwnsSnafu {})?
         }
     }
 
@@ -61,6 +80,18 @@ pub enum Error {
     #[non_exhaustive]
     #[snafu(display("Could not downgrade:\n  {source}"))]
     PackageDowngrade { source: downgrade::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("Could not pin:\n  {source}"))]
+    PackagePin { source: pin::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("Could not unpin:\n  {source}"))]
+    PackageUnpin { source: unpin::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("Could not look up the owner:\n  {source}"))]
+    PackageOwns { source: owns::Error },
 }
 
 // endregion: ERRORS
@@ -69,7 +100,7 @@ pub enum Error {
 
 use snafu::{ResultExt, Snafu};
 
-use crate::app::ui::console_template::cli::PackageSubcommand;
+use crate::app::ui::{console_template::cli::PackageSubcommand, GlobalArguments};
 
 // endregion: IMPORTS
 
@@ -78,8 +109,13 @@ use crate::app::ui::console_template::cli::PackageSubcommand;
 pub mod downgrade;
 pub mod install;
 pub mod list;
+pub mod owns;
+pub mod pin;
+pub mod resolver;
 pub mod search;
+pub mod transaction;
 pub mod uninstall;
+pub mod unpin;
 pub mod update;
 
 // endregion: MODULES
@@ -1,12 +1,372 @@
-#[allow(unused)]
-pub fn handle_package_downgrade_action(
+//! Downgrades an installed package to an older version offered by its
+//! repository. Besides the versions in the repository as it is, the versions
+//! it has dropped are found in its git history and tags, and extracted from
+//! there when picked. The downgrade goes through the resolver and the
+//! transactional install, like any other install.
+
+pub fn handle_package_downgrade_action<G: GlobalArguments>(
     package_downgrade_arguments: PackageDowngradeArguments,
+    global_arguments: G,
+) -> Result<(), Error> {
+    let config = config::load_conf();
+    let paxy_home = data::paxy_home();
+    let mut database = Database::load(&Database::filepath(&paxy_home)).context(DatabaseSnafu {})?;
+    let package_name = &package_downgrade_arguments.package_name;
+    let package = database
+        .get(package_name)
+        .cloned()
+        .context(NotInstalledSnafu { name: package_name })?;
+    let available_versions = available_versions(&paxy_home, &package)?;
+
+    let chosen_version = match package_downgrade_arguments.version {
+        Some(version) => {
+            let version = Version::parse(&version).context(InvalidVersionSnafu { version })?;
+            available_versions
+                .iter()
+                .find(|available_version| available_version.version == version)
+                .context(VersionUnavailableSnafu {
+                    name: package_name,
+                    version,
+                })?
+        }
+        None => {
+            emit_available_versions(&package, &available_versions)?;
+            if available_versions.is_empty() || global_arguments.is_test() {
+                return Ok(());
+            }
+            match ui::ask("Pick a version to downgrade to (number or version, empty to cancel):")
+                .context(AskSnafu {})?
+            {
+                Some(answer) if answer.is_empty() => {
+                    tracing::info!("{} Downgrade cancelled", console::Emoji("✋", ""));
+                    return Ok(());
+                }
+                Some(answer) => pick(&available_versions, &answer)?,
+                None => {
+                    tracing::info!("Pass `--version <version>` to downgrade to one of them.");
+                    return Ok(());
+                }
+            }
+        }
+    };
+
+    // Versions that are only in the history are extracted for the duration of
+    // the install
+    let extraction_dirpath = paxy_home
+        .join("tmp")
+        .join(format!("downgrade-{}", std::process::id()));
+    let result = downgrade(
+        &package,
+        chosen_version,
+        package_downgrade_arguments.pin,
+        &config,
+        &paxy_home,
+        &extraction_dirpath,
+        &mut database,
+        global_arguments.is_test(),
+    );
+    if extraction_dirpath.exists() {
+        if let Err(error) = fs::remove_dir_all(&extraction_dirpath) {
+            tracing::warn!("Could not remove {:?}: {}", extraction_dirpath, error);
+        }
+    }
+
+    result
+}
+
+/// An older version of a package that its repository offers.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AvailableVersion {
+    pub version: Version,
+    pub repository: String,
+    /// The commit to take the version from, if the repository has dropped it
+    /// since.
+    pub commit: Option<String>,
+}
+
+/// Lists the versions older than the installed one that the repository of a
+/// package offers, newest first. A package installed from a manifest given by
+/// path is looked up in every repository.
+pub fn available_versions(
+    paxy_home: &Path,
+    package: &InstalledPackage,
+) -> Result<Vec<AvailableVersion>, Error> {
+    let mut available_versions = Vec::new();
+    for local_repository in repository::local_repositories(paxy_home)
+        .context(RepositorySnafu {})?
+        .into_iter()
+        .filter(|local_repository| {
+            package
+                .repository
+                .as_ref()
+                .is_none_or(|repository| repository == &local_repository.name)
+        })
+    {
+        let current_versions: Vec<Version> =
+            repository::package_manifest_entries(&local_repository.dirpath, &package.name)
+                .context(RepositorySnafu {})?
+                .into_iter()
+                .map(|entry| entry.version)
+                .collect();
+        let historical_versions: Vec<(Version, Option<String>)> =
+            history::package_versions(&local_repository.dirpath, &package.name)
+                .context(HistorySnafu {})?
+                .into_iter()
+                .filter(|historical_version| {
+                    !current_versions.contains(&historical_version.version)
+                })
+                .map(|historical_version| {
+                    (historical_version.version, Some(historical_version.commit))
+                })
+                .collect();
+        available_versions.extend(
+            current_versions
+                .into_iter()
+                .map(|version| (version, None))
+                .chain(historical_versions)
+                .filter(|(version, _)| version < &package.version)
+                .map(|(version, commit)| AvailableVersion {
+                    version,
+                    repository: local_repository
+                        .name
+                        .clone(),
+                    commit,
+                }),
+        );
+    }
+    // Stable, so that the repositories stay in order for the same version
+    available_versions.sort_by(|a, b| {
+        b.version
+            .cmp(&a.version)
+    });
+
+    Ok(available_versions)
+}
+
+fn emit_available_versions(
+    package: &InstalledPackage,
+    available_versions: &[AvailableVersion],
+) -> Result<(), Error> {
+    tracing::info!(
+        target: "JSON",
+        "{}",
+        serde_json::to_string(available_versions).context(SerializeJsonSnafu {})?
+    );
+    if available_versions.is_empty() {
+        tracing::info!(
+            "{} No version of {} older than {} is available",
+            console::Emoji("📭", ""),
+            package.name.green(),
+            package.version
+        );
+        return Ok(());
+    }
+
+    tracing::info!(
+        "{} Versions of {} older than {}:",
+        console::Emoji("📜", ""),
+        package.name.green(),
+        package.version
+    );
+    for (index, available_version) in available_versions
+        .iter()
+        .enumerate()
+    {
+        let origin = match &available_version.commit {
+            Some(commit) => format!(
+                "[{}, from commit {}]",
+                available_version.repository,
+                &commit[..commit.len().min(7)]
+            ),
+            None => format!("[{}]", available_version.repository),
+        };
+        tracing::info!(
+            "  {}) {} {}",
+            index + 1,
+            available_version.version,
+            origin.dimmed()
+        );
+        tracing::info!(
+            target: "PLAIN",
+            "{}\t{}\t{}",
+            available_version.version,
+            available_version.repository,
+            available_version
+                .commit
+                .as_deref()
+                .unwrap_or("")
+        );
+    }
+
+    Ok(())
+}
+
+/// Finds the version picked by its number in the list or by the version
+/// itself.
+fn pick<'a>(
+    available_versions: &'a [AvailableVersion],
+    answer: &str,
+) -> Result<&'a AvailableVersion, Error> {
+    let picked = match answer.parse::<usize>() {
+        Ok(number) => number
+            .checked_sub(1)
+            .and_then(|index| available_versions.get(index)),
+        Err(_) => Version::parse(answer)
+            .ok()
+            .and_then(|version| {
+                available_versions
+                    .iter()
+                    .find(|available_version| available_version.version == version)
+            }),
+    };
+
+    picked.context(InvalidChoiceSnafu { answer })
+}
+
+#[allow(clippy::too_many_arguments)]
+fn downgrade(
+    package: &InstalledPackage,
+    chosen_version: &AvailableVersion,
+    pin: bool,
+    config: &Config,
+    paxy_home: &Path,
+    extraction_dirpath: &Path,
+    database: &mut Database,
+    is_test: bool,
 ) -> Result<(), Error> {
-    use crate::app::ui::console_template::cli::*;
+    let mut catalog = Catalog::new(paxy_home).context(ResolveSnafu {})?;
+    if let Some(commit) = &chosen_version.commit {
+        let local_repository = repository::local_repositories(paxy_home)
+            .context(RepositorySnafu {})?
+            .into_iter()
+            .find(|local_repository| local_repository.name == chosen_version.repository)
+            .context(VersionUnavailableSnafu {
+                name: &package.name,
+                version: chosen_version
+                    .version
+                    .clone(),
+            })?;
+        let manifest_filepath = history::extract_package_version(
+            &local_repository.dirpath,
+            commit,
+            &package.name,
+            &chosen_version.version,
+            extraction_dirpath,
+        )
+        .context(HistorySnafu {})?;
+        catalog.add_candidate(Candidate {
+            name: package.name.clone(),
+            version: chosen_version
+                .version
+                .clone(),
+            manifest_filepath,
+            repository: Some(
+                chosen_version
+                    .repository
+                    .clone(),
+            ),
+        });
+    }
 
-    todo!();
+    let requirement = resolver::exactly(&chosen_version.version);
+    let planned_database = database.clone();
+    // A new pin replaces the old one, which would stand in the way
+    if pin {
+        database.unpin(&package.name);
+    }
+    let resolution = resolver::resolve(
+        &catalog,
+        database,
+        &[Request {
+            name: package.name.clone(),
+            repository: None,
+            requirement: requirement.clone(),
+            preference: Preference::Latest,
+            features: BTreeSet::new(),
+        }],
+    )
+    .context(ResolveSnafu {})?;
+    for planned_install in resolution
+        .to_install
+        .iter()
+    {
+        tracing::info!(
+            "{} Will install {} {}",
+            console::Emoji("📋", ""),
+            planned_install
+                .candidate
+                .name
+                .green(),
+            planned_install
+                .candidate
+                .version
+        );
+    }
+    if is_test {
+        return Ok(());
+    }
 
-    // Ok(())
+    let mut transaction = Transaction::begin(paxy_home).context(TransactionSnafu {})?;
+    transaction
+        .check_database(&planned_database)
+        .context(TransactionSnafu {})?;
+    for planned_install in resolution.to_install {
+        let install_type = database
+            .get(
+                &planned_install
+                    .candidate
+                    .name,
+            )
+            .map_or(config.default_install_type, |package| package.install_type);
+        let installation = install::install_package(
+            &planned_install
+                .candidate
+                .manifest_filepath,
+            &planned_install.features,
+            install_type,
+            config.install_location(install_type),
+            paxy_home,
+            database,
+            &mut transaction,
+        )
+        .context(InstallSnafu {})?;
+        tracing::info!(
+            "{} Installed {} {}",
+            console::Emoji("📦", ""),
+            installation
+                .manifest
+                .name
+                .green(),
+            installation
+                .manifest
+                .version
+        );
+        database.insert(
+            installation.into_installed_package(
+                planned_install
+                    .candidate
+                    .repository,
+                install_type,
+            ),
+        );
+    }
+    if pin {
+        database.pin(&package.name, requirement.clone());
+        tracing::info!(
+            "{} Pinned {} to {}",
+            console::Emoji("📌", ""),
+            package.name.green(),
+            requirement
+        );
+    }
+    transaction
+        .commit(
+            database,
+            &format!("downgrade {} to {}", package.name, chosen_version.version),
+        )
+        .context(TransactionSnafu {})?;
+
+    Ok(())
 }
 
 // region: ERRORS
@@ -16,17 +376,141 @@ pub fn handle_package_downgrade_action(
 #[non_exhaustive]
 pub enum Error {
     #[non_exhaustive]
-    #[snafu(display(""))]
-    Dummy {},
+    #[snafu(display("the package '{name}' is not installed"))]
+    NotInstalled { name: String },
+
+    #[non_exhaustive]
+    #[snafu(display("'{version}' is not a valid version: {source}"))]
+    InvalidVersion {
+        version: String,
+        source: semver::Error,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display(
+        "no older version {version} of '{name}' is available. See `paxy package downgrade \
+         {name}` for the versions that are"
+    ))]
+    VersionUnavailable { name: String, version: Version },
+
+    #[non_exhaustive]
+    #[snafu(display("'{answer}' is neither the number of a version listed nor one of them"))]
+    InvalidChoice { answer: String },
+
+    #[non_exhaustive]
+    #[snafu(display("could not ask for a version: {source}"))]
+    Ask { source: std::io::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("in the installed-package database: {source}"))]
+    Database { source: installed::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("in the repositories: {source}"))]
+    Repository { source: repository::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("in the repository history: {source}"))]
+    History { source: history::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("could not resolve the dependencies: {source}"))]
+    Resolve { source: resolver::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("in the transaction: {source}"))]
+    Transaction { source: transaction::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("{source}"))]
+    Install {
+        #[snafu(source(from(install::Error, Box::new)))]
+        source: Box<install::Error>,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("could not serialize the versions to JSON: {source}"))]
+    SerializeJson { source: serde_json::Error },
 }
 
 // endregion: ERRORS
 
 // region: IMPORTS
 
-#[allow(unused)]
-use snafu::{ResultExt, Snafu};
+use std::{collections::BTreeSet, fs, path::Path};
+
+use owo_colors::OwoColorize;
+use semver::Version;
+use serde::Serialize;
+use snafu::{OptionExt, ResultExt, Snafu};
 
-use crate::app::ui::console_template::cli::PackageDowngradeArguments;
+use super::{
+    install,
+    resolver::{self, Candidate, Catalog, Preference, Request},
+    transaction::{self, Transaction},
+};
+use crate::{
+    app::ui::{self, console_template::cli::PackageDowngradeArguments, GlobalArguments},
+    data::{
+        self,
+        config::{self, Config},
+        installed::{self, Database, InstalledPackage},
+        repository::{self, history},
+    },
+};
 
 // endregion: IMPORTS
+
+// region: TESTS
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{installed::tests::installed_package, repository::tests::write_manifest};
+
+    #[test]
+    fn list_older_versions_from_the_repository_of_the_package() {
+        let paxy_home = tempfile::tempdir().unwrap();
+        for (repository, version) in [
+            ("main", "0.9.0"),
+            ("main", "1.0.0"),
+            ("main", "2.0.0"),
+            ("other", "1.5.0"),
+        ] {
+            write_manifest(
+                &data::repositories_dirpath(paxy_home.path()).join(repository),
+                "hello",
+                version,
+                "",
+            );
+        }
+        let package = InstalledPackage {
+            repository: Some("main".to_string()),
+            install_location: paxy_home
+                .path()
+                .to_path_buf(),
+            ..installed_package("hello", "2.0.0")
+        };
+
+        let available_versions = available_versions(paxy_home.path(), &package).unwrap();
+
+        assert_eq!(
+            available_versions
+                .iter()
+                .map(|available_version| available_version
+                    .version
+                    .to_string())
+                .collect::<Vec<_>>(),
+            ["1.0.0", "0.9.0"]
+        );
+        assert_eq!(
+            pick(&available_versions, "2")
+                .ok()
+                .map(|available_version| &available_version.version),
+            Some(&Version::new(0, 9, 0))
+        );
+        assert!(pick(&available_versions, "1.5.0").is_err());
+    }
+}
+
+// endregion: TESTS
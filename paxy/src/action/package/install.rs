@@ -1,18 +1,746 @@
-#[allow(unused)]
+//! Installs packages from their manifests. A package is given either as the
+//! path to a manifest (or to a directory containing one), or as a name to look
+//! up in the installed repositories, optionally qualified by the repository to
+//! take it from, as in `repository/package`.
+//!
+//! The sources of a package are gathered into a temporary work directory, and
+//! its install steps place files into a staging directory within it. Only
+//! once every step has succeeded are the staged files placed under the
+//! install location. All the packages installed together form a single
+//! transaction, which is rolled back if any of them fails.
+//!
+//! Packages are installed under the user or the system install location, as
+//! asked for with `--user` or `--system`, or else as configured by
+//! `default_install_type`.
+
 pub fn handle_package_install_action(
     package_install_arguments: PackageInstallArguments,
 ) -> Result<(), Error> {
-    use crate::app::ui::console_template::cli::*;
+    let config = config::load_conf();
+    let paxy_home = data::paxy_home();
+    let install_type = match (
+        package_install_arguments.user,
+        package_install_arguments.system,
+    ) {
+        (true, _) => InstallType::User,
+        (_, true) => InstallType::System,
+        _ => config.default_install_type,
+    };
+    let mut transaction = Transaction::begin(&paxy_home).context(TransactionSnafu {})?;
+    let database_filepath = Database::filepath(&paxy_home);
+    let mut database = Database::load(&database_filepath).context(DatabaseSnafu {})?;
+
+    let mut catalog = Catalog::new(&paxy_home).context(ResolveSnafu {})?;
+    let requests = package_install_arguments
+        .package_names
+        .iter()
+        .map(|package| request_for(&mut catalog, package))
+        .collect::<Result<Vec<_>, _>>()?;
+    let resolution = resolver::resolve(&catalog, &database, &requests).context(ResolveSnafu {})?;
+
+    for (name, version) in resolution
+        .kept
+        .iter()
+        .filter(|(name, _)| {
+            requests
+                .iter()
+                .any(|request| &request.name == name)
+        })
+    {
+        tracing::info!(
+            "{} {} {} is already installed",
+            console::Emoji("✅", ""),
+            name.green(),
+            version
+        );
+    }
+
+    for planned_install in resolution.to_install {
+        let installation = install_package(
+            &planned_install
+                .candidate
+                .manifest_filepath,
+            &planned_install.features,
+            install_type,
+            config.install_location(install_type),
+            &paxy_home,
+            &database,
+            &mut transaction,
+        )?;
+
+        tracing::info!(
+            "{} Installed {} {} ({} files under {:?})",
+            console::Emoji("📦", ""),
+            installation
+                .manifest
+                .name
+                .green(),
+            installation
+                .manifest
+                .version,
+            installation
+                .files
+                .len(),
+            installation.install_location,
+        );
+        tracing::info!(
+            target: "PLAIN",
+            "{} {}",
+            installation.manifest.name,
+            installation.manifest.version
+        );
+
+        // Later packages must see the files of earlier ones as owned
+        database.insert(
+            installation.into_installed_package(
+                planned_install
+                    .candidate
+                    .repository,
+                install_type,
+            ),
+        );
+    }
+    transaction
+        .commit(
+            &database,
+            &format!(
+                "install {}",
+                package_install_arguments
+                    .package_names
+                    .join(" ")
+            ),
+        )
+        .context(TransactionSnafu {})?;
+
+    Ok(())
+}
+
+/// Turns a package given on the commandline into a request for the resolver.
+/// A package may be followed by the features to enable, as in
+/// `name[feature1,feature2]`. Anything that exists on disk is treated as a
+/// path to a manifest or to a directory with a manifest in it, and is added to
+/// the catalog to be installed as it is. Anything else is treated as the name
+/// of a package in the repositories, or as `repository/package` to take it
+/// from a particular repository.
+fn request_for(catalog: &mut Catalog, package: &str) -> Result<Request, Error> {
+    let (package, features) = split_features(package)?;
+    let path = Path::new(package);
+    if !path.exists() {
+        let (repository, name) = match package.split_once('/') {
+            Some((repository, name)) => {
+                ensure!(
+                    !repository.is_empty() && !name.is_empty() && !name.contains('/'),
+                    InvalidPackageNameSnafu { package }
+                );
+                (Some(repository.to_string()), name)
+            }
+            None => (None, package),
+        };
+        return Ok(Request {
+            name: name.to_string(),
+            repository,
+            requirement: VersionReq::STAR,
+            preference: Preference::Installed,
+            features,
+        });
+    }
+
+    let manifest_filepath = if path.is_dir() {
+        manifest::manifest_filepath_in(path).context(ManifestNotInDirectorySnafu { path })?
+    } else {
+        path.to_path_buf()
+    };
+    let manifest = PackageManifest::from_path(&manifest_filepath).context(ManifestSnafu {
+        path: &manifest_filepath,
+    })?;
+    catalog.add_local(&manifest_filepath, &manifest);
+
+    Ok(Request {
+        requirement: resolver::exactly(&manifest.version),
+        name: manifest.name,
+        repository: None,
+        preference: Preference::Reinstall,
+        features,
+    })
+}
+
+/// Splits a package given as `name[feature1,feature2]` into its name and
+/// features. A package without brackets, or one that exists on disk as
+/// written, has no features.
+fn split_features(package: &str) -> Result<(&str, BTreeSet<String>), Error> {
+    if Path::new(package).exists() {
+        return Ok((package, BTreeSet::new()));
+    }
+    let Some((name, features)) = package
+        .strip_suffix(']')
+        .and_then(|package| package.split_once('['))
+    else {
+        ensure!(
+            !package.contains(['[', ']']),
+            InvalidFeatureListSnafu { package }
+        );
+        return Ok((package, BTreeSet::new()));
+    };
+
+    let features: BTreeSet<String> = features
+        .split(',')
+        .map(|feature| {
+            feature
+                .trim()
+                .to_string()
+        })
+        .collect();
+    ensure!(
+        !name.is_empty()
+            && features
+                .iter()
+                .all(|feature| !feature.is_empty() && !feature.contains(['[', ']'])),
+        InvalidFeatureListSnafu { package }
+    );
+
+    Ok((name, features))
+}
+
+/// The outcome of installing a single package.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Installation {
+    pub manifest: PackageManifest,
+    /// The optional features of the package that were installed.
+    pub features: BTreeSet<String>,
+    pub install_location: PathBuf,
+    /// Paths of the installed files, relative to the install location.
+    pub files: Vec<PathBuf>,
+    /// The configuration files among `files`, mapped to their checksums.
+    pub config_files: BTreeMap<PathBuf, String>,
+}
+
+impl Installation {
+    /// Turns the installation into a record for the installed-package
+    /// database.
+    pub fn into_installed_package(
+        self,
+        repository: Option<String>,
+        install_type: InstallType,
+    ) -> InstalledPackage {
+        let dependencies = self
+            .manifest
+            .dependencies_with(&self.features);
+        InstalledPackage {
+            name: self.manifest.name,
+            version: self
+                .manifest
+                .version,
+            repository,
+            install_type,
+            install_location: self.install_location,
+            installed_at: installed::now(),
+            files: self.files,
+            config_files: self.config_files,
+            features: self.features,
+            dependencies,
+        }
+    }
+}
+
+/// Installs the package described by the manifest at the given path under the
+/// install location of the given install type as part of a transaction, using a
+/// temporary work directory under `paxy_home`. Nothing is placed under the
+/// install location if any of the package's files would overwrite a file that
+/// is not already its own. Files of an earlier installation of the package that
+/// are not part of the new one are removed. The build is kept in the cache.
+pub fn install_package(
+    manifest_filepath: &Path,
+    features: &BTreeSet<String>,
+    install_type: InstallType,
+    install_location: &Path,
+    paxy_home: &Path,
+    database: &Database,
+    transaction: &mut Transaction,
+) -> Result<Installation, Error> {
+    let manifest = PackageManifest::from_path(manifest_filepath).context(ManifestSnafu {
+        path: manifest_filepath,
+    })?;
+    if let Some(plugin) = &manifest.plugin {
+        return UnsupportedPluginSnafu {
+            name: manifest
+                .name
+                .clone(),
+            plugin,
+        }
+        .fail();
+    }
+    let manifest_dirpath = manifest_filepath
+        .parent()
+        .unwrap_or(Path::new("."));
+
+    let work_directory = WorkDirectory::create(paxy_home, &manifest)?;
+    fetch_sources(
+        paxy_home,
+        &manifest,
+        manifest_dirpath,
+        &work_directory.source_dirpath(),
+    )?;
+    run_install_steps(&manifest, features, &work_directory, install_location)?;
+    if let Err(error) = cache::store(
+        paxy_home,
+        manifest_filepath,
+        &manifest,
+        install_type,
+        features,
+        &work_directory.destination_dirpath(),
+    ) {
+        tracing::warn!("Could not cache the build of '{}': {error}", manifest.name);
+    }
+
+    place_staged_files(
+        manifest,
+        features,
+        &work_directory.destination_dirpath(),
+        install_location,
+        database,
+        transaction,
+    )
+}
+
+/// Installs a package from a build of it in the cache, without fetching or
+/// building its sources.
+pub fn install_cached_package(
+    artifact: &CachedArtifact,
+    features: &BTreeSet<String>,
+    install_location: &Path,
+    database: &Database,
+    transaction: &mut Transaction,
+) -> Result<Installation, Error> {
+    let manifest =
+        PackageManifest::from_path(&artifact.manifest_filepath).context(ManifestSnafu {
+            path: &artifact.manifest_filepath,
+        })?;
+
+    place_staged_files(
+        manifest,
+        features,
+        &artifact.files_dirpath,
+        install_location,
+        database,
+        transaction,
+    )
+}
+
+/// Places the staged files of a package under the install location, and
+/// removes the files of an earlier installation of it that are not part of
+/// the new one. Configuration files the user edited since the earlier
+/// installation are never overwritten: the new version of one still shipped is
+/// placed beside it with a `.paxynew` suffix, and one no longer shipped is
+/// kept with a `.paxysave` suffix.
+fn place_staged_files(
+    manifest: PackageManifest,
+    features: &BTreeSet<String>,
+    staged_dirpath: &Path,
+    install_location: &Path,
+    database: &Database,
+    transaction: &mut Transaction,
+) -> Result<Installation, Error> {
+    let files = relative_filepaths(staged_dirpath)?;
+    check_conflicts(&manifest.name, &files, install_location, database)?;
+    // Checksums are taken of the staged files rather than the placed ones, so
+    // that an edited configuration file left in place is still seen as edited
+    // against the version this installation ships.
+    let config_files = config_file_checksums(&manifest, &files, staged_dirpath)?;
+    let previous = database.get(&manifest.name);
+    let previous_in_place =
+        previous.filter(|previous| previous.install_location == install_location);
+    for file in files.iter() {
+        let staged_filepath = staged_dirpath.join(file);
+        let filepath = install_location.join(file);
+        let is_kept = match previous_in_place {
+            Some(previous) => {
+                previous
+                    .is_edited_config_file(file)
+                    .context(ChecksumSnafu { path: &filepath })?
+                    && config_files.get(file)
+                        != Some(
+                            &data::sha256_of_file(&filepath)
+                                .context(ChecksumSnafu { path: &filepath })?,
+                        )
+            }
+            None => false,
+        };
+        if is_kept {
+            let new_filepath = suffixed(&filepath, ".paxynew");
+            tracing::warn!(
+                "Kept the edited configuration file {:?}; its new version is at {:?}",
+                filepath,
+                new_filepath
+            );
+            transaction
+                .place_file(&staged_filepath, &new_filepath)
+                .context(TransactionSnafu {})?;
+        } else {
+            transaction
+                .place_file(&staged_filepath, &filepath)
+                .context(TransactionSnafu {})?;
+        }
+    }
+    if let Some(previous) = previous {
+        for stale_file in previous
+            .files
+            .iter()
+            .filter(|file| previous.install_location != install_location || !files.contains(file))
+        {
+            let stale_filepath = previous
+                .install_location
+                .join(stale_file);
+            if previous
+                .is_edited_config_file(stale_file)
+                .context(ChecksumSnafu {
+                    path: &stale_filepath,
+                })?
+            {
+                let backup_filepath = suffixed(&stale_filepath, ".paxysave");
+                tracing::warn!(
+                    "Kept the edited configuration file {:?} as {:?}",
+                    stale_filepath,
+                    backup_filepath
+                );
+                transaction
+                    .rename_file(&stale_filepath, &backup_filepath)
+                    .context(TransactionSnafu {})?;
+            } else {
+                transaction
+                    .remove_file(&stale_filepath)
+                    .context(TransactionSnafu {})?;
+            }
+        }
+    }
+
+    Ok(Installation {
+        manifest,
+        features: features.clone(),
+        install_location: install_location.to_path_buf(),
+        files,
+        config_files,
+    })
+}
+
+/// Appends a suffix to the file name of a path.
+fn suffixed(filepath: &Path, suffix: &str) -> PathBuf {
+    let mut suffixed_filepath = filepath
+        .as_os_str()
+        .to_owned();
+    suffixed_filepath.push(suffix);
+
+    PathBuf::from(suffixed_filepath)
+}
+
+/// Fetches every source of the package into the source directory. Sources
+/// with mirrors are fetched from the first of their URLs that works.
+fn fetch_sources(
+    paxy_home: &Path,
+    manifest: &PackageManifest,
+    manifest_dirpath: &Path,
+    source_dirpath: &Path,
+) -> Result<(), Error> {
+    for source in manifest
+        .sources
+        .iter()
+    {
+        let target_dirpath = match &source.destination {
+            Some(destination) => source_dirpath.join(destination),
+            None => source_dirpath.to_path_buf(),
+        };
+        fs::create_dir_all(&target_dirpath).context(CreateWorkDirectorySnafu {
+            path: &target_dirpath,
+        })?;
+
+        let location = source
+            .location()
+            .context(NoSourceLocationSnafu {
+                name: &manifest.name,
+            })?;
+        match location {
+            SourceLocation::Path(path) => {
+                let path = manifest_dirpath.join(path);
+                copy_into(&path, &target_dirpath)?;
+            }
+            SourceLocation::Url(url) if url.scheme() == "file" => {
+                let path = url
+                    .to_file_path()
+                    .ok()
+                    .context(InvalidFileUrlSnafu { url: url.as_str() })?;
+                copy_into(&path, &target_dirpath)?;
+            }
+            SourceLocation::Url(_) => mirrors::try_mirrors(
+                paxy_home,
+                &source.urls(),
+                MirrorOrder::Listed,
+                |url| download_into(url, &target_dirpath),
+                Error::is_mirror_fault,
+            )
+            .context(FetchSourceSnafu {})?,
+            SourceLocation::Git(_) => mirrors::try_mirrors(
+                paxy_home,
+                &source.urls(),
+                MirrorOrder::Listed,
+                |url| clone_into(url, &target_dirpath),
+                Error::is_mirror_fault,
+            )
+            .context(FetchSourceSnafu {})?,
+        }
+    }
+
+    Ok(())
+}
+
+/// Copies a file or a directory into the given directory, keeping its name
+/// when it is a file and merging its contents when it is a directory.
+fn copy_into(path: &Path, target_dirpath: &Path) -> Result<(), Error> {
+    if path.is_dir() {
+        copy_recursively(path, target_dirpath)
+    } else {
+        let file_name = path
+            .file_name()
+            .context(SourceNotFoundSnafu { path })?;
+        copy_recursively(path, &target_dirpath.join(file_name))
+    }
+}
+
+fn download_into(url: &Url, target_dirpath: &Path) -> Result<(), Error> {
+    let file_name = url
+        .path_segments()
+        .and_then(|mut segments| segments.rfind(|segment| !segment.is_empty()))
+        .unwrap_or("download");
+    let bytes = reqwest::blocking::Client::builder()
+        .connect_timeout(mirrors::CONNECT_TIMEOUT)
+        .timeout(mirrors::TRANSFER_TIMEOUT)
+        .build()
+        .and_then(|client| {
+            client
+                .get(url.clone())
+                .send()
+        })
+        .and_then(|response| response.error_for_status())
+        .and_then(|response| response.bytes())
+        .context(DownloadSourceSnafu { url: url.as_str() })?;
+    let filepath = target_dirpath.join(file_name);
+    fs::write(&filepath, bytes).context(CopySnafu {
+        from: url.as_str(),
+        to: &filepath,
+    })
+}
+
+fn clone_into(url: &Url, target_dirpath: &Path) -> Result<(), Error> {
+    let fetch_options =
+        mirrors::git_fetch_options(url).context(CloneSourceSnafu { url: url.as_str() })?;
+    git2::build::RepoBuilder::new()
+        .fetch_options(fetch_options)
+        .clone(url.as_str(), target_dirpath)
+        .context(CloneSourceSnafu { url: url.as_str() })?;
+
+    Ok(())
+}
+
+fn run_install_steps(
+    manifest: &PackageManifest,
+    features: &BTreeSet<String>,
+    work_directory: &WorkDirectory,
+    install_location: &Path,
+) -> Result<(), Error> {
+    let source_dirpath = work_directory.source_dirpath();
+    let destination_dirpath = work_directory.destination_dirpath();
 
-    todo!();
+    let features_variable = features
+        .iter()
+        .cloned()
+        .collect::<Vec<_>>()
+        .join(",");
 
-    // Ok(())
+    for step in manifest.install_steps_with(features) {
+        match step {
+            InstallStep::Copy { from, to } => {
+                let from = source_dirpath.join(from);
+                let to = destination_dirpath.join(to);
+                ensure!(from.exists(), SourceNotFoundSnafu { path: from });
+                copy_recursively(&from, &to)?;
+            }
+            InstallStep::Run(command) => {
+                let status = shell_command(command)
+                    .current_dir(&source_dirpath)
+                    .env("PAXY_SOURCE_DIR", &source_dirpath)
+                    .env("PAXY_DESTINATION_DIR", &destination_dirpath)
+                    .env("PAXY_INSTALL_LOCATION", install_location)
+                    .env("PAXY_FEATURES", &features_variable)
+                    .status()
+                    .context(RunStepSnafu { command })?;
+                ensure!(status.success(), StepFailedSnafu { command, status });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn shell_command(command: &str) -> process::Command {
+    if cfg!(target_os = "windows") {
+        let mut shell = process::Command::new("cmd");
+        shell.args(["/C", command]);
+        shell
+    } else {
+        let mut shell = process::Command::new("sh");
+        shell.args(["-c", command]);
+        shell
+    }
 }
 
-#[allow(dead_code)]
-#[allow(unused_variables)]
-fn plugin(manifest: PathBuf) -> PathBuf {
-    todo!()
+/// Ensures that none of the files about to be installed for a package would
+/// overwrite a file owned by another package, or a file that paxy does not
+/// know about.
+fn check_conflicts(
+    package_name: &str,
+    files: &[PathBuf],
+    install_location: &Path,
+    database: &Database,
+) -> Result<(), Error> {
+    for file in files {
+        let filepath = install_location.join(file);
+        match database.owner_of(&filepath) {
+            Some(owner) => ensure!(
+                owner.name == package_name,
+                FileConflictSnafu {
+                    path: filepath,
+                    package: package_name,
+                    owner: owner.name.clone(),
+                }
+            ),
+            None => ensure!(
+                fs::symlink_metadata(&filepath).is_err(),
+                UntrackedFileConflictSnafu {
+                    path: filepath,
+                    package: package_name,
+                }
+            ),
+        }
+    }
+
+    Ok(())
+}
+
+/// Records the checksums of the configuration files of a package, as staged
+/// under the given directory, so that edits made to them once installed can be
+/// detected.
+fn config_file_checksums(
+    manifest: &PackageManifest,
+    files: &[PathBuf],
+    staged_dirpath: &Path,
+) -> Result<BTreeMap<PathBuf, String>, Error> {
+    let mut config_files = BTreeMap::new();
+    for config_file in manifest
+        .config_files
+        .iter()
+    {
+        if !files.contains(config_file) {
+            tracing::warn!(
+                "The configuration file {:?} of '{}' was not installed",
+                config_file,
+                manifest.name
+            );
+            continue;
+        }
+        let filepath = staged_dirpath.join(config_file);
+        let checksum = data::sha256_of_file(&filepath).context(ChecksumSnafu { path: filepath })?;
+        config_files.insert(config_file.clone(), checksum);
+    }
+
+    Ok(config_files)
+}
+
+/// Lists the paths of all files under a directory, relative to it.
+pub(crate) fn relative_filepaths(dirpath: &Path) -> Result<Vec<PathBuf>, Error> {
+    let mut files = Vec::new();
+    let mut pending = vec![dirpath.to_path_buf()];
+    while let Some(current) = pending.pop() {
+        for entry in fs::read_dir(&current).context(ReadStagingSnafu { path: &current })? {
+            let path = entry
+                .context(ReadStagingSnafu { path: &current })?
+                .path();
+            if path.is_dir() {
+                pending.push(path);
+            } else if let Ok(relative) = path.strip_prefix(dirpath) {
+                files.push(relative.to_path_buf());
+            }
+        }
+    }
+    files.sort();
+
+    Ok(files)
+}
+
+fn copy_recursively(from: &Path, to: &Path) -> Result<(), Error> {
+    if from.is_dir() {
+        fs::create_dir_all(to).context(CopySnafu { from, to })?;
+        for entry in fs::read_dir(from).context(CopySnafu { from, to })? {
+            let entry = entry.context(CopySnafu { from, to })?;
+            copy_recursively(&entry.path(), &to.join(entry.file_name()))?;
+        }
+    } else {
+        if let Some(parent) = to.parent() {
+            fs::create_dir_all(parent).context(CopySnafu { from, to })?;
+        }
+        fs::copy(from, to).context(CopySnafu { from, to })?;
+    }
+
+    Ok(())
+}
+
+/// A temporary directory under `~/.paxy/tmp` holding the sources of a package
+/// and the files staged for installation. It is removed when dropped.
+struct WorkDirectory {
+    dirpath: PathBuf,
+}
+
+impl WorkDirectory {
+    fn create(paxy_home: &Path, manifest: &PackageManifest) -> Result<Self, Error> {
+        let dirpath = paxy_home
+            .join("tmp")
+            .join(format!(
+                "{}-{}-{}",
+                manifest.name,
+                manifest.version,
+                process::id()
+            ));
+        if dirpath.exists() {
+            fs::remove_dir_all(&dirpath).context(CreateWorkDirectorySnafu { path: &dirpath })?;
+        }
+        let work_directory = Self { dirpath };
+        for path in [
+            work_directory.source_dirpath(),
+            work_directory.destination_dirpath(),
+        ] {
+            fs::create_dir_all(&path).context(CreateWorkDirectorySnafu { path })?;
+        }
+
+        Ok(work_directory)
+    }
+
+    fn source_dirpath(&self) -> PathBuf {
+        self.dirpath
+            .join("source")
+    }
+
+    fn destination_dirpath(&self) -> PathBuf {
+        self.dirpath
+            .join("destination")
+    }
+}
+
+impl Drop for WorkDirectory {
+    fn drop(&mut self) {
+        if let Err(error) = fs::remove_dir_all(&self.dirpath) {
+            tracing::warn!(
+                "Could not remove the work directory {:?}: {error}",
+                self.dirpath
+            );
+        }
+    }
 }
 
 // region: ERRORS
@@ -22,19 +750,524 @@ fn plugin(manifest: PathBuf) -> PathBuf {
 #[non_exhaustive]
 pub enum Error {
     #[non_exhaustive]
-    #[snafu(display(""))]
-    Dummy {},
+    #[snafu(display(
+        "'{package}' is not a valid package with features. Write it as \
+         `name[feature1,feature2]`"
+    ))]
+    InvalidFeatureList { package: String },
+
+    #[non_exhaustive]
+    #[snafu(display(
+        "'{package}' is neither an existing path nor a package name. Write a package as `name` \
+         or `repository/name`"
+    ))]
+    InvalidPackageName { package: String },
+
+    #[non_exhaustive]
+    #[snafu(display("no manifest was found in the directory {path:?}"))]
+    ManifestNotInDirectory { path: PathBuf },
+
+    #[non_exhaustive]
+    #[snafu(display("in the installed-package database: {source}"))]
+    Database { source: installed::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("in the manifest at {path:?}: {source}"))]
+    Manifest {
+        path: PathBuf,
+        source: manifest::Error,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("could not resolve the dependencies: {source}"))]
+    Resolve { source: resolver::Error },
+
+    #[non_exhaustive]
+    #[snafu(display(
+        "the package '{name}' needs the plugin '{plugin}', but plugins are not supported yet"
+    ))]
+    UnsupportedPlugin { name: String, plugin: String },
+
+    #[non_exhaustive]
+    #[snafu(display("could not create the work directory {path:?}: {source}"))]
+    CreateWorkDirectory {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("the source {path:?} does not exist"))]
+    SourceNotFound { path: PathBuf },
+
+    #[non_exhaustive]
+    #[snafu(display("a source of '{name}' has none of `path`, `url` and `git`"))]
+    NoSourceLocation { name: String },
+
+    #[non_exhaustive]
+    #[snafu(display("'{url}' is not a valid local file URL"))]
+    InvalidFileUrl { url: String },
+
+    #[non_exhaustive]
+    #[snafu(display("could not download '{url}': {source}"))]
+    DownloadSource { url: String, source: reqwest::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("could not clone '{url}': {source}"))]
+    CloneSource { url: String, source: git2::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("{source}"))]
+    FetchSource { source: mirrors::Failures<Error> },
+
+    #[non_exhaustive]
+    #[snafu(display("could not copy {from:?} to {to:?}: {source}"))]
+    Copy {
+        from: PathBuf,
+        to: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("could not run the install step `{command}`: {source}"))]
+    RunStep {
+        command: String,
+        source: std::io::Error,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("the install step `{command}` failed with {status}"))]
+    StepFailed { command: String, status: ExitStatus },
+
+    #[non_exhaustive]
+    #[snafu(display(
+        "'{package}' would overwrite {path:?}, which is owned by the package '{owner}'"
+    ))]
+    FileConflict {
+        path: PathBuf,
+        package: String,
+        owner: String,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display(
+        "'{package}' would overwrite {path:?}, which already exists and is not owned by any \
+         package"
+    ))]
+    UntrackedFileConflict { path: PathBuf, package: String },
+
+    #[non_exhaustive]
+    #[snafu(display("in the transaction: {source}"))]
+    Transaction { source: transaction::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("could not compute the checksum of {path:?}: {source}"))]
+    Checksum {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("could not read the staged files at {path:?}: {source}"))]
+    ReadStaging {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+}
+
+impl Error {
+    /// Whether fetching a source failed because of the server it was fetched
+    /// from, rather than because of the local system.
+    fn is_mirror_fault(&self) -> bool {
+        match self {
+            Self::DownloadSource { .. } => true,
+            Self::CloneSource { source, .. } => mirrors::is_git_mirror_fault(source),
+            _ => false,
+        }
+    }
 }
 
 // endregion: ERRORS
 
 // region: IMPORTS
 
-use std::path::PathBuf;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+    process::{self, ExitStatus},
+};
 
-#[allow(unused)]
-use snafu::{ResultExt, Snafu};
+use owo_colors::OwoColorize;
+use semver::VersionReq;
+use snafu::{ensure, OptionExt, ResultExt, Snafu};
+use url::Url;
 
-use crate::app::ui::console_template::cli::PackageInstallArguments;
+use super::{
+    resolver::{self, Catalog, Preference, Request},
+    transaction::{self, Transaction},
+};
+use crate::{
+    app::ui::console_template::cli::PackageInstallArguments,
+    data::{
+        self,
+        cache::{self, CachedArtifact},
+        config::{self, InstallType},
+        installed::{self, Database, InstalledPackage},
+        manifest::{self, InstallStep, PackageManifest, SourceLocation},
+        mirrors::{self, MirrorOrder},
+    },
+};
 
 // endregion: IMPORTS
+
+// region: TESTS
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::repository::tests::write_manifest;
+
+    /// Writes a package into a repository directory, with the rest of its
+    /// manifest given by `extra` and a `src` directory beside it.
+    fn write_package(repository_dirpath: &Path, name: &str, version: &str, extra: &str) -> PathBuf {
+        let manifest_filepath = write_manifest(repository_dirpath, name, version, extra);
+        let source_dirpath = manifest_filepath.with_file_name("src");
+        fs::create_dir_all(source_dirpath.join("doc")).unwrap();
+        fs::write(source_dirpath.join("hello"), "#!/bin/sh\necho hello\n").unwrap();
+        fs::write(source_dirpath.join("doc/README"), "hello\n").unwrap();
+        manifest_filepath
+    }
+
+    #[test]
+    fn install_local_manifest() {
+        let temporary_dirpath = tempfile::tempdir().unwrap();
+        let paxy_home = temporary_dirpath
+            .path()
+            .join(".paxy");
+        let install_location = temporary_dirpath
+            .path()
+            .join("prefix");
+        let manifest_filepath = write_package(
+            temporary_dirpath.path(),
+            "hello",
+            "1.0.0",
+            r#"
+[[sources]]
+path = "src"
+
+[[install]]
+copy = { from = "hello", to = "bin/hello" }
+
+[[install]]
+copy = { from = "doc", to = "share/doc/hello" }
+"#,
+        );
+
+        let mut transaction = Transaction::begin(&paxy_home).unwrap();
+        let installation = install_package(
+            &manifest_filepath,
+            &BTreeSet::new(),
+            InstallType::User,
+            &install_location,
+            &paxy_home,
+            &Database::default(),
+            &mut transaction,
+        )
+        .unwrap();
+
+        assert_eq!(
+            installation.files,
+            vec![
+                PathBuf::from("bin/hello"),
+                PathBuf::from("share/doc/hello/README")
+            ]
+        );
+        assert!(install_location
+            .join("bin/hello")
+            .is_file());
+        transaction
+            .commit(&Database::default(), "install hello")
+            .unwrap();
+        assert!(install_location
+            .join("bin/hello")
+            .is_file());
+        assert_eq!(
+            fs::read_dir(paxy_home.join("tmp"))
+                .unwrap()
+                .count(),
+            0
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn failed_step_installs_nothing() {
+        let temporary_dirpath = tempfile::tempdir().unwrap();
+        let install_location = temporary_dirpath
+            .path()
+            .join("prefix");
+        let manifest_filepath = write_package(
+            temporary_dirpath.path(),
+            "hello",
+            "1.0.0",
+            r#"
+[[sources]]
+path = "src"
+
+[[install]]
+copy = { from = "hello", to = "bin/hello" }
+
+[[install]]
+run = "exit 3"
+"#,
+        );
+
+        let mut transaction = Transaction::begin(temporary_dirpath.path()).unwrap();
+        let error = install_package(
+            &manifest_filepath,
+            &BTreeSet::new(),
+            InstallType::User,
+            &install_location,
+            temporary_dirpath.path(),
+            &Database::default(),
+            &mut transaction,
+        )
+        .unwrap_err();
+
+        assert!(matches!(error, Error::StepFailed { .. }));
+        assert!(!install_location.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn failure_rolls_back_earlier_packages() {
+        let temporary_dirpath = tempfile::tempdir().unwrap();
+        let install_location = temporary_dirpath
+            .path()
+            .join("prefix");
+        let manifest = |name: &str, step: &str| {
+            format!(
+                r#"
+[[sources]]
+path = "src"
+
+[[install]]
+copy = {{ from = "hello", to = "bin/{name}" }}
+
+[[install]]
+run = "{step}"
+"#
+            )
+        };
+        let mut transaction = Transaction::begin(temporary_dirpath.path()).unwrap();
+        for (name, step) in [("hello", "true"), ("broken", "exit 1")] {
+            let manifest_filepath = write_package(
+                temporary_dirpath.path(),
+                name,
+                "1.0.0",
+                &manifest(name, step),
+            );
+            let result = install_package(
+                &manifest_filepath,
+                &BTreeSet::new(),
+                InstallType::User,
+                &install_location,
+                temporary_dirpath.path(),
+                &Database::default(),
+                &mut transaction,
+            );
+            if result.is_err() {
+                break;
+            }
+        }
+        assert!(install_location
+            .join("bin/hello")
+            .is_file());
+
+        drop(transaction);
+
+        assert!(!install_location.exists());
+    }
+
+    #[test]
+    fn split_features_from_package_names() {
+        assert_eq!(
+            split_features("ripgrep[completions, gui]").unwrap(),
+            (
+                "ripgrep",
+                BTreeSet::from(["completions".to_string(), "gui".to_string()])
+            )
+        );
+        assert_eq!(
+            split_features("ripgrep").unwrap(),
+            ("ripgrep", BTreeSet::new())
+        );
+        for package in ["ripgrep[", "ripgrep[]", "[gui]", "ripgrep[gui]x"] {
+            assert!(matches!(
+                split_features(package),
+                Err(Error::InvalidFeatureList { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn refuse_to_overwrite_files_of_other_packages() {
+        let temporary_dirpath = tempfile::tempdir().unwrap();
+        let install_location = temporary_dirpath
+            .path()
+            .join("prefix");
+        let manifest = r#"
+[[sources]]
+path = "src"
+
+[[install]]
+copy = { from = "hello", to = "bin/hello" }
+"#;
+        let mut database = Database::default();
+        let mut transaction = Transaction::begin(temporary_dirpath.path()).unwrap();
+        let hello_filepath = write_package(temporary_dirpath.path(), "hello", "1.0.0", manifest);
+        let installation = install_package(
+            &hello_filepath,
+            &BTreeSet::new(),
+            InstallType::User,
+            &install_location,
+            temporary_dirpath.path(),
+            &database,
+            &mut transaction,
+        )
+        .unwrap();
+        let hello = installation.into_installed_package(None, InstallType::User);
+        database.insert(hello.clone());
+
+        let imposter_filepath =
+            write_package(temporary_dirpath.path(), "imposter", "1.0.0", manifest);
+        let error = install_package(
+            &imposter_filepath,
+            &BTreeSet::new(),
+            InstallType::User,
+            &install_location,
+            temporary_dirpath.path(),
+            &database,
+            &mut transaction,
+        )
+        .unwrap_err();
+        assert!(matches!(error, Error::FileConflict { ref owner, .. } if owner == "hello"));
+
+        database.remove("hello");
+        let error = install_package(
+            &imposter_filepath,
+            &BTreeSet::new(),
+            InstallType::User,
+            &install_location,
+            temporary_dirpath.path(),
+            &database,
+            &mut transaction,
+        )
+        .unwrap_err();
+        assert!(matches!(error, Error::UntrackedFileConflict { .. }));
+
+        // Reinstalling a package over its own files is allowed
+        database.insert(hello);
+        install_package(
+            &hello_filepath,
+            &BTreeSet::new(),
+            InstallType::User,
+            &install_location,
+            temporary_dirpath.path(),
+            &database,
+            &mut transaction,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn update_keeps_edited_config_files() {
+        let temporary_dirpath = tempfile::tempdir().unwrap();
+        let install_location = temporary_dirpath
+            .path()
+            .join("prefix");
+        let mut database = Database::default();
+        let mut transaction = Transaction::begin(temporary_dirpath.path()).unwrap();
+        let manifest_filepath = write_package(
+            temporary_dirpath.path(),
+            "hello",
+            "1.0.0",
+            r#"config_files = ["etc/hello.conf", "etc/old.conf"]
+
+[[sources]]
+path = "src"
+
+[[install]]
+copy = { from = "doc/README", to = "etc/hello.conf" }
+
+[[install]]
+copy = { from = "doc/README", to = "etc/old.conf" }
+"#,
+        );
+        let installation = install_package(
+            &manifest_filepath,
+            &BTreeSet::new(),
+            InstallType::User,
+            &install_location,
+            temporary_dirpath.path(),
+            &database,
+            &mut transaction,
+        )
+        .unwrap();
+        database.insert(installation.into_installed_package(None, InstallType::User));
+        fs::write(install_location.join("etc/hello.conf"), "edited\n").unwrap();
+        fs::write(install_location.join("etc/old.conf"), "edited\n").unwrap();
+
+        let manifest_filepath = write_package(
+            temporary_dirpath.path(),
+            "hello",
+            "2.0.0",
+            r#"config_files = ["etc/hello.conf"]
+
+[[sources]]
+path = "src"
+
+[[install]]
+copy = { from = "doc/README", to = "etc/hello.conf" }
+"#,
+        );
+        fs::write(
+            manifest_filepath.with_file_name("src/doc/README"),
+            "hello again\n",
+        )
+        .unwrap();
+        let installation = install_package(
+            &manifest_filepath,
+            &BTreeSet::new(),
+            InstallType::User,
+            &install_location,
+            temporary_dirpath.path(),
+            &database,
+            &mut transaction,
+        )
+        .unwrap();
+
+        assert_eq!(
+            fs::read_to_string(install_location.join("etc/hello.conf")).unwrap(),
+            "edited\n"
+        );
+        assert_eq!(
+            fs::read_to_string(install_location.join("etc/hello.conf.paxynew")).unwrap(),
+            "hello again\n"
+        );
+        assert!(!install_location
+            .join("etc/old.conf")
+            .exists());
+        assert_eq!(
+            fs::read_to_string(install_location.join("etc/old.conf.paxysave")).unwrap(),
+            "edited\n"
+        );
+        // The kept file still counts as edited against the new version
+        let updated = installation.into_installed_package(None, InstallType::User);
+        assert!(updated
+            .is_edited_config_file(Path::new("etc/hello.conf"))
+            .unwrap());
+    }
+}
+
+// endregion: TESTS
@@ -1,12 +1,97 @@
-#[allow(unused)]
+//! Lists installed packages from the installed-package database.
+
 pub fn handle_package_list_action(
     package_list_arguments: PackageListArguments,
 ) -> Result<(), Error> {
-    use crate::app::ui::console_template::cli::*;
+    let database_filepath = Database::filepath(&data::paxy_home());
+    let database = Database::load(&database_filepath).context(DatabaseSnafu {})?;
+
+    let packages = filter_packages(
+        &database,
+        &package_list_arguments.partial_package_name,
+        &package_list_arguments.excluded_partial_package_names,
+    );
 
-    todo!();
+    if packages.is_empty() {
+        tracing::info!(
+            "{} No matching packages are installed.",
+            console::Emoji("📭", "")
+        );
+    }
+    for package in packages.iter() {
+        tracing::info!(
+            "{} {}{} {} {} {}{}",
+            console::Emoji("📦", ""),
+            package.name.green(),
+            if package
+                .features
+                .is_empty()
+            {
+                String::new()
+            } else {
+                format!(
+                    "[{}]",
+                    package
+                        .features
+                        .iter()
+                        .cloned()
+                        .collect::<Vec<_>>()
+                        .join(",")
+                )
+            },
+            package.version,
+            format!(
+                "[{}, {:?}]",
+                package
+                    .repository
+                    .as_deref()
+                    .unwrap_or("local"),
+                package.install_type
+            )
+            .dimmed(),
+            format!(
+                "installed {}",
+                ui::format_unix_timestamp(package.installed_at)
+            )
+            .dimmed(),
+            database
+                .pin_of(&package.name)
+                .map(|pin| format!(" (pinned to {pin})"))
+                .unwrap_or_default(),
+        );
+        tracing::info!(target: "PLAIN", "{} {}", package.name, package.version);
+    }
+    tracing::info!(
+        target: "JSON",
+        "{}",
+        serde_json::to_string(&packages).context(SerializeJsonSnafu {})?
+    );
 
-    // Ok(())
+    Ok(())
+}
+
+/// Returns the installed packages whose names contain any of the given
+/// partial names (or all packages, if none are given), leaving out those
+/// whose names contain any of the excluded partial names.
+pub fn filter_packages<'a>(
+    database: &'a Database,
+    partial_package_names: &[String],
+    excluded_partial_package_names: &[String],
+) -> Vec<&'a InstalledPackage> {
+    let matches_any = |name: &str, partial_names: &[String]| {
+        partial_names
+            .iter()
+            .any(|partial_name| name.contains(&partial_name.to_lowercase()))
+    };
+
+    database
+        .packages
+        .values()
+        .filter(|package| {
+            partial_package_names.is_empty() || matches_any(&package.name, partial_package_names)
+        })
+        .filter(|package| !matches_any(&package.name, excluded_partial_package_names))
+        .collect()
 }
 
 // region: ERRORS
@@ -16,17 +101,64 @@ pub fn handle_package_list_action(
 #[non_exhaustive]
 pub enum Error {
     #[non_exhaustive]
-    #[snafu(display(""))]
-    Dummy {},
+    #[snafu(display("in the installed-package database: {source}"))]
+    Database { source: installed::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("could not serialize the package list to JSON: {source}"))]
+    SerializeJson { source: serde_json::Error },
 }
 
 // endregion: ERRORS
 
 // region: IMPORTS
 
-#[allow(unused)]
+use owo_colors::OwoColorize;
 use snafu::{ResultExt, Snafu};
 
-use crate::app::ui::console_template::cli::PackageListArguments;
+use crate::{
+    app::ui::{self, console_template::cli::PackageListArguments},
+    data::{
+        self,
+        installed::{self, Database, InstalledPackage},
+    },
+};
 
 // endregion: IMPORTS
+
+// region: TESTS
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::installed::tests::installed_package;
+
+    #[test]
+    fn filter_by_partial_and_excluded_names() {
+        let mut database = Database::default();
+        for name in ["ripgrep", "ripgrep-all", "fd", "bat"] {
+            database.insert(installed_package(name, "1.0.0"));
+        }
+        let names = |packages: Vec<&InstalledPackage>| {
+            packages
+                .into_iter()
+                .map(|package| package.name.clone())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            names(filter_packages(&database, &[], &[])),
+            ["bat", "fd", "ripgrep", "ripgrep-all"]
+        );
+        assert_eq!(
+            names(filter_packages(
+                &database,
+                &["RIP".to_string()],
+                &["all".to_string()]
+            )),
+            ["ripgrep"]
+        );
+    }
+}
+
+// endregion: TESTS
@@ -0,0 +1,67 @@
+//! Looks up which installed package owns a file.
+
+pub fn handle_package_owns_action(
+    package_owns_arguments: PackageOwnsArguments,
+) -> Result<(), Error> {
+    let database_filepath = Database::filepath(&data::paxy_home());
+    let database = Database::load(&database_filepath).context(DatabaseSnafu {})?;
+
+    let filepath = path::absolute(&package_owns_arguments.path).context(ResolvePathSnafu {
+        path: &package_owns_arguments.path,
+    })?;
+    let owner = database
+        .owner_of(&filepath)
+        .context(NotOwnedSnafu { path: &filepath })?;
+
+    tracing::info!(
+        "{} {:?} is owned by {} {}",
+        console::Emoji("📦", ""),
+        filepath,
+        owner.name.green(),
+        owner.version
+    );
+    tracing::info!(target: "PLAIN", "{} {}", owner.name, owner.version);
+
+    Ok(())
+}
+
+// region: ERRORS
+
+#[derive(Debug, Snafu)]
+#[snafu(visibility(pub(crate)))]
+#[non_exhaustive]
+pub enum Error {
+    #[non_exhaustive]
+    #[snafu(display("in the installed-package database: {source}"))]
+    Database { source: installed::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("could not resolve the path {path:?}: {source}"))]
+    ResolvePath {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("{path:?} is not owned by any installed package"))]
+    NotOwned { path: PathBuf },
+}
+
+// endregion: ERRORS
+
+// region: IMPORTS
+
+use std::path::{self, PathBuf};
+
+use owo_colors::OwoColorize;
+use snafu::{OptionExt, ResultExt, Snafu};
+
+use crate::{
+    app::ui::console_template::cli::PackageOwnsArguments,
+    data::{
+        self,
+        installed::{self, Database},
+    },
+};
+
+// endregion: IMPORTS
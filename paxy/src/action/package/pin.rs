@@ -0,0 +1,164 @@
+//! Pins an installed package to the versions matching a constraint. Updates
+//! leave a pinned package within its pin, and skip updating other packages
+//! when that would need the pinned one to move. Without a constraint, the
+//! package is held at exactly its installed version.
+
+pub fn handle_package_pin_action(package_pin_arguments: PackagePinArguments) -> Result<(), Error> {
+    let database_filepath = Database::filepath(&data::paxy_home());
+    let mut database = Database::load(&database_filepath).context(DatabaseSnafu {})?;
+
+    let requirement = pin(
+        &mut database,
+        &package_pin_arguments.package_name,
+        package_pin_arguments
+            .version_constraint
+            .as_deref(),
+    )?;
+    database
+        .save(&database_filepath)
+        .context(DatabaseSnafu {})?;
+
+    tracing::info!(
+        "{} Pinned {} to {}",
+        console::Emoji("📌", ""),
+        package_pin_arguments
+            .package_name
+            .green(),
+        requirement
+    );
+    tracing::info!(
+        target: "PLAIN",
+        "{} {}",
+        package_pin_arguments.package_name,
+        requirement
+    );
+
+    Ok(())
+}
+
+/// Pins an installed package in the database, to exactly its installed
+/// version if no constraint is given. The installed version has to satisfy
+/// the constraint.
+pub fn pin(
+    database: &mut Database,
+    package_name: &str,
+    version_constraint: Option<&str>,
+) -> Result<VersionReq, Error> {
+    let package = database
+        .get(package_name)
+        .context(NotInstalledSnafu { name: package_name })?;
+    let requirement = match version_constraint {
+        Some(version_constraint) => VersionReq::parse(version_constraint)
+            .context(InvalidConstraintSnafu { version_constraint })?,
+        None => resolver::exactly(&package.version),
+    };
+    ensure!(
+        requirement.matches(&package.version),
+        InstalledVersionExcludedSnafu {
+            name: package_name,
+            version: package
+                .version
+                .to_string(),
+            requirement: requirement.to_string(),
+        }
+    );
+
+    database.pin(package_name, requirement.clone());
+
+    Ok(requirement)
+}
+
+// region: ERRORS
+
+#[derive(Debug, Snafu)]
+#[snafu(visibility(pub(crate)))]
+#[non_exhaustive]
+pub enum Error {
+    #[non_exhaustive]
+    #[snafu(display("the package '{name}' is not installed"))]
+    NotInstalled { name: String },
+
+    #[non_exhaustive]
+    #[snafu(display("'{version_constraint}' is not a valid version constraint: {source}"))]
+    InvalidConstraint {
+        version_constraint: String,
+        source: semver::Error,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display(
+        "the installed version {version} of '{name}' does not match {requirement}. Update or \
+         downgrade it first"
+    ))]
+    InstalledVersionExcluded {
+        name: String,
+        version: String,
+        requirement: String,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("in the installed-package database: {source}"))]
+    Database { source: installed::Error },
+}
+
+// endregion: ERRORS
+
+// region: IMPORTS
+
+use owo_colors::OwoColorize;
+use semver::VersionReq;
+use snafu::{ensure, OptionExt, ResultExt, Snafu};
+
+use super::resolver;
+use crate::{
+    app::ui::console_template::cli::PackagePinArguments,
+    data::{
+        self,
+        installed::{self, Database},
+    },
+};
+
+// endregion: IMPORTS
+
+// region: TESTS
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::installed::tests::installed_package;
+
+    #[test]
+    fn pin_to_the_installed_version_or_a_constraint() {
+        let mut database = Database::default();
+        database.insert(installed_package("gcc", "13.2.0"));
+
+        assert_eq!(
+            pin(&mut database, "gcc", None)
+                .unwrap()
+                .to_string(),
+            "=13.2.0"
+        );
+        assert_eq!(
+            pin(&mut database, "gcc", Some("^13"))
+                .unwrap()
+                .to_string(),
+            "^13"
+        );
+        assert_eq!(
+            database
+                .pin_of("gcc")
+                .map(VersionReq::to_string),
+            Some("^13".to_string())
+        );
+        assert!(matches!(
+            pin(&mut database, "gcc", Some("^14")),
+            Err(Error::InstalledVersionExcluded { .. })
+        ));
+        assert!(matches!(
+            pin(&mut database, "clang", None),
+            Err(Error::NotInstalled { .. })
+        ));
+    }
+}
+
+// endregion: TESTS
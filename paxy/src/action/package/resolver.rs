@@ -0,0 +1,1260 @@
+//! Resolves the packages asked for on the commandline, along with everything
+//! they depend on, into the set of package versions to install. The
+//! dependencies declared by the already-installed packages are honoured too,
+//! so that installing, updating or downgrading one package never leaves
+//! another with a dependency it does not accept.
+//!
+//! Packages are visited depth-first, and the version chosen for each is the
+//! installed one when it is acceptable, or else the highest version available
+//! that satisfies every constraint known at that point, from the repository
+//! with the highest priority that has one. An installed package only comes
+//! from the repository it was installed from, unless another is asked for as
+//! `repository/package`. Choices are not revisited, so a constraint that turns
+//! up later and rejects an earlier choice is reported as a conflict, along
+//! with the chain of packages behind each constraint involved.
+
+/// A version of a package available to install.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidate {
+    pub name: String,
+    pub version: Version,
+    pub manifest_filepath: PathBuf,
+    /// The repository the manifest is in, or `None` for a manifest given by
+    /// path.
+    pub repository: Option<String>,
+}
+
+/// The packages that can be installed: those in the installed repositories,
+/// and those whose manifests were given by path. What is read from the
+/// repositories is remembered, so that resolving many times over reads each
+/// package directory and manifest only once.
+#[derive(Debug, Clone, Default)]
+pub struct Catalog {
+    /// Sorted by priority, highest first, and then by name.
+    repositories: Vec<LocalRepository>,
+    priorities: BTreeMap<String, i32>,
+    local_candidates: BTreeMap<String, Candidate>,
+    extra_candidates: BTreeMap<String, Vec<Candidate>>,
+    /// The versions of each package looked up so far in the repositories.
+    repository_candidates: RefCell<BTreeMap<String, Vec<Candidate>>>,
+    /// The manifests read so far, by path.
+    manifests: RefCell<BTreeMap<PathBuf, PackageManifest>>,
+}
+
+impl Catalog {
+    /// Creates a catalog of the repositories inside the given paxy home.
+    pub fn new(paxy_home: &Path) -> Result<Self, Error> {
+        let priorities: BTreeMap<String, i32> = Registry::load(&Registry::filepath(paxy_home))
+            .context(RegistrySnafu {})?
+            .repositories
+            .into_iter()
+            .map(|(name, registered_repository)| (name, registered_repository.priority))
+            .collect();
+        let mut repositories =
+            repository::local_repositories(paxy_home).context(RepositorySnafu {})?;
+        // Stable, so that repositories of equal priority stay sorted by name
+        repositories.sort_by_key(|local_repository| {
+            Reverse(
+                priorities
+                    .get(&local_repository.name)
+                    .copied()
+                    .unwrap_or_default(),
+            )
+        });
+
+        Ok(Self {
+            repositories,
+            priorities,
+            ..Self::default()
+        })
+    }
+
+    /// Returns the priority of the repository a candidate is in.
+    pub fn priority(&self, candidate: &Candidate) -> i32 {
+        candidate
+            .repository
+            .as_ref()
+            .and_then(|repository| {
+                self.priorities
+                    .get(repository)
+            })
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Adds a package from a manifest outside of any repository. It hides
+    /// every version of the same package in the repositories.
+    pub fn add_local(&mut self, manifest_filepath: &Path, manifest: &PackageManifest) {
+        self.local_candidates
+            .insert(
+                manifest
+                    .name
+                    .clone(),
+                Candidate {
+                    name: manifest
+                        .name
+                        .clone(),
+                    version: manifest
+                        .version
+                        .clone(),
+                    manifest_filepath: manifest_filepath.to_path_buf(),
+                    repository: None,
+                },
+            );
+    }
+
+    /// Adds a version of a package that is not in the repositories as they
+    /// are on disk, such as one extracted from their history. Unlike
+    /// [`Catalog::add_local`], it is offered alongside the versions in the
+    /// repositories.
+    pub fn add_candidate(&mut self, candidate: Candidate) {
+        self.extra_candidates
+            .entry(
+                candidate
+                    .name
+                    .clone(),
+            )
+            .or_default()
+            .push(candidate);
+    }
+
+    /// Lists every available version of the named package, in the order of
+    /// the priorities of the repositories they are in, followed by any added
+    /// with [`Catalog::add_candidate`].
+    pub fn candidates(&self, name: &str) -> Result<Vec<Candidate>, Error> {
+        if let Some(candidate) = self
+            .local_candidates
+            .get(name)
+        {
+            return Ok(vec![candidate.clone()]);
+        }
+
+        let cached_candidates = self
+            .repository_candidates
+            .borrow()
+            .get(name)
+            .cloned();
+        let mut candidates = match cached_candidates {
+            Some(candidates) => candidates,
+            None => {
+                let candidates = self.read_repository_candidates(name)?;
+                self.repository_candidates
+                    .borrow_mut()
+                    .insert(name.to_string(), candidates.clone());
+                candidates
+            }
+        };
+        if let Some(extra_candidates) = self
+            .extra_candidates
+            .get(name)
+        {
+            candidates.extend_from_slice(extra_candidates);
+        }
+
+        Ok(candidates)
+    }
+
+    /// Reads every version of the named package in the repositories, in the
+    /// order of their priorities.
+    fn read_repository_candidates(&self, name: &str) -> Result<Vec<Candidate>, Error> {
+        let mut candidates = Vec::new();
+        for local_repository in self
+            .repositories
+            .iter()
+        {
+            let entries = repository::package_manifest_entries(&local_repository.dirpath, name)
+                .context(RepositorySnafu {})?;
+            candidates.extend(
+                entries
+                    .into_iter()
+                    .map(|entry| Candidate {
+                        name: entry.name,
+                        version: entry.version,
+                        manifest_filepath: entry.manifest_filepath,
+                        repository: Some(
+                            local_repository
+                                .name
+                                .clone(),
+                        ),
+                    }),
+            );
+        }
+
+        Ok(candidates)
+    }
+
+    /// Reads the manifest of a candidate.
+    pub fn manifest(&self, candidate: &Candidate) -> Result<PackageManifest, Error> {
+        if let Some(manifest) = self
+            .manifests
+            .borrow()
+            .get(&candidate.manifest_filepath)
+        {
+            return Ok(manifest.clone());
+        }
+
+        let manifest =
+            PackageManifest::from_path(&candidate.manifest_filepath).context(ManifestSnafu {
+                path: &candidate.manifest_filepath,
+            })?;
+        self.manifests
+            .borrow_mut()
+            .insert(
+                candidate
+                    .manifest_filepath
+                    .clone(),
+                manifest.clone(),
+            );
+
+        Ok(manifest)
+    }
+}
+
+/// Which version of a requested package to prefer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Preference {
+    /// Keep the installed version if it is acceptable.
+    Installed,
+    /// Pick the highest acceptable version, keeping the installed version if
+    /// no acceptable version available is higher.
+    Latest,
+    /// Pick the highest acceptable version, and install it even if that
+    /// version is already installed.
+    Reinstall,
+}
+
+/// A package asked for by the user.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    pub name: String,
+    /// The repository to install the package from, if one was named. An
+    /// installed package otherwise stays with the repository it came from.
+    pub repository: Option<String>,
+    pub requirement: VersionReq,
+    pub preference: Preference,
+    /// The optional features of the package to install, on top of any that
+    /// are installed already.
+    pub features: BTreeSet<String>,
+}
+
+/// A constraint on the version of a package, along with where it came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Constraint {
+    pub name: String,
+    pub requirement: VersionReq,
+    /// The packages through which the constraint arose, outermost first, as
+    /// in `["app 1.0.0", "tool 2.0.0"]`. Empty for a requested package.
+    pub chain: Vec<String>,
+    /// Whether the constraint is the pin of the package.
+    pub is_pin: bool,
+}
+
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_pin {
+            write!(
+                f,
+                "{} is pinned to {} (see `paxy package unpin`)",
+                self.name, self.requirement
+            )
+        } else if self
+            .chain
+            .is_empty()
+        {
+            write!(f, "you asked for {} {}", self.name, self.requirement)
+        } else {
+            write!(
+                f,
+                "{} requires {} {}",
+                self.chain
+                    .join(" -> "),
+                self.name,
+                self.requirement
+            )
+        }
+    }
+}
+
+/// A package version chosen to be installed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlannedInstall {
+    pub candidate: Candidate,
+    pub manifest: PackageManifest,
+    pub features: BTreeSet<String>,
+}
+
+/// The outcome of resolving a set of requests.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Resolution {
+    /// The packages to install, each after the packages it depends on.
+    pub to_install: Vec<PlannedInstall>,
+    /// The installed packages that are kept as they are, with their versions.
+    pub kept: Vec<(String, Version)>,
+}
+
+/// Returns the requirement matched by the given version and no other.
+pub fn exactly(version: &Version) -> VersionReq {
+    VersionReq {
+        comparators: vec![Comparator {
+            op: Op::Exact,
+            major: version.major,
+            minor: Some(version.minor),
+            patch: Some(version.patch),
+            pre: version.pre.clone(),
+        }],
+    }
+}
+
+/// Resolves the given requests against the catalog and the installed
+/// packages.
+pub fn resolve(
+    catalog: &Catalog,
+    database: &Database,
+    requests: &[Request],
+) -> Result<Resolution, Error> {
+    // Installed packages stay with their repositories, unless asked otherwise
+    let mut sources: BTreeMap<String, String> = database
+        .packages
+        .values()
+        .filter_map(|package| {
+            package
+                .repository
+                .clone()
+                .map(|repository| (package.name.clone(), repository))
+        })
+        .collect();
+    for request in requests {
+        if let Some(repository) = &request.repository {
+            sources.insert(request.name.clone(), repository.clone());
+        }
+    }
+
+    // Features may be asked for on a package that another request depends
+    // on, so they are gathered before anything is selected
+    let mut requested_features: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    for request in requests {
+        requested_features
+            .entry(request.name.clone())
+            .or_default()
+            .extend(
+                request
+                    .features
+                    .iter()
+                    .cloned(),
+            );
+    }
+
+    let mut resolver = Resolver {
+        catalog,
+        database,
+        sources,
+        requested_features,
+        constraints: BTreeMap::new(),
+        selected: BTreeMap::new(),
+        stack: Vec::new(),
+        resolution: Resolution::default(),
+    };
+
+    // The dependencies of installed packages that are not being replaced must
+    // remain satisfied
+    for package in database
+        .packages
+        .values()
+        .filter(|package| {
+            !requests
+                .iter()
+                .any(|request| request.name == package.name)
+        })
+    {
+        for (name, requirement) in package
+            .dependencies
+            .iter()
+        {
+            resolver
+                .constraints
+                .entry(name.clone())
+                .or_default()
+                .push(Constraint {
+                    name: name.clone(),
+                    requirement: requirement.clone(),
+                    chain: vec![format!("{} {} (installed)", package.name, package.version)],
+                    is_pin: false,
+                });
+        }
+    }
+    // Pinned packages stay within their pins, whether requested or not
+    for (name, requirement) in database.pins.iter() {
+        resolver
+            .constraints
+            .entry(name.clone())
+            .or_default()
+            .push(Constraint {
+                name: name.clone(),
+                requirement: requirement.clone(),
+                chain: Vec::new(),
+                is_pin: true,
+            });
+    }
+
+    for request in requests {
+        resolver.resolve_package(
+            Constraint {
+                name: request.name.clone(),
+                requirement: request
+                    .requirement
+                    .clone(),
+                chain: Vec::new(),
+                is_pin: false,
+            },
+            request.preference,
+            &request.features,
+        )?;
+    }
+
+    Ok(resolver.resolution)
+}
+
+struct Resolver<'a> {
+    catalog: &'a Catalog,
+    database: &'a Database,
+    /// The repository each package must come from, if any.
+    sources: BTreeMap<String, String>,
+    /// The features asked for on each package by any of the requests.
+    requested_features: BTreeMap<String, BTreeSet<String>>,
+    constraints: BTreeMap<String, Vec<Constraint>>,
+    selected: BTreeMap<String, Version>,
+    /// The packages whose dependencies are being resolved, outermost first.
+    stack: Vec<(String, Version)>,
+    resolution: Resolution,
+}
+
+impl Resolver<'_> {
+    fn resolve_package(
+        &mut self,
+        constraint: Constraint,
+        preference: Preference,
+        features: &BTreeSet<String>,
+    ) -> Result<(), Error> {
+        let name = constraint
+            .name
+            .clone();
+        if let Some(position) = self
+            .stack
+            .iter()
+            .position(|(stacked_name, _)| stacked_name == &name)
+        {
+            let mut cycle: Vec<String> = self.stack[position..]
+                .iter()
+                .map(|(name, version)| format!("{name} {version}"))
+                .collect();
+            cycle.push(name);
+            return CycleSnafu {
+                cycle: cycle.join(" -> "),
+            }
+            .fail();
+        }
+
+        let constraints = self
+            .constraints
+            .entry(name.clone())
+            .or_default();
+        constraints.push(constraint.clone());
+        let constraints = constraints.clone();
+        let accepts = |version: &Version| {
+            constraints
+                .iter()
+                .all(|constraint| {
+                    constraint
+                        .requirement
+                        .matches(version)
+                })
+        };
+
+        // Every feature asked for on the package is merged in before it is
+        // first selected, so coming back to it cannot add any
+        if let Some(version) = self
+            .selected
+            .get(&name)
+        {
+            ensure!(
+                accepts(version),
+                ConflictSnafu {
+                    name,
+                    version: version.clone(),
+                    constraints: describe(&constraints),
+                    involves_pin: involves_pin(&constraints),
+                }
+            );
+            return Ok(());
+        }
+
+        let features: BTreeSet<String> = features
+            .iter()
+            .chain(
+                self.requested_features
+                    .get(&name)
+                    .into_iter()
+                    .flatten(),
+            )
+            .cloned()
+            .collect();
+        let installed_features = self
+            .database
+            .get(&name)
+            .map(|package| {
+                package
+                    .features
+                    .clone()
+            })
+            .unwrap_or_default();
+        let source = self
+            .sources
+            .get(&name)
+            .cloned();
+        let installed = self
+            .database
+            .get(&name)
+            .filter(|package| accepts(&package.version) && features.is_subset(&package.features))
+            .filter(|package| {
+                source.is_none()
+                    || package
+                        .repository
+                        .as_ref()
+                        == source.as_ref()
+            });
+        if let (Preference::Installed, Some(installed)) = (preference, installed) {
+            return self.keep(installed, &constraint);
+        }
+
+        // Manifests given by path are not in any repository, and always count
+        let candidates: Vec<Candidate> = self
+            .catalog
+            .candidates(&name)?
+            .into_iter()
+            .filter(|candidate| {
+                candidate
+                    .repository
+                    .is_none()
+                    || source.is_none()
+                    || candidate.repository == source
+            })
+            .collect();
+        let mut best: Option<&Candidate> = None;
+        for candidate in candidates
+            .iter()
+            .filter(|candidate| accepts(&candidate.version))
+        {
+            // The first repository wins among equal priorities and versions
+            if best.is_none_or(|best| {
+                (
+                    self.catalog
+                        .priority(candidate),
+                    &candidate.version,
+                ) > (
+                    self.catalog
+                        .priority(best),
+                    &best.version,
+                )
+            }) {
+                best = Some(candidate);
+            }
+        }
+
+        // An installed version that nothing available beats is kept, even if
+        // the repositories no longer offer it
+        if let (Preference::Latest, Some(installed)) = (preference, installed) {
+            if best.is_none_or(|best| best.version <= installed.version) {
+                return self.keep(installed, &constraint);
+            }
+        }
+
+        if let Some(repository) = source.filter(|_| candidates.is_empty()) {
+            return NotInRepositorySnafu { name, repository }.fail();
+        }
+        ensure!(
+            !candidates.is_empty(),
+            PackageNotFoundSnafu {
+                name,
+                required_by: describe(&constraints),
+            }
+        );
+        let Some(best) = best else {
+            return UnsatisfiableSnafu {
+                name,
+                constraints: describe(&constraints),
+                involves_pin: involves_pin(&constraints),
+                available: candidates
+                    .iter()
+                    .map(|candidate| {
+                        candidate
+                            .version
+                            .to_string()
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            }
+            .fail();
+        };
+
+        let manifest = self
+            .catalog
+            .manifest(best)?;
+        // Features that were installed earlier are kept if the new version
+        // still has them
+        let features: BTreeSet<String> = features
+            .iter()
+            .cloned()
+            .chain(
+                installed_features
+                    .into_iter()
+                    .filter(|feature| {
+                        manifest
+                            .features
+                            .contains_key(feature)
+                    }),
+            )
+            .collect();
+        if let Some(feature) = features
+            .iter()
+            .find(|feature| {
+                !manifest
+                    .features
+                    .contains_key(*feature)
+            })
+        {
+            return UnknownFeatureSnafu {
+                name,
+                version: best.version.clone(),
+                feature,
+                available: manifest
+                    .features
+                    .keys()
+                    .cloned()
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            }
+            .fail();
+        }
+
+        self.selected
+            .insert(name.clone(), best.version.clone());
+        self.resolve_dependencies(
+            &name,
+            &best.version,
+            &manifest.dependencies_with(&features),
+            &constraint,
+        )?;
+        self.resolution
+            .to_install
+            .push(PlannedInstall {
+                candidate: best.clone(),
+                manifest,
+                features,
+            });
+
+        Ok(())
+    }
+
+    fn keep(&mut self, installed: &InstalledPackage, constraint: &Constraint) -> Result<(), Error> {
+        self.selected
+            .insert(
+                installed
+                    .name
+                    .clone(),
+                installed
+                    .version
+                    .clone(),
+            );
+        self.resolve_dependencies(
+            &installed.name,
+            &installed.version,
+            &installed.dependencies,
+            constraint,
+        )?;
+        self.resolution
+            .kept
+            .push((
+                installed
+                    .name
+                    .clone(),
+                installed
+                    .version
+                    .clone(),
+            ));
+
+        Ok(())
+    }
+
+    fn resolve_dependencies(
+        &mut self,
+        name: &str,
+        version: &Version,
+        dependencies: &BTreeMap<String, VersionReq>,
+        constraint: &Constraint,
+    ) -> Result<(), Error> {
+        let mut chain = constraint
+            .chain
+            .clone();
+        chain.push(format!("{name} {version}"));
+
+        self.stack
+            .push((name.to_string(), version.clone()));
+        for (dependency_name, requirement) in dependencies {
+            self.resolve_package(
+                Constraint {
+                    name: dependency_name.clone(),
+                    requirement: requirement.clone(),
+                    chain: chain.clone(),
+                    is_pin: false,
+                },
+                Preference::Installed,
+                &BTreeSet::new(),
+            )?;
+        }
+        self.stack.pop();
+
+        Ok(())
+    }
+}
+
+fn describe(constraints: &[Constraint]) -> String {
+    constraints
+        .iter()
+        .map(|constraint| format!("\n    {constraint}"))
+        .collect()
+}
+
+fn involves_pin(constraints: &[Constraint]) -> bool {
+    constraints
+        .iter()
+        .any(|constraint| constraint.is_pin)
+}
+
+// region: ERRORS
+
+#[derive(Debug, Snafu)]
+#[snafu(visibility(pub(crate)))]
+#[non_exhaustive]
+pub enum Error {
+    #[non_exhaustive]
+    #[snafu(display(
+        "no package named '{name}' was found in any repository, as needed by:{required_by}"
+    ))]
+    PackageNotFound { name: String, required_by: String },
+
+    #[non_exhaustive]
+    #[snafu(display(
+        "the repository '{repository}' does not offer '{name}'. Ask for it as \
+         <repository>/{name} to install it from another repository"
+    ))]
+    NotInRepository { name: String, repository: String },
+
+    #[non_exhaustive]
+    #[snafu(display(
+        "no version of '{name}' satisfies every constraint on it:{constraints}\n  available \
+         versions: {available}"
+    ))]
+    Unsatisfiable {
+        name: String,
+        constraints: String,
+        available: String,
+        involves_pin: bool,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display(
+        "'{name}' {version} was already chosen, but it does not satisfy every constraint on \
+         it:{constraints}"
+    ))]
+    Conflict {
+        name: String,
+        version: Version,
+        constraints: String,
+        involves_pin: bool,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display(
+        "'{name}' {version} has no feature named '{feature}'. Its features are: {available}"
+    ))]
+    UnknownFeature {
+        name: String,
+        version: Version,
+        feature: String,
+        available: String,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("the dependencies form a cycle: {cycle}"))]
+    Cycle { cycle: String },
+
+    #[non_exhaustive]
+    #[snafu(display("in the manifest at {path:?}: {source}"))]
+    Manifest {
+        path: PathBuf,
+        source: manifest::Error,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("in a repository: {source}"))]
+    Repository { source: repository::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("in the repository registry: {source}"))]
+    Registry { source: registry::Error },
+}
+
+impl Error {
+    /// Whether a pin is among the constraints that could not be satisfied.
+    pub fn involves_pin(&self) -> bool {
+        matches!(
+            self,
+            Self::Unsatisfiable {
+                involves_pin: true,
+                ..
+            } | Self::Conflict {
+                involves_pin: true,
+                ..
+            }
+        )
+    }
+}
+
+// endregion: ERRORS
+
+// region: IMPORTS
+
+use std::{
+    cell::RefCell,
+    cmp::Reverse,
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use semver::{Comparator, Op, Version, VersionReq};
+use snafu::{ensure, ResultExt, Snafu};
+
+use crate::data::{
+    installed::{Database, InstalledPackage},
+    manifest::{self, PackageManifest},
+    registry::{self, Registry},
+    repository::{self, LocalRepository},
+};
+
+// endregion: IMPORTS
+
+// region: TESTS
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::data::{
+        self,
+        installed::tests::installed_package,
+        mirrors::MirrorOrder,
+        registry::{RegisteredRepository, RepositoryKind},
+        repository::tests::write_manifest,
+    };
+
+    /// A package to write into a test repository: its name, version and
+    /// dependencies, each given as a name and a version requirement.
+    type TestPackage<'a> = (&'a str, &'a str, &'a [(&'a str, &'a str)]);
+
+    /// Writes a repository named `main` holding the given packages.
+    fn catalog_with(paxy_home: &Path, packages: &[TestPackage]) -> Catalog {
+        for (name, version, dependencies) in packages {
+            let dependencies: String = dependencies
+                .iter()
+                .map(|(name, requirement)| format!("{name} = \"{requirement}\"\n"))
+                .collect();
+            write_manifest(
+                &data::repositories_dirpath(paxy_home).join("main"),
+                name,
+                version,
+                &format!("\n[dependencies]\n{dependencies}"),
+            );
+        }
+        Catalog::new(paxy_home).unwrap()
+    }
+
+    fn request(name: &str, requirement: &str) -> Request {
+        Request {
+            name: name.to_string(),
+            repository: None,
+            requirement: VersionReq::parse(requirement).unwrap(),
+            preference: Preference::Installed,
+            features: BTreeSet::new(),
+        }
+    }
+
+    fn planned(resolution: &Resolution) -> Vec<String> {
+        resolution
+            .to_install
+            .iter()
+            .map(|planned| {
+                format!(
+                    "{} {}",
+                    planned
+                        .candidate
+                        .name,
+                    planned
+                        .candidate
+                        .version
+                )
+            })
+            .collect()
+    }
+
+    fn installed(name: &str, version: &str, dependencies: &[(&str, &str)]) -> InstalledPackage {
+        InstalledPackage {
+            repository: Some("main".to_string()),
+            dependencies: dependencies
+                .iter()
+                .map(|(name, requirement)| {
+                    (name.to_string(), VersionReq::parse(requirement).unwrap())
+                })
+                .collect(),
+            ..installed_package(name, version)
+        }
+    }
+
+    #[test]
+    fn dependencies_come_first_at_the_highest_acceptable_version() {
+        let paxy_home = tempfile::tempdir().unwrap();
+        let catalog = catalog_with(
+            paxy_home.path(),
+            &[
+                ("app", "1.0.0", &[("lib", "^1.2")]),
+                ("lib", "1.1.0", &[]),
+                ("lib", "1.4.0", &[]),
+                ("lib", "2.0.0", &[]),
+            ],
+        );
+
+        let resolution = resolve(&catalog, &Database::default(), &[request("app", "*")]).unwrap();
+
+        assert_eq!(planned(&resolution), ["lib 1.4.0", "app 1.0.0"]);
+        assert_eq!(
+            resolution.to_install[0]
+                .candidate
+                .repository
+                .as_deref(),
+            Some("main")
+        );
+    }
+
+    #[test]
+    fn acceptable_installed_versions_are_kept() {
+        let paxy_home = tempfile::tempdir().unwrap();
+        let catalog = catalog_with(
+            paxy_home.path(),
+            &[
+                ("app", "1.0.0", &[("lib", "^1")]),
+                ("lib", "1.0.0", &[]),
+                ("lib", "1.4.0", &[]),
+                ("lib", "2.0.0", &[]),
+            ],
+        );
+        let mut database = Database::default();
+        database.insert(installed("lib", "1.0.0", &[]));
+        database.insert(installed("tool", "1.0.0", &[("lib", "<1.3")]));
+
+        let resolution = resolve(&catalog, &database, &[request("app", "*")]).unwrap();
+        assert_eq!(planned(&resolution), ["app 1.0.0"]);
+        assert_eq!(
+            resolution.kept,
+            [("lib".to_string(), Version::new(1, 0, 0))]
+        );
+
+        // An update may not break the installed packages depending on it
+        let resolution = resolve(
+            &catalog,
+            &database,
+            &[Request {
+                preference: Preference::Latest,
+                ..request("lib", "*")
+            }],
+        )
+        .unwrap();
+        assert!(planned(&resolution).is_empty());
+    }
+
+    #[test]
+    fn conflicts_explain_the_chain_of_constraints() {
+        let paxy_home = tempfile::tempdir().unwrap();
+        let catalog = catalog_with(
+            paxy_home.path(),
+            &[
+                ("app", "1.0.0", &[("lib", "^1"), ("tool", "^2")]),
+                ("tool", "2.0.0", &[("lib", "^2")]),
+                ("lib", "1.0.0", &[]),
+                ("lib", "2.0.0", &[]),
+            ],
+        );
+
+        let error = resolve(&catalog, &Database::default(), &[request("app", "*")]).unwrap_err();
+
+        assert!(matches!(error, Error::Conflict { ref name, .. } if name == "lib"));
+        let message = error.to_string();
+        assert!(message.contains("app 1.0.0 requires lib ^1"));
+        assert!(message.contains("app 1.0.0 -> tool 2.0.0 requires lib ^2"));
+
+        let error = resolve(&catalog, &Database::default(), &[request("lib", ">=3")]).unwrap_err();
+        assert!(
+            matches!(error, Error::Unsatisfiable { ref available, .. } if available == "1.0.0, 2.0.0")
+        );
+    }
+
+    #[test]
+    fn features_pull_in_their_dependencies_and_are_kept() {
+        let paxy_home = tempfile::tempdir().unwrap();
+        let mut catalog = catalog_with(
+            paxy_home.path(),
+            &[
+                ("gtk", "4.0.0", &[]),
+                ("app", "1.1.0", &[]),
+                ("suite", "1.0.0", &[("app", "^1.2")]),
+            ],
+        );
+        let app_dirpath = paxy_home
+            .path()
+            .join("app");
+        fs::create_dir_all(&app_dirpath).unwrap();
+        let app_filepath = app_dirpath.join("manifest.toml");
+        fs::write(
+            &app_filepath,
+            "format_version = 1\nname = \"app\"\nversion = \"1.2.0\"\n\n[features.gui]\ndependencies \
+             = { gtk = \"^4\" }\n",
+        )
+        .unwrap();
+        let manifest = PackageManifest::from_path(&app_filepath).unwrap();
+        catalog.add_local(&app_filepath, &manifest);
+
+        let resolution = resolve(
+            &catalog,
+            &Database::default(),
+            &[Request {
+                features: BTreeSet::from(["gui".to_string()]),
+                ..request("app", "*")
+            }],
+        )
+        .unwrap();
+        assert_eq!(planned(&resolution), ["gtk 4.0.0", "app 1.2.0"]);
+        assert_eq!(
+            resolution.to_install[1].features,
+            BTreeSet::from(["gui".to_string()])
+        );
+
+        // Features asked for on a package that another request depends on
+        // are not lost when the dependency is reached first
+        let resolution = resolve(
+            &catalog,
+            &Database::default(),
+            &[
+                request("suite", "*"),
+                Request {
+                    features: BTreeSet::from(["gui".to_string()]),
+                    ..request("app", "*")
+                },
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            planned(&resolution),
+            ["gtk 4.0.0", "app 1.2.0", "suite 1.0.0"]
+        );
+        assert_eq!(
+            resolution.to_install[1].features,
+            BTreeSet::from(["gui".to_string()])
+        );
+
+        // Reinstalling keeps the installed features
+        let mut database = Database::default();
+        database.insert(InstalledPackage {
+            features: BTreeSet::from(["gui".to_string()]),
+            ..installed("app", "1.2.0", &[("gtk", "^4")])
+        });
+        let resolution = resolve(
+            &catalog,
+            &database,
+            &[Request {
+                preference: Preference::Reinstall,
+                ..request("app", "*")
+            }],
+        )
+        .unwrap();
+        assert_eq!(planned(&resolution), ["gtk 4.0.0", "app 1.2.0"]);
+        assert_eq!(
+            resolution.to_install[1].features,
+            BTreeSet::from(["gui".to_string()])
+        );
+
+        let error = resolve(
+            &catalog,
+            &Database::default(),
+            &[Request {
+                features: BTreeSet::from(["tui".to_string()]),
+                ..request("app", "*")
+            }],
+        )
+        .unwrap_err();
+        assert!(matches!(error, Error::UnknownFeature { ref available, .. } if available == "gui"));
+    }
+
+    #[test]
+    fn repositories_are_chosen_by_priority_and_kept_once_installed() {
+        let paxy_home = tempfile::tempdir().unwrap();
+        catalog_with(paxy_home.path(), &[("hello", "1.0.0", &[])]);
+        let write_extra = |version: &str| {
+            let version_dirpath = data::repositories_dirpath(paxy_home.path())
+                .join("extra/hello")
+                .join(version);
+            fs::create_dir_all(&version_dirpath).unwrap();
+            fs::write(
+                version_dirpath.join("manifest.toml"),
+                format!("format_version = 1\nname = \"hello\"\nversion = \"{version}\"\n"),
+            )
+            .unwrap();
+        };
+        write_extra("2.0.0");
+        let chosen = |database: &Database, request: Request| {
+            let resolution = resolve(
+                &Catalog::new(paxy_home.path()).unwrap(),
+                database,
+                &[request],
+            )
+            .unwrap();
+            resolution
+                .to_install
+                .first()
+                .map(|planned| {
+                    format!(
+                        "{}/{}",
+                        planned
+                            .candidate
+                            .repository
+                            .as_deref()
+                            .unwrap_or_default(),
+                        planned
+                            .candidate
+                            .version
+                    )
+                })
+        };
+
+        // Among equal priorities, the highest version wins
+        assert_eq!(
+            chosen(&Database::default(), request("hello", "*")).as_deref(),
+            Some("extra/2.0.0")
+        );
+        let registry_filepath = Registry::filepath(paxy_home.path());
+        let mut registry = Registry::default();
+        for (name, priority) in [("main", 10), ("extra", 0)] {
+            registry.insert(
+                name,
+                RegisteredRepository {
+                    url: format!("https://example.com/{name}.git"),
+                    kind: RepositoryKind::Git,
+                    priority,
+                    mirrors: Vec::new(),
+                    mirror_order: MirrorOrder::default(),
+                    added_at: 0,
+                    synced_at: None,
+                    pin: None,
+                },
+            );
+        }
+        registry
+            .save(&registry_filepath)
+            .unwrap();
+        assert_eq!(
+            chosen(&Database::default(), request("hello", "*")).as_deref(),
+            Some("main/1.0.0")
+        );
+
+        // An installed package keeps coming from its repository
+        let mut database = Database::default();
+        database.insert(InstalledPackage {
+            repository: Some("extra".to_string()),
+            ..installed("hello", "2.0.0", &[])
+        });
+        write_extra("2.1.0");
+        let latest = Request {
+            preference: Preference::Latest,
+            ..request("hello", "*")
+        };
+        assert_eq!(
+            chosen(&database, latest.clone()).as_deref(),
+            Some("extra/2.1.0")
+        );
+
+        // Unless another repository is asked for
+        assert_eq!(
+            chosen(
+                &database,
+                Request {
+                    repository: Some("main".to_string()),
+                    ..latest.clone()
+                }
+            )
+            .as_deref(),
+            Some("main/1.0.0")
+        );
+        assert!(matches!(
+            resolve(
+                &Catalog::new(paxy_home.path()).unwrap(),
+                &database,
+                &[Request {
+                    repository: Some("elsewhere".to_string()),
+                    ..latest
+                }],
+            ),
+            Err(Error::NotInRepository { .. })
+        ));
+    }
+
+    #[test]
+    fn cycles_are_detected() {
+        let paxy_home = tempfile::tempdir().unwrap();
+        let catalog = catalog_with(
+            paxy_home.path(),
+            &[
+                ("a", "1.0.0", &[("b", "*")]),
+                ("b", "1.0.0", &[("c", "*")]),
+                ("c", "1.0.0", &[("a", "*")]),
+            ],
+        );
+
+        let error = resolve(&catalog, &Database::default(), &[request("a", "*")]).unwrap_err();
+
+        assert!(
+            matches!(error, Error::Cycle { ref cycle } if cycle == "a 1.0.0 -> b 1.0.0 -> c 1.0.0 -> a")
+        );
+        assert!(matches!(
+            resolve(&catalog, &Database::default(), &[request("d", "*")]),
+            Err(Error::PackageNotFound { .. })
+        ));
+    }
+
+    #[test]
+    fn the_catalog_reads_each_package_once() {
+        let paxy_home = tempfile::tempdir().unwrap();
+        let catalog = catalog_with(
+            paxy_home.path(),
+            &[("app", "1.0.0", &[("lib", "^1")]), ("lib", "1.0.0", &[])],
+        );
+        let resolution = resolve(&catalog, &Database::default(), &[request("app", "*")]).unwrap();
+
+        // Later lookups are answered without the repository
+        fs::remove_dir_all(data::repositories_dirpath(paxy_home.path())).unwrap();
+        assert_eq!(
+            planned(&resolve(&catalog, &Database::default(), &[request("app", "*")]).unwrap()),
+            planned(&resolution)
+        );
+    }
+}
+
+// endregion: TESTS
@@ -1,12 +1,115 @@
-#[allow(unused)]
+//! Searches the packages available in the repositories under `~/.paxy/repos`
+//! by name, description and tags, through the search index of each
+//! repository. Only the clones on disk are read, so search works offline
+//! against whatever was last synced.
+
 pub fn handle_package_search_action(
     package_search_arguments: PackageSearchArguments,
 ) -> Result<(), Error> {
-    use crate::app::ui::console_template::cli::*;
+    let paxy_home = data::paxy_home();
+    let hits = search(
+        &paxy_home,
+        &package_search_arguments.partial_package_name,
+        &package_search_arguments.excluded_partial_package_names,
+    )?;
+
+    if hits.is_empty() {
+        tracing::info!(
+            "{} No matching packages are available.",
+            console::Emoji("📭", "")
+        );
+    }
+    for hit in hits.iter() {
+        tracing::info!(
+            "{} {} {} {} {}",
+            console::Emoji("📦", ""),
+            hit.name.green(),
+            hit.latest_version,
+            format!("[{}]", hit.repository).dimmed(),
+            hit.description
+                .as_deref()
+                .unwrap_or_default()
+        );
+        tracing::info!(
+            target: "PLAIN",
+            "{} {} {}",
+            hit.name,
+            hit.latest_version,
+            hit.repository
+        );
+    }
+    tracing::info!(
+        target: "JSON",
+        "{}",
+        serde_json::to_string(&hits).context(SerializeJsonSnafu {})?
+    );
+
+    Ok(())
+}
+
+/// A package found by a search.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SearchHit {
+    pub name: String,
+    pub latest_version: Version,
+    pub repository: String,
+    pub description: Option<String>,
+    pub tags: Vec<String>,
+    /// How well the package matches the search terms. Higher is better.
+    pub score: u32,
+}
+
+/// Searches the packages in every repository for those matching all the given
+/// terms, best matches first. Without any terms, every package matches. The
+/// packages whose names contain any of the excluded partial names are left
+/// out.
+pub fn search(
+    paxy_home: &Path,
+    terms: &[String],
+    excluded_partial_package_names: &[String],
+) -> Result<Vec<SearchHit>, Error> {
+    let excluded_partial_package_names: Vec<String> = excluded_partial_package_names
+        .iter()
+        .map(|partial_name| partial_name.to_lowercase())
+        .collect();
 
-    todo!();
+    let mut hits = Vec::new();
+    for local_repository in repository::local_repositories(paxy_home).context(RepositorySnafu {})? {
+        let index = SearchIndex::open(paxy_home, &local_repository).context(IndexSnafu {})?;
+        for (summary, score) in index.search(terms) {
+            let name = summary
+                .name
+                .to_lowercase();
+            if excluded_partial_package_names
+                .iter()
+                .any(|partial_name| name.contains(partial_name))
+            {
+                continue;
+            }
+            hits.push(SearchHit {
+                name: summary.name.clone(),
+                latest_version: summary
+                    .latest_version
+                    .clone(),
+                repository: local_repository
+                    .name
+                    .clone(),
+                description: summary
+                    .description
+                    .clone(),
+                tags: summary.tags.clone(),
+                score,
+            });
+        }
+    }
+    // Stable, so that the repositories stay in order for the same package
+    hits.sort_by(|a, b| {
+        b.score
+            .cmp(&a.score)
+            .then_with(|| a.name.cmp(&b.name))
+    });
 
-    // Ok(())
+    Ok(hits)
 }
 
 // region: ERRORS
@@ -16,17 +119,99 @@ pub fn handle_package_search_action(
 #[non_exhaustive]
 pub enum Error {
     #[non_exhaustive]
-    #[snafu(display(""))]
-    Dummy {},
+    #[snafu(display("in the repositories: {source}"))]
+    Repository { source: repository::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("in the search index: {source}"))]
+    Index { source: search_index::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("could not serialize the search results to JSON: {source}"))]
+    SerializeJson { source: serde_json::Error },
 }
 
 // endregion: ERRORS
 
 // region: IMPORTS
 
-#[allow(unused)]
+use std::path::Path;
+
+use owo_colors::OwoColorize;
+use semver::Version;
+use serde::Serialize;
 use snafu::{ResultExt, Snafu};
 
-use crate::app::ui::console_template::cli::PackageSearchArguments;
+use crate::{
+    app::ui::console_template::cli::PackageSearchArguments,
+    data::{
+        self,
+        repository,
+        search_index::{self, SearchIndex},
+    },
+};
 
 // endregion: IMPORTS
+
+// region: TESTS
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::repository::tests::write_manifest;
+
+    #[test]
+    fn rank_matches_in_names_above_tags_and_descriptions() {
+        let paxy_home = tempfile::tempdir().unwrap();
+        for (repository, name, version, extra) in [
+            ("main", "ripgrep", "13.0.0", "tags = [\"search\", \"grep\"]"),
+            ("main", "ripgrep", "14.1.0", "tags = [\"search\", \"grep\"]"),
+            ("main", "grep", "3.11.0", "description = \"The GNU grep\""),
+            (
+                "main",
+                "fd",
+                "9.0.0",
+                "description = \"Find files, like grep for paths\"",
+            ),
+            ("main", "gnugrep-extras", "1.0.0", ""),
+            ("other", "sed", "4.9.0", ""),
+        ] {
+            write_manifest(
+                &data::repositories_dirpath(paxy_home.path()).join(repository),
+                name,
+                version,
+                extra,
+            );
+        }
+
+        let hits = search(
+            paxy_home.path(),
+            &["GREP".to_string()],
+            &["extras".to_string()],
+        )
+        .unwrap();
+
+        assert_eq!(
+            hits.iter()
+                .map(|hit| (
+                    hit.name.as_str(),
+                    hit.latest_version
+                        .to_string()
+                ))
+                .collect::<Vec<_>>(),
+            [
+                ("grep", "3.11.0".to_string()),
+                ("ripgrep", "14.1.0".to_string()),
+                ("fd", "9.0.0".to_string())
+            ]
+        );
+        assert_eq!(
+            search(paxy_home.path(), &[], &[])
+                .unwrap()
+                .len(),
+            5
+        );
+    }
+}
+
+// endregion: TESTS
@@ -0,0 +1,766 @@
+//! Groups the filesystem changes made while installing, updating or
+//! uninstalling packages, so that they can be undone as a whole. Every file
+//! that is overwritten or removed is first moved into a backup directory under
+//! `~/.paxy/tmp`, and every change is written to a journal there before it is
+//! made. Committing saves the installed-package database, records the result
+//! as a new generation, and discards the backups. A transaction that is
+//! dropped without being committed, such as when an error is returned part way
+//! through, is rolled back.
+//!
+//! A transaction cut short by a crash leaves its journal behind. The next
+//! transaction to begin finishes it if it got as far as committing, and
+//! otherwise rolls it back.
+
+/// The name of the journal inside the directory of a transaction.
+const JOURNAL_FILE_NAME: &str = "journal.bson";
+
+/// The name of the copy of the new installed-package database, kept inside
+/// the directory of a transaction while it is committed.
+const DATABASE_FILE_NAME: &str = "installed.bson";
+
+/// The name of the file marking a transaction as committed. It holds the
+/// description of the new generation.
+const COMMITTED_FILE_NAME: &str = "committed";
+
+/// A set of filesystem changes that are either all kept or all undone. Only
+/// one transaction runs at a time in a paxy home, so the installed-package
+/// database should be loaded once it has begun.
+pub struct Transaction {
+    paxy_home: PathBuf,
+    dirpath: PathBuf,
+    /// Held open, and locked, until the transaction is committed or rolled
+    /// back, so that transactions of other processes wait for it to finish
+    /// rather than overwrite the installed-package database it saves.
+    _lock_file: File,
+    /// Held open, and locked, for as long as the transaction runs, so that
+    /// other processes leave it alone.
+    journal_file: Option<File>,
+    journal: Vec<Change>,
+    is_finished: bool,
+}
+
+/// A single journaled change to the filesystem.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+enum Change {
+    CreatedDirectory(PathBuf),
+    CreatedFile(PathBuf),
+    /// A file that was moved away to `backup`, either to be replaced or to be
+    /// removed.
+    MovedAway {
+        path: PathBuf,
+        backup: PathBuf,
+    },
+    Renamed {
+        from: PathBuf,
+        to: PathBuf,
+    },
+}
+
+impl Change {
+    /// Undoes the change. A change that was journaled but never made, or was
+    /// already undone, is skipped.
+    fn undo(&self) -> io::Result<()> {
+        let result = match self {
+            Change::CreatedDirectory(path) => fs::remove_dir(path),
+            Change::CreatedFile(path) => fs::remove_file(path),
+            Change::MovedAway { path, backup } => move_file(backup, path),
+            Change::Renamed { from, to } => fs::rename(to, from),
+        };
+        match result {
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    }
+}
+
+impl Transaction {
+    /// Starts a transaction, with its backups and journal kept in a directory
+    /// inside the given paxy home. Transactions left behind by a crash are
+    /// finished or rolled back first.
+    pub fn begin(paxy_home: &Path) -> Result<Self, Error> {
+        let lock_filepath = paxy_home.join("transactions.lock");
+        fs::create_dir_all(paxy_home).context(LockSnafu {
+            path: &lock_filepath,
+        })?;
+        // Held from before recovering and creating the directory, so that no
+        // other process mistakes the new transaction for an abandoned one
+        let lock_file = File::create(&lock_filepath).context(LockSnafu {
+            path: &lock_filepath,
+        })?;
+        lock_file
+            .lock()
+            .context(LockSnafu {
+                path: &lock_filepath,
+            })?;
+        recover(paxy_home);
+
+        let nanoseconds = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_nanos())
+            .unwrap_or_default();
+        let dirpath = paxy_home
+            .join("tmp")
+            .join(format!("transaction-{}-{nanoseconds}", process::id()));
+        fs::create_dir_all(&dirpath).context(CreateBackupDirectorySnafu { path: &dirpath })?;
+        let journal_filepath = dirpath.join(JOURNAL_FILE_NAME);
+        let journal_file = OpenOptions::new()
+            .append(true)
+            .create_new(true)
+            .open(&journal_filepath)
+            .context(JournalSnafu {
+                path: &journal_filepath,
+            })?;
+        journal_file
+            .lock()
+            .context(LockSnafu {
+                path: &journal_filepath,
+            })?;
+
+        Ok(Self {
+            paxy_home: paxy_home.to_path_buf(),
+            dirpath,
+            _lock_file: lock_file,
+            journal_file: Some(journal_file),
+            journal: Vec::new(),
+            is_finished: false,
+        })
+    }
+
+    /// Fails if the installed-package database is no longer the one given,
+    /// which the changes about to be made were planned against, as happens
+    /// when another process finished a transaction in the meantime.
+    pub fn check_database(&self, database: &Database) -> Result<(), Error> {
+        let current_database =
+            Database::load(&Database::filepath(&self.paxy_home)).context(DatabaseSnafu {})?;
+        ensure!(&current_database == database, DatabaseChangedSnafu {});
+
+        Ok(())
+    }
+
+    /// Copies a staged file to its place, backing up any file already there.
+    pub fn place_file(&mut self, from: &Path, to: &Path) -> Result<(), Error> {
+        if fs::symlink_metadata(to).is_ok() {
+            self.move_away(to)?;
+        } else if let Some(parent) = to.parent() {
+            self.create_directories(parent)?;
+        }
+
+        self.record(Change::CreatedFile(to.to_path_buf()))?;
+        fs::copy(from, to).context(PlaceSnafu { from, to })?;
+
+        Ok(())
+    }
+
+    /// Removes a file, keeping a backup of it. A file that does not exist is
+    /// skipped.
+    pub fn remove_file(&mut self, path: &Path) -> Result<(), Error> {
+        if fs::symlink_metadata(path).is_err() {
+            return Ok(());
+        }
+
+        self.move_away(path)
+    }
+
+    /// Renames a file.
+    pub fn rename_file(&mut self, from: &Path, to: &Path) -> Result<(), Error> {
+        // Journaled only once done, since undoing a rename that failed could
+        // move an unrelated file
+        fs::rename(from, to).context(RenameSnafu { from, to })?;
+        self.record(Change::Renamed {
+            from: from.to_path_buf(),
+            to: to.to_path_buf(),
+        })
+    }
+
+    /// Saves the installed-package database, records the installed packages
+    /// as a new generation with the given description, and discards the
+    /// backups.
+    pub fn commit(mut self, database: &Database, description: &str) -> Result<(), Error> {
+        // Kept with the journal first, so that a commit cut short can be
+        // finished on the next start
+        database
+            .save(
+                &self
+                    .dirpath
+                    .join(DATABASE_FILE_NAME),
+            )
+            .context(DatabaseSnafu {})?;
+        let committed_filepath = self
+            .dirpath
+            .join(COMMITTED_FILE_NAME);
+        data::write_atomically(&committed_filepath, description.as_bytes()).context(
+            JournalSnafu {
+                path: &committed_filepath,
+            },
+        )?;
+
+        database
+            .save(&Database::filepath(&self.paxy_home))
+            .context(DatabaseSnafu {})?;
+        self.is_finished = true;
+        self.remove_backup_directory();
+
+        // The changes are in place by now, so failing to record them in the
+        // history is not worth undoing them for
+        if let Err(error) = record_generation(&self.paxy_home, database, description) {
+            tracing::warn!("Could not record the new generation: {error}");
+        }
+
+        Ok(())
+    }
+
+    /// Undoes every change made so far, most recent first. Changes that cannot
+    /// be undone are reported and skipped, so that as much as possible is
+    /// restored.
+    pub fn roll_back(mut self) {
+        self.undo_changes();
+    }
+
+    fn undo_changes(&mut self) {
+        if self.is_finished {
+            return;
+        }
+        self.is_finished = true;
+        if !self
+            .journal
+            .is_empty()
+        {
+            tracing::warn!(
+                "{} Rolling back {} change(s)",
+                console::Emoji("⏪", ""),
+                self.journal.len()
+            );
+        }
+
+        while let Some(change) = self.journal.pop() {
+            if let Err(error) = change.undo() {
+                tracing::error!("Could not undo {:?}: {error}", change);
+            }
+        }
+        self.remove_backup_directory();
+    }
+
+    /// Writes a change to the journal, ahead of making it.
+    fn record(&mut self, change: Change) -> Result<(), Error> {
+        let journal_filepath = self
+            .dirpath
+            .join(JOURNAL_FILE_NAME);
+        let document = bson::to_document(&change).context(SerializeJournalSnafu {})?;
+        let mut buffer = Vec::new();
+        document
+            .to_writer(&mut buffer)
+            .context(SerializeJournalSnafu {})?;
+        if let Some(journal_file) = self
+            .journal_file
+            .as_mut()
+        {
+            journal_file
+                .write_all(&buffer)
+                .and_then(|_| journal_file.sync_data())
+                .context(JournalSnafu {
+                    path: &journal_filepath,
+                })?;
+        }
+        self.journal
+            .push(change);
+
+        Ok(())
+    }
+
+    fn move_away(&mut self, path: &Path) -> Result<(), Error> {
+        let backup = self.dirpath.join(
+            self.journal
+                .len()
+                .to_string(),
+        );
+        self.record(Change::MovedAway {
+            path: path.to_path_buf(),
+            backup: backup.clone(),
+        })?;
+        move_file(path, &backup).context(BackUpSnafu { path })?;
+
+        Ok(())
+    }
+
+    /// Creates a directory and any missing parents, journaling each one.
+    fn create_directories(&mut self, dirpath: &Path) -> Result<(), Error> {
+        let mut missing: Vec<&Path> = dirpath
+            .ancestors()
+            .take_while(|ancestor| !ancestor.exists())
+            .collect();
+        while let Some(ancestor) = missing.pop() {
+            self.record(Change::CreatedDirectory(ancestor.to_path_buf()))?;
+            fs::create_dir(ancestor).context(CreateDirectorySnafu { path: ancestor })?;
+        }
+
+        Ok(())
+    }
+
+    fn remove_backup_directory(&mut self) {
+        // Closed first, since an open file cannot be removed everywhere
+        self.journal_file = None;
+        remove_transaction_directory(&self.dirpath);
+    }
+}
+
+impl Drop for Transaction {
+    fn drop(&mut self) {
+        self.undo_changes();
+    }
+}
+
+/// Finishes or rolls back the transactions in the given paxy home that were
+/// cut short, leaving alone those still running in other processes. A
+/// directory without a journal is never removed, since its backups could not
+/// be put back.
+fn recover(paxy_home: &Path) {
+    let Ok(entries) = fs::read_dir(paxy_home.join("tmp")) else {
+        return;
+    };
+    for dirpath in entries
+        .filter_map(Result::ok)
+        .filter(|entry| {
+            entry
+                .file_name()
+                .to_string_lossy()
+                .starts_with("transaction-")
+        })
+        .map(|entry| entry.path())
+    {
+        let journal_filepath = dirpath.join(JOURNAL_FILE_NAME);
+        let Ok(journal_file) = File::open(&journal_filepath) else {
+            tracing::warn!(
+                "Leaving the transaction directory {:?} alone, since it has no journal",
+                dirpath
+            );
+            continue;
+        };
+        if journal_file
+            .try_lock()
+            .is_err()
+        {
+            continue;
+        }
+
+        let result = match fs::read_to_string(dirpath.join(COMMITTED_FILE_NAME)) {
+            Ok(description) => finish_commit(paxy_home, &dirpath, &description),
+            Err(_) => roll_back_journal(&journal_file),
+        };
+        match result {
+            Ok(()) => {
+                drop(journal_file);
+                remove_transaction_directory(&dirpath);
+            }
+            Err(error) => {
+                tracing::error!(
+                    "Could not recover the interrupted transaction in {dirpath:?}, which is left \
+                     in place: {error}"
+                );
+            }
+        }
+    }
+}
+
+/// Removes the directory of a finished transaction, its journal first so that
+/// whatever is left of it is never replayed.
+fn remove_transaction_directory(dirpath: &Path) {
+    let result = fs::remove_file(dirpath.join(JOURNAL_FILE_NAME))
+        .or_else(|error| match error.kind() {
+            io::ErrorKind::NotFound => Ok(()),
+            _ => Err(error),
+        })
+        .and_then(|_| fs::remove_dir_all(dirpath));
+    if let Err(error) = result {
+        tracing::warn!("Could not remove the backup directory {dirpath:?}: {error}");
+    }
+}
+
+/// Saves the installed-package database kept by a transaction that was cut
+/// short while committing, and records it as a new generation.
+fn finish_commit(paxy_home: &Path, dirpath: &Path, description: &str) -> Result<(), Error> {
+    tracing::warn!(
+        "{} Finishing an interrupted transaction: {description}",
+        console::Emoji("⏩", "")
+    );
+    let database = Database::load(&dirpath.join(DATABASE_FILE_NAME)).context(DatabaseSnafu {})?;
+    database
+        .save(&Database::filepath(paxy_home))
+        .context(DatabaseSnafu {})?;
+    if let Err(error) = record_generation(paxy_home, &database, description) {
+        tracing::warn!("Could not record the new generation: {error}");
+    }
+
+    Ok(())
+}
+
+/// Undoes every change in the journal of a transaction that was cut short,
+/// most recent first. A change that was being written when it was cut short
+/// is left out, since it was never made.
+fn roll_back_journal(journal_file: &File) -> Result<(), Error> {
+    let mut reader = io::BufReader::new(journal_file);
+    let mut journal = Vec::new();
+    while let Ok(document) = Document::from_reader(&mut reader) {
+        journal.push(bson::from_document::<Change>(document).context(DeserializeJournalSnafu {})?);
+    }
+
+    tracing::warn!(
+        "{} Rolling back an interrupted transaction of {} change(s)",
+        console::Emoji("⏪", ""),
+        journal.len()
+    );
+    let mut is_complete = true;
+    while let Some(change) = journal.pop() {
+        // An earlier attempt at rolling back may have put the file replaced
+        // by this one back already, which must then be left in place
+        let is_replaced_file_restored = match &change {
+            Change::CreatedFile(path) => journal
+                .iter()
+                .any(|earlier_change| {
+                    matches!(
+                        earlier_change,
+                        Change::MovedAway { path: moved_path, backup }
+                            if moved_path == path && fs::symlink_metadata(backup).is_err()
+                    )
+                }),
+            _ => false,
+        };
+        if is_replaced_file_restored {
+            continue;
+        }
+        if let Err(error) = change.undo() {
+            tracing::error!("Could not undo {:?}: {error}", change);
+            is_complete = false;
+        }
+    }
+    ensure!(is_complete, IncompleteRollBackSnafu {});
+
+    Ok(())
+}
+
+fn record_generation(
+    paxy_home: &Path,
+    database: &Database,
+    description: &str,
+) -> Result<(), generation::Error> {
+    let history_filepath = History::filepath(paxy_home);
+    let mut history = History::load(&history_filepath)?;
+    if let Some(generation) = history.record(database, description) {
+        tracing::debug!("Recorded generation {}", generation.number);
+        history.save(&history_filepath)?;
+    }
+    if let Err(error) = cache::prune(paxy_home, &history) {
+        tracing::warn!("Could not prune the cache of built packages: {error}");
+    }
+
+    Ok(())
+}
+
+/// Moves a file, falling back to copying and removing it when it cannot be
+/// renamed, such as across filesystems.
+fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+
+    fs::copy(from, to)?;
+    fs::remove_file(from)
+}
+
+// region: ERRORS
+
+#[derive(Debug, Snafu)]
+#[snafu(visibility(pub(crate)))]
+#[non_exhaustive]
+pub enum Error {
+    #[non_exhaustive]
+    #[snafu(display("could not create the backup directory {path:?}: {source}"))]
+    CreateBackupDirectory {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("could not create the directory {path:?}: {source}"))]
+    CreateDirectory {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("could not place {from:?} at {to:?}: {source}"))]
+    Place {
+        from: PathBuf,
+        to: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("could not back up {path:?}: {source}"))]
+    BackUp {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("could not rename {from:?} to {to:?}: {source}"))]
+    Rename {
+        from: PathBuf,
+        to: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("could not lock {path:?}: {source}"))]
+    Lock {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("could not write the journal {path:?}: {source}"))]
+    Journal {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("could not serialize a change for the journal: {source}"))]
+    SerializeJournal {
+        #[snafu(source(from(bson::ser::Error, Box::new)))]
+        source: Box<bson::ser::Error>,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("the journal holds an invalid change: {source}"))]
+    DeserializeJournal { source: bson::de::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("some changes could not be undone"))]
+    IncompleteRollBack {},
+
+    #[non_exhaustive]
+    #[snafu(display("in the installed-package database: {source}"))]
+    Database { source: installed::Error },
+
+    #[non_exhaustive]
+    #[snafu(display(
+        "the installed packages changed while the changes were being planned. Try again"
+    ))]
+    DatabaseChanged {},
+}
+
+// endregion: ERRORS
+
+// region: IMPORTS
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bson::Document;
+use serde::{Deserialize, Serialize};
+use snafu::{ensure, ResultExt, Snafu};
+
+use crate::data::{
+    self,
+    cache,
+    generation::{self, History},
+    installed::{self, Database},
+};
+
+// endregion: IMPORTS
+
+// region: TESTS
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dropping_a_transaction_rolls_it_back() {
+        let paxy_home = tempfile::tempdir().unwrap();
+        let prefix = paxy_home
+            .path()
+            .join("prefix");
+        let staged_filepath = paxy_home
+            .path()
+            .join("staged");
+        fs::create_dir_all(prefix.join("bin")).unwrap();
+        fs::write(&staged_filepath, "new").unwrap();
+        fs::write(prefix.join("bin/replaced"), "old").unwrap();
+        fs::write(prefix.join("bin/removed"), "old").unwrap();
+        fs::write(prefix.join("bin/renamed"), "old").unwrap();
+
+        let mut transaction = Transaction::begin(paxy_home.path()).unwrap();
+        transaction
+            .place_file(&staged_filepath, &prefix.join("bin/replaced"))
+            .unwrap();
+        transaction
+            .place_file(&staged_filepath, &prefix.join("share/doc/created"))
+            .unwrap();
+        transaction
+            .remove_file(&prefix.join("bin/removed"))
+            .unwrap();
+        transaction
+            .rename_file(
+                &prefix.join("bin/renamed"),
+                &prefix.join("bin/renamed.paxysave"),
+            )
+            .unwrap();
+        assert_eq!(
+            fs::read_to_string(prefix.join("bin/replaced")).unwrap(),
+            "new"
+        );
+        drop(transaction);
+
+        for file in ["bin/replaced", "bin/removed", "bin/renamed"] {
+            assert_eq!(fs::read_to_string(prefix.join(file)).unwrap(), "old");
+        }
+        assert!(!prefix
+            .join("share")
+            .exists());
+        assert!(!prefix
+            .join("bin/renamed.paxysave")
+            .exists());
+        assert_eq!(
+            fs::read_dir(
+                paxy_home
+                    .path()
+                    .join("tmp")
+            )
+            .unwrap()
+            .count(),
+            0
+        );
+    }
+
+    #[test]
+    fn a_transaction_cut_short_is_rolled_back_on_the_next_start() {
+        let paxy_home = tempfile::tempdir().unwrap();
+        let prefix = paxy_home
+            .path()
+            .join("prefix");
+        let staged_filepath = paxy_home
+            .path()
+            .join("staged");
+        fs::create_dir_all(prefix.join("bin")).unwrap();
+        fs::write(&staged_filepath, "new").unwrap();
+        fs::write(prefix.join("bin/replaced"), "old").unwrap();
+        fs::write(prefix.join("bin/removed"), "old").unwrap();
+
+        // A directory left behind by an older version, with no journal
+        let leftover_dirpath = paxy_home
+            .path()
+            .join("tmp/transaction-1");
+        fs::create_dir_all(&leftover_dirpath).unwrap();
+        fs::write(leftover_dirpath.join("0"), "backup").unwrap();
+
+        let mut transaction = Transaction::begin(paxy_home.path()).unwrap();
+        transaction
+            .place_file(&staged_filepath, &prefix.join("bin/replaced"))
+            .unwrap();
+        transaction
+            .place_file(&staged_filepath, &prefix.join("share/doc/created"))
+            .unwrap();
+        transaction
+            .remove_file(&prefix.join("bin/removed"))
+            .unwrap();
+        // Abandoned as if the process had been killed, releasing its lock
+        // without undoing anything
+        transaction.is_finished = true;
+        drop(transaction);
+        assert_eq!(
+            fs::read_to_string(prefix.join("bin/replaced")).unwrap(),
+            "new"
+        );
+
+        let transaction = Transaction::begin(paxy_home.path()).unwrap();
+        for file in ["bin/replaced", "bin/removed"] {
+            assert_eq!(fs::read_to_string(prefix.join(file)).unwrap(), "old");
+        }
+        assert!(!prefix
+            .join("share")
+            .exists());
+        assert!(leftover_dirpath
+            .join("0")
+            .is_file());
+        transaction.roll_back();
+        assert_eq!(
+            fs::read_dir(
+                paxy_home
+                    .path()
+                    .join("tmp")
+            )
+            .unwrap()
+            .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn committing_a_transaction_keeps_its_changes() {
+        let paxy_home = tempfile::tempdir().unwrap();
+        let staged_filepath = paxy_home
+            .path()
+            .join("staged");
+        let placed_filepath = paxy_home
+            .path()
+            .join("prefix/bin/placed");
+        fs::write(&staged_filepath, "new").unwrap();
+        let mut transaction = Transaction::begin(paxy_home.path()).unwrap();
+        transaction
+            .place_file(&staged_filepath, &placed_filepath)
+            .unwrap();
+        transaction
+            .commit(&Database::default(), "test")
+            .unwrap();
+
+        assert!(placed_filepath.is_file());
+        assert!(Database::filepath(paxy_home.path()).is_file());
+        assert!(History::filepath(paxy_home.path()).is_file());
+    }
+
+    #[test]
+    fn one_transaction_runs_at_a_time() {
+        let paxy_home = tempfile::tempdir().unwrap();
+        let lock_filepath = paxy_home
+            .path()
+            .join("transactions.lock");
+        let is_locked = || {
+            File::open(&lock_filepath)
+                .unwrap()
+                .try_lock()
+                .is_err()
+        };
+        let transaction = Transaction::begin(paxy_home.path()).unwrap();
+        assert!(is_locked());
+
+        // Another process saving the database in the meantime is noticed
+        let mut database = Database::default();
+        database.pin("hello", semver::VersionReq::STAR);
+        database
+            .save(&Database::filepath(paxy_home.path()))
+            .unwrap();
+        assert!(matches!(
+            transaction.check_database(&Database::default()),
+            Err(Error::DatabaseChanged { .. })
+        ));
+        transaction
+            .check_database(&database)
+            .unwrap();
+
+        transaction
+            .commit(&database, "test")
+            .unwrap();
+        assert!(!is_locked());
+    }
+}
+
+// endregion: TESTS
@@ -1,12 +1,272 @@
-#[allow(unused)]
-pub fn handle_package_uninstall_action(
+//! Uninstalls packages by removing exactly the files recorded for them in the
+//! installed-package database, along with any directories left empty.
+//! Configuration files that were edited since they were installed are kept,
+//! renamed with a `.paxysave` suffix. All the packages uninstalled together
+//! form a single transaction, which is rolled back if any of them fails.
+//! Packages that other installed packages depend on are only uninstalled
+//! along with those packages.
+
+pub fn handle_package_uninstall_action<G: GlobalArguments>(
     package_uninstall_arguments: PackageUninstallArguments,
+    global_arguments: G,
 ) -> Result<(), Error> {
-    use crate::app::ui::console_template::cli::*;
+    let paxy_home = data::paxy_home();
+    if global_arguments.is_test() {
+        return preview_uninstall(&paxy_home, &package_uninstall_arguments.package_names);
+    }
+
+    let database_filepath = Database::filepath(&paxy_home);
+    let mut database = Database::load(&database_filepath).context(DatabaseSnafu {})?;
+    let package_names = names_to_uninstall(
+        &database,
+        &package_uninstall_arguments.package_names,
+        || {
+            Ok(package_uninstall_arguments.uninstall_dependents
+                || ui::confirm("Uninstall them too? Otherwise nothing is uninstalled.")
+                    .context(ConfirmSnafu {})?
+                    .unwrap_or_default())
+        },
+    )?;
+
+    let description = format!("uninstall {}", package_names.join(" "));
+    let mut transaction = Transaction::begin(&paxy_home).context(TransactionSnafu {})?;
+    transaction
+        .check_database(&database)
+        .context(TransactionSnafu {})?;
+    for package_name in package_names {
+        let package = database
+            .get(&package_name)
+            .cloned()
+            .context(NotInstalledSnafu {
+                name: &package_name,
+            })?;
+        let plan = UninstallPlan::new(&package)?;
+        uninstall_package(&package, &plan, &mut transaction)?;
+        database.remove(&package_name);
+        database.unpin(&package_name);
+
+        tracing::info!(
+            "{} Uninstalled {} {}",
+            console::Emoji("🗑️", ""),
+            package.name.green(),
+            package.version
+        );
+        tracing::info!(target: "PLAIN", "{} {}", package.name, package.version);
+    }
+    transaction
+        .commit(&database, &description)
+        .context(TransactionSnafu {})?;
+
+    Ok(())
+}
+
+/// Shows what uninstalling the named packages, along with the installed
+/// packages that depend on them, would do, without changing anything on disk.
+pub fn preview_uninstall(paxy_home: &Path, package_names: &[String]) -> Result<(), Error> {
+    let database = Database::load(&Database::filepath(paxy_home)).context(DatabaseSnafu {})?;
+    for package_name in names_to_uninstall(&database, package_names, || Ok(true))? {
+        let package = database
+            .get(&package_name)
+            .context(NotInstalledSnafu {
+                name: &package_name,
+            })?;
+        UninstallPlan::new(package)?.emit(package);
+    }
+
+    Ok(())
+}
+
+/// Returns the names of the packages to uninstall, sorted and each only once:
+/// the named packages, which must all be installed, and the installed
+/// packages that depend on them. Dependents that were not named are listed,
+/// and only added if `uninstall_dependents` agrees to it; otherwise nothing
+/// is uninstalled.
+fn names_to_uninstall(
+    database: &Database,
+    package_names: &[String],
+    uninstall_dependents: impl FnOnce() -> Result<bool, Error>,
+) -> Result<Vec<String>, Error> {
+    for package_name in package_names.iter() {
+        ensure!(
+            database
+                .get(package_name)
+                .is_some(),
+            NotInstalledSnafu { name: package_name }
+        );
+    }
+    let mut names: BTreeSet<String> = package_names
+        .iter()
+        .cloned()
+        .collect();
+
+    let dependents: Vec<&InstalledPackage> = database
+        .dependents(package_names)
+        .into_iter()
+        .filter(|package| !names.contains(&package.name))
+        .collect();
+    if !dependents.is_empty() {
+        tracing::warn!(
+            "{} These installed packages depend on the packages being uninstalled:",
+            console::Emoji("⚠️", "")
+        );
+        for package in dependents.iter() {
+            tracing::warn!("  {} {}", package.name, package.version);
+        }
+        let dependent_names: Vec<String> = dependents
+            .iter()
+            .map(|package| package.name.clone())
+            .collect();
+        ensure!(
+            uninstall_dependents()?,
+            DependentsSnafu {
+                names: dependent_names
+            }
+        );
+        names.extend(dependent_names);
+    }
+
+    Ok(names
+        .into_iter()
+        .collect())
+}
+
+/// What uninstalling a package will do to the filesystem.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UninstallPlan {
+    /// Absolute paths of the files to delete.
+    pub removals: Vec<PathBuf>,
+    /// Absolute paths of edited configuration files to keep, each with the
+    /// path it will be moved to.
+    pub backups: Vec<(PathBuf, PathBuf)>,
+    /// Absolute paths of recorded files that no longer exist.
+    pub missing: Vec<PathBuf>,
+}
+
+impl UninstallPlan {
+    /// Works out what to do with each file of an installed package.
+    pub fn new(package: &InstalledPackage) -> Result<Self, Error> {
+        let mut plan = UninstallPlan::default();
+
+        for file in package.files.iter() {
+            let filepath = package
+                .install_location
+                .join(file);
+            if fs::symlink_metadata(&filepath).is_err() {
+                plan.missing
+                    .push(filepath);
+                continue;
+            }
+
+            if package
+                .is_edited_config_file(file)
+                .context(ChecksumSnafu { path: &filepath })?
+            {
+                let mut backup_filepath = filepath
+                    .as_os_str()
+                    .to_owned();
+                backup_filepath.push(".paxysave");
+                plan.backups
+                    .push((filepath, PathBuf::from(backup_filepath)));
+            } else {
+                plan.removals
+                    .push(filepath);
+            }
+        }
+
+        Ok(plan)
+    }
+
+    /// Describes the plan without carrying it out.
+    fn emit(&self, package: &InstalledPackage) {
+        tracing::info!(
+            "{} Uninstalling {} {} would:",
+            console::Emoji("🧪", ""),
+            package.name.green(),
+            package.version
+        );
+        for filepath in self.removals.iter() {
+            tracing::info!("  remove {:?}", filepath);
+        }
+        for (filepath, backup_filepath) in self.backups.iter() {
+            tracing::info!("  keep the edited {:?} as {:?}", filepath, backup_filepath);
+        }
+        for filepath in self.missing.iter() {
+            tracing::info!("  skip {:?}, which no longer exists", filepath);
+        }
+        tracing::info!("  remove any directories left empty");
+    }
+}
+
+/// Carries out an uninstall plan for a package as part of a transaction, and
+/// then removes the directories under the package's install location that
+/// were left empty.
+pub fn uninstall_package(
+    package: &InstalledPackage,
+    plan: &UninstallPlan,
+    transaction: &mut Transaction,
+) -> Result<(), Error> {
+    for (filepath, backup_filepath) in plan.backups.iter() {
+        transaction
+            .rename_file(filepath, backup_filepath)
+            .context(TransactionSnafu {})?;
+        tracing::warn!(
+            "Kept the edited configuration file {:?} as {:?}",
+            filepath,
+            backup_filepath
+        );
+    }
+
+    for filepath in plan.removals.iter() {
+        transaction
+            .remove_file(filepath)
+            .context(TransactionSnafu {})?;
+    }
+
+    let touched_filepaths = plan
+        .removals
+        .iter()
+        .chain(
+            plan.backups
+                .iter()
+                .map(|(filepath, _)| filepath),
+        );
+    remove_empty_directories(touched_filepaths, &package.install_location);
+
+    Ok(())
+}
 
-    todo!();
+/// Removes the parent directories of the given files, deepest first, as long
+/// as they are empty and lie strictly inside the install location.
+fn remove_empty_directories<'a>(
+    filepaths: impl Iterator<Item = &'a PathBuf>,
+    install_location: &Path,
+) {
+    let mut dirpaths: BTreeSet<&Path> = BTreeSet::new();
+    for filepath in filepaths {
+        dirpaths.extend(
+            filepath
+                .ancestors()
+                .skip(1)
+                .take_while(|dirpath| {
+                    dirpath != &install_location && dirpath.starts_with(install_location)
+                }),
+        );
+    }
 
-    // Ok(())
+    let mut dirpaths: Vec<&Path> = dirpaths
+        .into_iter()
+        .collect();
+    dirpaths.sort_by_key(|dirpath| {
+        std::cmp::Reverse(
+            dirpath
+                .components()
+                .count(),
+        )
+    });
+    for dirpath in dirpaths {
+        // Directories that are not empty are left alone
+        _ = fs::remove_dir(dirpath);
+    }
 }
 
 // region: ERRORS
@@ -16,17 +276,218 @@ pub fn handle_package_uninstall_action(
 #[non_exhaustive]
 pub enum Error {
     #[non_exhaustive]
-    #[snafu(display(""))]
-    Dummy {},
+    #[snafu(display("in the installed-package database: {source}"))]
+    Database { source: installed::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("the package '{name}' is not installed"))]
+    NotInstalled { name: String },
+
+    #[non_exhaustive]
+    #[snafu(display("could not ask whether to uninstall the dependent packages: {source}"))]
+    Confirm { source: std::io::Error },
+
+    #[non_exhaustive]
+    #[snafu(display(
+        "{} depend(s) on the packages being uninstalled. Pass --uninstall-dependents to \
+         uninstall them too",
+        names.join(", ")
+    ))]
+    Dependents { names: Vec<String> },
+
+    #[non_exhaustive]
+    #[snafu(display("could not compute the checksum of {path:?}: {source}"))]
+    Checksum {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("in the transaction: {source}"))]
+    Transaction { source: transaction::Error },
 }
 
 // endregion: ERRORS
 
 // region: IMPORTS
 
-#[allow(unused)]
-use snafu::{ResultExt, Snafu};
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use owo_colors::OwoColorize;
+use snafu::{ensure, OptionExt, ResultExt, Snafu};
 
-use crate::app::ui::console_template::cli::PackageUninstallArguments;
+use super::transaction::{self, Transaction};
+use crate::{
+    app::ui::{self, console_template::cli::PackageUninstallArguments, GlobalArguments},
+    data::{
+        self,
+        installed::{self, Database, InstalledPackage},
+    },
+};
 
 // endregion: IMPORTS
+
+// region: TESTS
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use semver::VersionReq;
+
+    use super::*;
+    use crate::data::installed::tests::installed_package;
+
+    #[test]
+    fn uninstall_keeps_edited_config_files() {
+        let install_location = tempfile::tempdir().unwrap();
+        let install_location = install_location.path();
+        for (file, contents) in [
+            ("bin/hello", "hello"),
+            ("share/hello/README", "readme"),
+            ("etc/hello.conf", "greeting = hello"),
+            ("etc/other.conf", "not ours"),
+        ] {
+            let filepath = install_location.join(file);
+            fs::create_dir_all(
+                filepath
+                    .parent()
+                    .unwrap(),
+            )
+            .unwrap();
+            fs::write(filepath, contents).unwrap();
+        }
+        let config_filepath = install_location.join("etc/hello.conf");
+        let package = InstalledPackage {
+            install_location: install_location.to_path_buf(),
+            files: [
+                "bin/hello",
+                "share/hello/README",
+                "etc/hello.conf",
+                "lib/gone",
+            ]
+            .into_iter()
+            .map(PathBuf::from)
+            .collect(),
+            config_files: BTreeMap::from([(
+                PathBuf::from("etc/hello.conf"),
+                data::sha256_of_file(&config_filepath).unwrap(),
+            )]),
+            ..installed_package("hello", "1.0.0")
+        };
+        fs::write(&config_filepath, "greeting = hi").unwrap();
+
+        let plan = UninstallPlan::new(&package).unwrap();
+        assert_eq!(plan.removals.len(), 2);
+        assert_eq!(plan.missing, [install_location.join("lib/gone")]);
+
+        let paxy_home = tempfile::tempdir().unwrap();
+        let mut transaction = Transaction::begin(paxy_home.path()).unwrap();
+        uninstall_package(&package, &plan, &mut transaction).unwrap();
+        transaction
+            .commit(&Database::default(), "uninstall hello")
+            .unwrap();
+
+        assert!(!install_location
+            .join("bin")
+            .exists());
+        assert!(!install_location
+            .join("share")
+            .exists());
+        assert!(!config_filepath.exists());
+        assert_eq!(
+            fs::read_to_string(install_location.join("etc/hello.conf.paxysave")).unwrap(),
+            "greeting = hi"
+        );
+        assert!(install_location
+            .join("etc/other.conf")
+            .exists());
+        assert!(install_location.exists());
+    }
+
+    /// Returns every directory and file under the given directory, along with
+    /// the contents of the files.
+    fn snapshot(dirpath: &Path) -> BTreeMap<PathBuf, Option<Vec<u8>>> {
+        let mut snapshot = BTreeMap::new();
+        for entry in fs::read_dir(dirpath).unwrap() {
+            let path = entry
+                .unwrap()
+                .path();
+            if path.is_dir() {
+                snapshot.extend(self::snapshot(&path));
+                snapshot.insert(path, None);
+            } else {
+                let contents = fs::read(&path).unwrap();
+                snapshot.insert(path, Some(contents));
+            }
+        }
+        snapshot
+    }
+
+    #[test]
+    fn preview_leaves_the_paxy_home_alone() {
+        let paxy_home = tempfile::tempdir().unwrap();
+        let install_location = paxy_home
+            .path()
+            .join("pkgs");
+        fs::create_dir_all(install_location.join("bin")).unwrap();
+        fs::write(install_location.join("bin/hello"), "hello").unwrap();
+        let mut database = Database::default();
+        database.insert(InstalledPackage {
+            install_location: install_location.clone(),
+            files: vec![PathBuf::from("bin/hello")],
+            ..installed_package("hello", "1.0.0")
+        });
+        database.insert(InstalledPackage {
+            dependencies: BTreeMap::from([("hello".to_string(), VersionReq::STAR)]),
+            ..installed_package("app", "1.0.0")
+        });
+        database
+            .save(&Database::filepath(paxy_home.path()))
+            .unwrap();
+        let before = snapshot(paxy_home.path());
+
+        preview_uninstall(paxy_home.path(), &["hello".to_string()]).unwrap();
+
+        assert_eq!(snapshot(paxy_home.path()), before);
+    }
+
+    #[test]
+    fn named_dependents_are_uninstalled_once() {
+        let mut database = Database::default();
+        database.insert(installed_package("hello", "1.0.0"));
+        database.insert(InstalledPackage {
+            dependencies: BTreeMap::from([("hello".to_string(), VersionReq::STAR)]),
+            ..installed_package("app", "1.0.0")
+        });
+        database.insert(InstalledPackage {
+            dependencies: BTreeMap::from([("app".to_string(), VersionReq::STAR)]),
+            ..installed_package("tool", "1.0.0")
+        });
+        let names = |package_names: &[&str]| {
+            package_names
+                .iter()
+                .map(|name| name.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            names_to_uninstall(&database, &names(&["hello", "app", "hello"]), || Ok(true)).unwrap(),
+            ["app", "hello", "tool"]
+        );
+        assert!(matches!(
+            names_to_uninstall(&database, &names(&["hello"]), || Ok(false)),
+            Err(Error::Dependents { names }) if names == ["app", "tool"]
+        ));
+        assert_eq!(
+            names_to_uninstall(&database, &names(&["hello", "app", "tool"]), || Ok(false)).unwrap(),
+            ["app", "hello", "tool"]
+        );
+    }
+}
+
+// endregion: TESTS
@@ -0,0 +1,76 @@
+//! Releases the pins of packages, so that updates may move them again.
+
+pub fn handle_package_unpin_action(
+    package_unpin_arguments: PackageUnpinArguments,
+) -> Result<(), Error> {
+    let database_filepath = Database::filepath(&data::paxy_home());
+    let mut database = Database::load(&database_filepath).context(DatabaseSnafu {})?;
+
+    // Every package is checked first, so that nothing is unpinned unless all
+    // of them can be
+    for package_name in package_unpin_arguments
+        .package_names
+        .iter()
+    {
+        ensure!(
+            database
+                .pin_of(package_name)
+                .is_some(),
+            NotPinnedSnafu { name: package_name }
+        );
+    }
+
+    for package_name in package_unpin_arguments
+        .package_names
+        .iter()
+    {
+        // A package named twice is only unpinned once
+        let Some(requirement) = database.unpin(package_name) else {
+            continue;
+        };
+        tracing::info!(
+            "{} Unpinned {} from {}",
+            console::Emoji("📍", ""),
+            package_name.green(),
+            requirement
+        );
+        tracing::info!(target: "PLAIN", "{}", package_name);
+    }
+    database
+        .save(&database_filepath)
+        .context(DatabaseSnafu {})?;
+
+    Ok(())
+}
+
+// region: ERRORS
+
+#[derive(Debug, Snafu)]
+#[snafu(visibility(pub(crate)))]
+#[non_exhaustive]
+pub enum Error {
+    #[non_exhaustive]
+    #[snafu(display("the package '{name}' is not pinned"))]
+    NotPinned { name: String },
+
+    #[non_exhaustive]
+    #[snafu(display("in the installed-package database: {source}"))]
+    Database { source: installed::Error },
+}
+
+// endregion: ERRORS
+
+// region: IMPORTS
+
+use owo_colors::OwoColorize;
+use snafu::{ensure, ResultExt, Snafu};
+
+use crate::{
+    app::ui::console_template::cli::PackageUnpinArguments,
+    data::{
+        self,
+        installed::{self, Database},
+    },
+};
+
+// endregion: IMPORTS
@@ -1,12 +1,444 @@
-#[allow(unused)]
-pub fn handle_package_update_action(
+//! Updates installed packages to the latest versions offered by the
+//! repositories. The update is planned in full first, and nothing is changed
+//! until the plan has been shown and confirmed.
+
+pub fn handle_package_update_action<G: GlobalArguments>(
     package_update_arguments: PackageUpdateArguments,
+    global_arguments: G,
 ) -> Result<(), Error> {
-    use crate::app::ui::console_template::cli::*;
+    let config = config::load_conf();
+    let paxy_home = data::paxy_home();
+    let mut database = Database::load(&Database::filepath(&paxy_home)).context(DatabaseSnafu {})?;
+    let catalog = Catalog::new(&paxy_home).context(ResolveSnafu {})?;
+
+    let (resolution, plan) = plan_update(
+        &catalog,
+        &database,
+        &paxy_home,
+        &package_update_arguments.package_names,
+        &package_update_arguments.excluded_package_names,
+    )?;
+    plan.emit()?;
+    if plan
+        .updates
+        .is_empty()
+        || global_arguments.is_test()
+    {
+        return Ok(());
+    }
+
+    if !package_update_arguments.assume_yes {
+        match ui::confirm("Proceed with the update?").context(ConfirmSnafu {})? {
+            Some(true) => {}
+            Some(false) => {
+                tracing::info!("{} Update cancelled", console::Emoji("✋", ""));
+                return Ok(());
+            }
+            None => return ConfirmationRequiredSnafu {}.fail(),
+        }
+    }
+
+    let mut transaction = Transaction::begin(&paxy_home).context(TransactionSnafu {})?;
+    transaction
+        .check_database(&database)
+        .context(TransactionSnafu {})?;
+    for planned_install in resolution.to_install {
+        // Updated packages stay where they are, and new dependencies go to the
+        // default location
+        let install_type = database
+            .get(
+                &planned_install
+                    .candidate
+                    .name,
+            )
+            .map_or(config.default_install_type, |package| package.install_type);
+        let installation = install::install_package(
+            &planned_install
+                .candidate
+                .manifest_filepath,
+            &planned_install.features,
+            install_type,
+            config.install_location(install_type),
+            &paxy_home,
+            &database,
+            &mut transaction,
+        )
+        .context(InstallSnafu {})?;
+        tracing::info!(
+            "{} Installed {} {}",
+            console::Emoji("📦", ""),
+            installation
+                .manifest
+                .name
+                .green(),
+            installation
+                .manifest
+                .version
+        );
+        database.insert(
+            installation.into_installed_package(
+                planned_install
+                    .candidate
+                    .repository,
+                install_type,
+            ),
+        );
+    }
+    transaction
+        .commit(
+            &database,
+            &format!(
+                "update {}",
+                plan.updates
+                    .iter()
+                    .map(|update| update.name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            ),
+        )
+        .context(TransactionSnafu {})?;
+
+    Ok(())
+}
+
+/// A single change in an update plan.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PlannedUpdate {
+    pub name: String,
+    /// The installed version, or `None` for a new dependency.
+    pub current_version: Option<Version>,
+    pub new_version: Version,
+    pub repository: Option<String>,
+    /// The change in the size of the installed files in bytes, if the size of
+    /// the new version is known.
+    pub size_delta: Option<i64>,
+}
+
+/// A package left out of an update, with the reason why.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SkippedUpdate {
+    pub name: String,
+    pub reason: String,
+}
+
+/// Everything an update would change, and what it leaves out.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct UpdatePlan {
+    pub updates: Vec<PlannedUpdate>,
+    pub skipped: Vec<SkippedUpdate>,
+}
+
+impl UpdatePlan {
+    /// Shows the plan as a table, and as a document on the `JSON` target.
+    fn emit(&self) -> Result<(), Error> {
+        tracing::info!(
+            target: "JSON",
+            "{}",
+            serde_json::to_string(self).context(SerializeJsonSnafu {})?
+        );
+        for skipped_update in self.skipped.iter() {
+            tracing::info!(
+                "{} Skipping {}: {}",
+                console::Emoji("📌", ""),
+                skipped_update
+                    .name
+                    .green(),
+                skipped_update.reason
+            );
+        }
+        if self
+            .updates
+            .is_empty()
+        {
+            if self
+                .skipped
+                .is_empty()
+            {
+                tracing::info!("{} Everything is up to date", console::Emoji("✅", ""));
+            } else {
+                tracing::info!("{} Nothing else to update", console::Emoji("✅", ""));
+            }
+            return Ok(());
+        }
+
+        let rows: Vec<[String; 4]> = self
+            .updates
+            .iter()
+            .map(|update| {
+                [
+                    update.name.clone(),
+                    update
+                        .current_version
+                        .as_ref()
+                        .map_or("(new)".to_string(), Version::to_string),
+                    update
+                        .new_version
+                        .to_string(),
+                    ui::format_size_delta(update.size_delta),
+                ]
+            })
+            .collect();
+        let header = [
+            "Package".to_string(),
+            "Current".to_string(),
+            "New".to_string(),
+            "Size change".to_string(),
+        ];
+        let mut widths = [0; 4];
+        for row in std::iter::once(&header).chain(rows.iter()) {
+            for (width, cell) in widths
+                .iter_mut()
+                .zip(row.iter())
+            {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+        let format_row = |row: &[String; 4]| {
+            format!(
+                "{:<w0$}  {:<w1$}  {:<w2$}  {:>w3$}",
+                row[0],
+                row[1],
+                row[2],
+                row[3],
+                w0 = widths[0],
+                w1 = widths[1],
+                w2 = widths[2],
+                w3 = widths[3],
+            )
+        };
 
-    todo!();
+        tracing::info!(
+            "{} {} package(s) to update:",
+            console::Emoji("📋", ""),
+            self.updates.len()
+        );
+        tracing::info!("  {}", format_row(&header).bold());
+        for row in rows.iter() {
+            tracing::info!("  {}", format_row(row));
+            tracing::info!(target: "PLAIN", "{}", row.join("\t"));
+        }
 
-    // Ok(())
+        Ok(())
+    }
+}
+
+/// Works out which packages to update to which versions. Without package
+/// names, every installed package is considered. Excluded packages are kept
+/// at their installed versions, even when another update would need them
+/// updated too, and pinned packages are kept within their pins.
+pub fn plan_update(
+    catalog: &Catalog,
+    database: &Database,
+    paxy_home: &Path,
+    package_names: &[String],
+    excluded_package_names: &[String],
+) -> Result<(Resolution, UpdatePlan), Error> {
+    for package_name in package_names
+        .iter()
+        .chain(excluded_package_names)
+    {
+        ensure!(
+            database
+                .get(package_name)
+                .is_some(),
+            NotInstalledSnafu { name: package_name }
+        );
+    }
+
+    // Excluded packages are held at their installed versions
+    let held_requests: Vec<Request> = excluded_package_names
+        .iter()
+        .filter_map(|name| database.get(name))
+        .map(hold)
+        .collect();
+    let targets: Vec<&InstalledPackage> = database
+        .packages
+        .values()
+        .filter(|package| {
+            !excluded_package_names.contains(&package.name)
+                && (package_names.is_empty() || package_names.contains(&package.name))
+        })
+        .collect();
+    let mut skipped = Vec::new();
+
+    // Usually nothing stands in the way, and every package is updated at once
+    let mut requests: Vec<Request> = held_requests
+        .iter()
+        .cloned()
+        .chain(
+            targets
+                .iter()
+                .map(|package| update(package)),
+        )
+        .collect();
+    let resolution = match resolver::resolve(catalog, database, &requests) {
+        Ok(resolution) => resolution,
+        // Otherwise the packages are added one by one, so that those whose
+        // update would break a pin are found, skipped, and held where they are
+        Err(error) if error.involves_pin() => {
+            requests = held_requests;
+            for package in targets {
+                let mut attempt = requests.clone();
+                attempt.push(update(package));
+                match resolver::resolve(catalog, database, &attempt) {
+                    Ok(_) => requests = attempt,
+                    Err(error) if error.involves_pin() => {
+                        skipped.push(SkippedUpdate {
+                            name: package.name.clone(),
+                            reason: error.to_string(),
+                        });
+                        requests.push(hold(package));
+                    }
+                    Err(error) => return Err(error).context(ResolveSnafu {}),
+                }
+            }
+            resolver::resolve(catalog, database, &requests).context(ResolveSnafu {})?
+        }
+        Err(error) => return Err(error).context(ResolveSnafu {}),
+    };
+
+    // Pinned packages held back from newer versions are explained too
+    for request in requests
+        .iter()
+        .filter(|request| {
+            !resolution
+                .to_install
+                .iter()
+                .any(|planned_install| {
+                    planned_install
+                        .candidate
+                        .name
+                        == request.name
+                })
+        })
+    {
+        let (Some(package), Some(pin)) =
+            (database.get(&request.name), database.pin_of(&request.name))
+        else {
+            continue;
+        };
+        let newest_version = catalog
+            .candidates(&request.name)
+            .context(ResolveSnafu {})?
+            .into_iter()
+            .map(|candidate| candidate.version)
+            .max();
+        if let Some(newest_version) =
+            newest_version.filter(|version| version > &package.version && !pin.matches(version))
+        {
+            skipped.push(SkippedUpdate {
+                name: request.name.clone(),
+                reason: format!(
+                    "{} is pinned to {pin}, while {newest_version} is available",
+                    request.name
+                ),
+            });
+        }
+    }
+
+    let updates = resolution
+        .to_install
+        .iter()
+        .map(|planned_install| {
+            let installed = database.get(
+                &planned_install
+                    .candidate
+                    .name,
+            );
+            let current_size = installed.map_or(0, installed_size);
+            let new_size = planned_install
+                .manifest
+                .installed_size
+                .or_else(|| {
+                    // A build for either install location tells the size
+                    [InstallType::User, InstallType::System]
+                        .into_iter()
+                        .find_map(|install_type| {
+                            cache::lookup(
+                                paxy_home,
+                                &planned_install
+                                    .candidate
+                                    .name,
+                                &planned_install
+                                    .candidate
+                                    .version,
+                                install_type,
+                                &planned_install.features,
+                            )
+                        })
+                        .map(|artifact| directory_size(&artifact.files_dirpath))
+                });
+            PlannedUpdate {
+                name: planned_install
+                    .candidate
+                    .name
+                    .clone(),
+                current_version: installed.map(|package| {
+                    package
+                        .version
+                        .clone()
+                }),
+                new_version: planned_install
+                    .candidate
+                    .version
+                    .clone(),
+                repository: planned_install
+                    .candidate
+                    .repository
+                    .clone(),
+                size_delta: new_size.map(|new_size| new_size as i64 - current_size as i64),
+            }
+        })
+        .collect();
+
+    Ok((resolution, UpdatePlan { updates, skipped }))
+}
+
+/// Returns a request that updates an installed package to the latest version
+/// it can have.
+fn update(package: &InstalledPackage) -> Request {
+    Request {
+        name: package.name.clone(),
+        repository: None,
+        requirement: VersionReq::STAR,
+        preference: Preference::Latest,
+        features: BTreeSet::new(),
+    }
+}
+
+/// Returns a request that keeps an installed package as it is.
+fn hold(package: &InstalledPackage) -> Request {
+    Request {
+        name: package.name.clone(),
+        repository: None,
+        requirement: resolver::exactly(&package.version),
+        preference: Preference::Installed,
+        features: BTreeSet::new(),
+    }
+}
+
+/// Returns the total size of the files of an installed package that are still
+/// there.
+fn installed_size(package: &InstalledPackage) -> u64 {
+    package
+        .absolute_filepaths()
+        .filter_map(|filepath| fs::symlink_metadata(filepath).ok())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+fn directory_size(dirpath: &Path) -> u64 {
+    fs::read_dir(dirpath)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .map(|entry| match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => directory_size(&entry.path()),
+            _ => entry
+                .metadata()
+                .map_or(0, |metadata| metadata.len()),
+        })
+        .sum()
 }
 
 // region: ERRORS
@@ -16,17 +448,193 @@ pub fn handle_package_update_action(
 #[non_exhaustive]
 pub enum Error {
     #[non_exhaustive]
-    #[snafu(display(""))]
-    Dummy {},
+    #[snafu(display("the package '{name}' is not installed"))]
+    NotInstalled { name: String },
+
+    #[non_exhaustive]
+    #[snafu(display(
+        "there is no terminal to confirm the update on. Pass `--yes` to update without \
+         confirmation"
+    ))]
+    ConfirmationRequired {},
+
+    #[non_exhaustive]
+    #[snafu(display("could not ask for confirmation: {source}"))]
+    Confirm { source: std::io::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("in the installed-package database: {source}"))]
+    Database { source: installed::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("could not resolve the dependencies: {source}"))]
+    Resolve { source: resolver::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("in the transaction: {source}"))]
+    Transaction { source: transaction::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("{source}"))]
+    Install { source: install::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("could not serialize the update plan to JSON: {source}"))]
+    SerializeJson { source: serde_json::Error },
 }
 
 // endregion: ERRORS
 
 // region: IMPORTS
 
-#[allow(unused)]
-use snafu::{ResultExt, Snafu};
+use std::{collections::BTreeSet, fs, path::Path};
+
+use owo_colors::OwoColorize;
+use semver::{Version, VersionReq};
+use serde::Serialize;
+use snafu::{ensure, ResultExt, Snafu};
 
-use crate::app::ui::console_template::cli::PackageUpdateArguments;
+use super::{
+    install,
+    resolver::{self, Catalog, Preference, Request, Resolution},
+    transaction::{self, Transaction},
+};
+use crate::{
+    app::ui::{self, console_template::cli::PackageUpdateArguments, GlobalArguments},
+    data::{
+        self,
+        cache,
+        config::{self, InstallType},
+        installed::{self, Database, InstalledPackage},
+    },
+};
 
 // endregion: IMPORTS
+
+// region: TESTS
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, path::PathBuf};
+
+    use super::*;
+    use crate::data::{installed::tests::installed_package, repository::tests::write_manifest};
+
+    #[test]
+    fn plan_updates_to_newer_versions_except_excluded_packages() {
+        let paxy_home = tempfile::tempdir().unwrap();
+        for (name, version, size) in [
+            ("hello", "1.0.0", 100),
+            ("hello", "1.1.0", 150),
+            ("lib", "1.0.0", 10),
+            ("lib", "2.0.0", 10),
+            ("fresh", "1.0.0", 10),
+        ] {
+            write_manifest(
+                &data::repositories_dirpath(paxy_home.path()).join("main"),
+                name,
+                version,
+                &format!("installed_size = {size}"),
+            );
+        }
+        let prefix = paxy_home
+            .path()
+            .join("prefix");
+        fs::create_dir_all(&prefix).unwrap();
+        fs::write(prefix.join("hello"), [0; 120]).unwrap();
+        let mut database = Database::default();
+        for (name, files) in [("hello", vec![PathBuf::from("hello")]), ("lib", Vec::new())] {
+            database.insert(InstalledPackage {
+                repository: Some("main".to_string()),
+                install_location: prefix.clone(),
+                files,
+                ..installed_package(name, "1.0.0")
+            });
+        }
+        let catalog = Catalog::new(paxy_home.path()).unwrap();
+
+        let (_, plan) = plan_update(
+            &catalog,
+            &database,
+            paxy_home.path(),
+            &[],
+            &["lib".to_string()],
+        )
+        .unwrap();
+
+        assert_eq!(
+            plan.updates,
+            [PlannedUpdate {
+                name: "hello".to_string(),
+                current_version: Some(Version::new(1, 0, 0)),
+                new_version: Version::new(1, 1, 0),
+                repository: Some("main".to_string()),
+                size_delta: Some(30),
+            }]
+        );
+        assert!(matches!(
+            plan_update(
+                &catalog,
+                &database,
+                paxy_home.path(),
+                &["fresh".to_string()],
+                &[]
+            ),
+            Err(Error::NotInstalled { .. })
+        ));
+    }
+
+    #[test]
+    fn pinned_dependencies_block_updates_that_would_break_them() {
+        let paxy_home = tempfile::tempdir().unwrap();
+        for (name, version, dependency) in [
+            ("app", "1.0.0", "lib = \"^1\""),
+            ("app", "2.0.0", "lib = \"^2\""),
+            ("lib", "1.0.0", ""),
+            ("lib", "2.0.0", ""),
+        ] {
+            write_manifest(
+                &data::repositories_dirpath(paxy_home.path()).join("main"),
+                name,
+                version,
+                &format!("\n[dependencies]\n{dependency}"),
+            );
+        }
+        let mut database = Database::default();
+        for (name, dependencies) in [
+            (
+                "app",
+                BTreeMap::from([("lib".to_string(), VersionReq::parse("^1").unwrap())]),
+            ),
+            ("lib", BTreeMap::new()),
+        ] {
+            database.insert(InstalledPackage {
+                repository: Some("main".to_string()),
+                dependencies,
+                ..installed_package(name, "1.0.0")
+            });
+        }
+        database.pin("lib", resolver::exactly(&Version::new(1, 0, 0)));
+        let catalog = Catalog::new(paxy_home.path()).unwrap();
+
+        let (_, plan) = plan_update(&catalog, &database, paxy_home.path(), &[], &[]).unwrap();
+
+        assert!(plan
+            .updates
+            .is_empty());
+        assert_eq!(
+            plan.skipped
+                .iter()
+                .map(|skipped_update| skipped_update
+                    .name
+                    .as_str())
+                .collect::<Vec<_>>(),
+            ["app", "lib"]
+        );
+        assert!(plan.skipped[0]
+            .reason
+            .contains("lib is pinned to =1.0.0"));
+    }
+}
+
+// endregion: TESTS
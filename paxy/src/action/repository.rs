@@ -1,6 +1,9 @@
 //! Handles repository-related actions.
 
-pub fn handle_repository_action(repository_subcommand: RepositorySubcommand) -> Result<(), Error> {
+pub fn handle_repository_action<G: GlobalArguments>(
+    repository_subcommand: RepositorySubcommand,
+    global_arguments: G,
+) -> Result<(), Error> {
     use crate::app::ui::console_template::cli::*;
 
     match repository_subcommand {
@@ -21,40 +24,31 @@ pub fn handle_repository_action(repository_subcommand: RepositorySubcommand) ->
                 .context(RepositoryUpdateSnafu {})?
         }
         RepositorySubcommand::Uninstall(repository_uninstall_arguments) => {
-            uninstall::handle_repository_uninstall_action(repository_uninstall_arguments)
-                .context(RepositoryUninstallSnafu {})?
+            uninstall::handle_repository_uninstall_action(
+                repository_uninstall_arguments,
+                global_arguments,
+            )
+            .context(RepositoryUninstallSnafu {})?
         }
         RepositorySubcommand::Downgrade(repository_downgrade_arguments) => {
             downgrade::handle_repository_downgrade_action(repository_downgrade_arguments)
                 .context(RepositoryDowngradeSnafu {})?
         }
-    }
-
-    Ok(())
-}
-
-macro_rules! home {
-    () => {
-        match home::home_dir() {
-            Some(path) => path,
-            None => panic!("Impossible to get your home dir!"),
+        RepositorySubcommand::Validate(repository_validate_arguments) => {
+            validate::handle_repository_validate_action(repository_validate_arguments)
+                .context(RepositoryValidateSnafu {})?
         }
-    };
-}
-
-#[inline]
-pub fn ensure_path(path: Option<&PathBuf>) {
-    if let Some(path) = path {
-        if !path.is_dir() {
-            ::std::fs::create_dir_all(path.clone()).expect("Inufficient permissions");
+        RepositorySubcommand::SetPriority(repository_set_priority_arguments) => {
+            set_priority::handle_repository_set_priority_action(repository_set_priority_arguments)
+                .context(RepositorySetPrioritySnafu {})?
         }
-    } else {
-        let mut file = home!();
-        file.push(".paxy");
-        if !file.is_dir() {
-            ::std::fs::create_dir_all(file).expect("Inufficient permissions");
+        RepositorySubcommand::Mirrors(repository_mirrors_arguments) => {
+            mirrors::handle_repository_mirrors_action(repository_mirrors_arguments)
+                .context(RepositoryMirrorsSnafu {})?
         }
     }
+
+    Ok(())
 }
 
 // region: ERRORS
@@ -86,17 +80,27 @@ pub enum Error {
     #[non_exhaustive]
     #[snafu(display("Could not downgrade:\n  {source}"))]
     RepositoryDowngrade { source: downgrade::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("Could not validate:\n  {source}"))]
+    RepositoryValidate { source: validate::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("Could not set the priority:\n  {source}"))]
+    RepositorySetPriority { source: set_priority::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("Could not change the mirrors:\n  {source}"))]
+    RepositoryMirrors { source: mirrors::Error },
 }
 
 // endregion: ERRORS
 
 // region: IMPORTS
 
-use std::path::PathBuf;
-
 use snafu::{ResultExt, Snafu};
 
-use crate::app::ui::console_template::cli::RepositorySubcommand;
+use crate::app::ui::{console_template::cli::RepositorySubcommand, GlobalArguments};
 
 // endregion: IMPORTS
 
@@ -105,14 +109,11 @@ use crate::app::ui::console_template::cli::RepositorySubcommand;
 pub mod downgrade;
 pub mod install;
 pub mod list;
+pub mod mirrors;
 pub mod search;
+pub mod set_priority;
 pub mod uninstall;
 pub mod update;
+pub mod validate;
 
 // endregion: EXTERNAL-SUBMODULES
-
-// region: RE-EXPORTS
-
-pub(crate) use home;
-
-// endregion: RE-EXPORTS
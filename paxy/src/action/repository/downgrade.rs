@@ -1,12 +1,272 @@
-#[allow(unused)]
+//! Pins the clone of a repository at an earlier tag, commit or date, which
+//! freezes the packages it offers. The pin is recorded in the repository
+//! registry, and updates leave the repository alone until it is unpinned.
+
 pub fn handle_repository_downgrade_action(
     repository_downgrade_arguments: RepositoryDowngradeArguments,
 ) -> Result<(), Error> {
-    use crate::app::ui::console_template::cli::*;
+    let paxy_home = data::paxy_home();
+    let repository_name = &repository_downgrade_arguments.repository_name;
+
+    if repository_downgrade_arguments.unpin {
+        let pin = unpin_repository(&paxy_home, repository_name)?;
+        tracing::info!(
+            "{} Unpinned {} from {}, back on {}",
+            console::Emoji("📍", ""),
+            repository_name.green(),
+            pin.reference,
+            pin.branch
+        );
+        tracing::info!(target: "PLAIN", "{}", repository_name);
+        return Ok(());
+    }
 
-    todo!();
+    let target = match (
+        repository_downgrade_arguments.version,
+        repository_downgrade_arguments.date,
+    ) {
+        (Some(reference), _) => PinTarget::Reference(reference),
+        (None, Some(date)) => PinTarget::Date {
+            start_of_day: ui::parse_date(&date).context(InvalidDateSnafu { date: &date })?,
+            text: date,
+        },
+        (None, None) => return MissingTargetSnafu {}.fail(),
+    };
+    let pin = pin_repository(&paxy_home, repository_name, &target)?;
+    tracing::info!(
+        "{} Pinned {} at {} {}",
+        console::Emoji("📌", ""),
+        repository_name.green(),
+        pin.reference,
+        format!(
+            "({})",
+            &pin.commit[..pin
+                .commit
+                .len()
+                .min(12)]
+        )
+        .dimmed()
+    );
+    tracing::info!(target: "PLAIN", "{} {}", repository_name, pin.commit);
+
+    Ok(())
+}
 
-    // Ok(())
+/// What to pin a repository at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PinTarget {
+    /// A tag, commit or anything else git can resolve to a commit.
+    Reference(String),
+    /// The last commit on the branch made on or before the day starting at
+    /// the given seconds since the Unix epoch.
+    Date { text: String, start_of_day: u64 },
+}
+
+/// Checks the clone of a repository out at the given target, detached from
+/// its branch, and records the pin in the registry. A repository that is
+/// already pinned can be pinned elsewhere, and keeps the branch it was on
+/// originally.
+pub fn pin_repository(
+    paxy_home: &Path,
+    repository_name: &str,
+    target: &PinTarget,
+) -> Result<RepositoryPin, Error> {
+    let registry_filepath = Registry::filepath(paxy_home);
+    let mut registry = Registry::load(&registry_filepath).context(RegistrySnafu {})?;
+    let registered_repository = registry
+        .get(repository_name)
+        .context(NotInstalledSnafu {
+            name: repository_name,
+        })?;
+    ensure!(
+        registered_repository.kind == RepositoryKind::Git,
+        NotGitSnafu {
+            name: repository_name,
+            kind: registered_repository.kind
+        }
+    );
+    let dirpath = data::repositories_dirpath(paxy_home).join(repository_name);
+    let git_repository = open_clean_clone(repository_name, &dirpath)?;
+
+    let branch = match &registered_repository.pin {
+        Some(pin) => pin.branch.clone(),
+        None => {
+            let head = git_repository
+                .head()
+                .context(GitSnafu {
+                    name: repository_name,
+                })?;
+            ensure!(
+                head.is_branch(),
+                DetachedSnafu {
+                    name: repository_name
+                }
+            );
+            head.shorthand()
+                .map(str::to_string)
+                .context(DetachedSnafu {
+                    name: repository_name,
+                })?
+        }
+    };
+
+    let (reference, commit) = match target {
+        PinTarget::Reference(reference) => {
+            let commit = git_repository
+                .revparse_single(reference)
+                .and_then(|object| object.peel_to_commit())
+                .context(UnknownReferenceSnafu {
+                    name: repository_name,
+                    reference,
+                })?;
+            (reference.clone(), commit.id())
+        }
+        PinTarget::Date { text, start_of_day } => (
+            text.clone(),
+            last_commit_before(
+                &git_repository,
+                &branch,
+                start_of_day.saturating_add(86_400),
+            )
+            .context(GitSnafu {
+                name: repository_name,
+            })?
+            .context(NoCommitBeforeDateSnafu {
+                name: repository_name,
+                date: text,
+            })?,
+        ),
+    };
+
+    git_repository
+        .set_head_detached(commit)
+        .context(GitSnafu {
+            name: repository_name,
+        })?;
+    git_repository
+        .checkout_head(Some(CheckoutBuilder::new().force()))
+        .context(GitSnafu {
+            name: repository_name,
+        })?;
+
+    let pin = RepositoryPin {
+        reference,
+        commit: commit.to_string(),
+        branch,
+    };
+    if let Some(registered_repository) = registry
+        .repositories
+        .get_mut(repository_name)
+    {
+        registered_repository.pin = Some(pin.clone());
+    }
+    registry
+        .save(&registry_filepath)
+        .context(RegistrySnafu {})?;
+    rebuild_search_index(paxy_home, repository_name, &dirpath);
+
+    Ok(pin)
+}
+
+/// Returns the clone of a repository to the branch it was on before it was
+/// pinned, and drops the pin from the registry. The branch is where it was
+/// when last updated, and the next update moves it on.
+pub fn unpin_repository(paxy_home: &Path, repository_name: &str) -> Result<RepositoryPin, Error> {
+    let registry_filepath = Registry::filepath(paxy_home);
+    let mut registry = Registry::load(&registry_filepath).context(RegistrySnafu {})?;
+    let pin = registry
+        .get(repository_name)
+        .context(NotInstalledSnafu {
+            name: repository_name,
+        })?
+        .pin
+        .clone()
+        .context(NotPinnedSnafu {
+            name: repository_name,
+        })?;
+    let dirpath = data::repositories_dirpath(paxy_home).join(repository_name);
+    let git_repository = open_clean_clone(repository_name, &dirpath)?;
+
+    git_repository
+        .set_head(&format!("refs/heads/{}", pin.branch))
+        .context(GitSnafu {
+            name: repository_name,
+        })?;
+    git_repository
+        .checkout_head(Some(CheckoutBuilder::new().force()))
+        .context(GitSnafu {
+            name: repository_name,
+        })?;
+
+    if let Some(registered_repository) = registry
+        .repositories
+        .get_mut(repository_name)
+    {
+        registered_repository.pin = None;
+    }
+    registry
+        .save(&registry_filepath)
+        .context(RegistrySnafu {})?;
+    rebuild_search_index(paxy_home, repository_name, &dirpath);
+
+    Ok(pin)
+}
+
+/// Opens the clone of a repository, refusing to if it has local
+/// modifications that checking out another commit would discard.
+fn open_clean_clone(repository_name: &str, dirpath: &Path) -> Result<Repository, Error> {
+    match list::clone_state(dirpath, RepositoryKind::Git) {
+        (_, CloneState::Missing) => MissingSnafu {
+            name: repository_name,
+        }
+        .fail(),
+        (_, CloneState::Broken) => BrokenSnafu {
+            name: repository_name,
+        }
+        .fail(),
+        (_, CloneState::Dirty) => LocalModificationsSnafu {
+            name: repository_name,
+        }
+        .fail(),
+        (_, CloneState::Clean) => Repository::open(dirpath).context(GitSnafu {
+            name: repository_name,
+        }),
+    }
+}
+
+/// Finds the newest commit on the given branch made before the given seconds
+/// since the Unix epoch.
+fn last_commit_before(
+    git_repository: &Repository,
+    branch: &str,
+    before: u64,
+) -> Result<Option<Oid>, git2::Error> {
+    let mut revwalk = git_repository.revwalk()?;
+    revwalk.set_sorting(Sort::TIME)?;
+    revwalk.push_ref(&format!("refs/heads/{branch}"))?;
+    for oid in revwalk {
+        let oid = oid?;
+        let seconds = git_repository
+            .find_commit(oid)?
+            .time()
+            .seconds();
+        if u64::try_from(seconds).is_ok_and(|seconds| seconds < before) {
+            return Ok(Some(oid));
+        }
+    }
+
+    Ok(None)
+}
+
+fn rebuild_search_index(paxy_home: &Path, repository_name: &str, dirpath: &Path) {
+    let local_repository = LocalRepository {
+        name: repository_name.to_string(),
+        dirpath: dirpath.to_path_buf(),
+        kind: RepositoryKind::Git,
+    };
+    if let Err(error) = SearchIndex::rebuild(paxy_home, &local_repository) {
+        tracing::warn!("Could not rebuild the search index of {repository_name}: {error}");
+    }
 }
 
 // region: ERRORS
@@ -16,17 +276,200 @@ pub fn handle_repository_downgrade_action(
 #[non_exhaustive]
 pub enum Error {
     #[non_exhaustive]
-    #[snafu(display(""))]
-    Dummy {},
+    #[snafu(display("the repository '{name}' is not installed"))]
+    NotInstalled { name: String },
+
+    #[non_exhaustive]
+    #[snafu(display(
+        "the repository '{name}' is a {kind} repository rather than a git clone, so it has no \
+         history to downgrade in"
+    ))]
+    NotGit { name: String, kind: RepositoryKind },
+
+    #[non_exhaustive]
+    #[snafu(display("the repository '{name}' is not pinned"))]
+    NotPinned { name: String },
+
+    #[non_exhaustive]
+    #[snafu(display("give a --version or --date to pin the repository at, or --unpin"))]
+    MissingTarget {},
+
+    #[non_exhaustive]
+    #[snafu(display("'{date}' is not a date like 2024-05-19"))]
+    InvalidDate { date: String },
+
+    #[non_exhaustive]
+    #[snafu(display("'{reference}' is not a tag or commit of '{name}': {source}"))]
+    UnknownReference {
+        name: String,
+        reference: String,
+        source: git2::Error,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("'{name}' has no commits on or before {date}"))]
+    NoCommitBeforeDate { name: String, date: String },
+
+    #[non_exhaustive]
+    #[snafu(display(
+        "the clone of '{name}' is not on a branch. Uninstall the repository and install it again"
+    ))]
+    Detached { name: String },
+
+    #[non_exhaustive]
+    #[snafu(display(
+        "the clone of '{name}' is missing. Uninstall the repository and install it again"
+    ))]
+    Missing { name: String },
+
+    #[non_exhaustive]
+    #[snafu(display(
+        "the directory of '{name}' is not a git clone. Uninstall the repository and install it \
+         again"
+    ))]
+    Broken { name: String },
+
+    #[non_exhaustive]
+    #[snafu(display(
+        "the clone of '{name}' has local modifications. Discard them with `paxy repository \
+         update --force` first"
+    ))]
+    LocalModifications { name: String },
+
+    #[non_exhaustive]
+    #[snafu(display("git failed on '{name}': {source}"))]
+    Git { name: String, source: git2::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("in the repository registry: {source}"))]
+    Registry { source: registry::Error },
 }
 
 // endregion: ERRORS
 
 // region: IMPORTS
 
-#[allow(unused)]
-use snafu::{ResultExt, Snafu};
+use std::path::Path;
 
-use crate::app::ui::console_template::cli::RepositoryDowngradeArguments;
+use git2::{build::CheckoutBuilder, Oid, Repository, Sort};
+use owo_colors::OwoColorize;
+use snafu::{ensure, OptionExt, ResultExt, Snafu};
+
+use super::list::{self, CloneState};
+use crate::{
+    app::ui::{self, console_template::cli::RepositoryDowngradeArguments},
+    data::{
+        self,
+        registry::{self, Registry, RepositoryKind, RepositoryPin},
+        repository::LocalRepository,
+        search_index::SearchIndex,
+    },
+};
 
 // endregion: IMPORTS
+
+// region: TESTS
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::action::repository::{
+        install::{install_repository, tests::source_repository},
+        update::update_repositories,
+    };
+
+    #[test]
+    fn pin_at_a_tag_until_unpinned() {
+        let paxy_home = tempfile::tempdir().unwrap();
+        let sources = tempfile::tempdir().unwrap();
+        let source_dirpath = sources
+            .path()
+            .join("main");
+        let manifest = |version: &str| {
+            format!("format_version = 1\nname = \"hello\"\nversion = \"{version}\"\n")
+        };
+        let url = source_repository(
+            &source_dirpath,
+            &[("hello/1.0.0/manifest.toml", &manifest("1.0.0"))],
+        );
+        let source = Repository::open(&source_dirpath).unwrap();
+        source
+            .tag_lightweight(
+                "v1",
+                &source
+                    .revparse_single("HEAD")
+                    .unwrap(),
+                false,
+            )
+            .unwrap();
+        source_repository(
+            &source_dirpath,
+            &[("hello/2.0.0/manifest.toml", &manifest("2.0.0"))],
+        );
+        let local_repository = install_repository(paxy_home.path(), "main", &url).unwrap();
+        let has_version = |version: &str| {
+            local_repository
+                .dirpath
+                .join("hello")
+                .join(version)
+                .exists()
+        };
+
+        pin_repository(
+            paxy_home.path(),
+            "main",
+            &PinTarget::Reference("v1".to_string()),
+        )
+        .unwrap();
+        assert!(!has_version("2.0.0"));
+
+        source_repository(
+            &source_dirpath,
+            &[("hello/3.0.0/manifest.toml", &manifest("3.0.0"))],
+        );
+        let report = update_repositories(paxy_home.path(), &[], &[], false).unwrap();
+        assert!(report
+            .updates
+            .is_empty());
+        assert_eq!(report.pinned, ["main"]);
+        assert!(!has_version("2.0.0"));
+
+        let pin = unpin_repository(paxy_home.path(), "main").unwrap();
+        assert_eq!(pin.reference, "v1");
+        assert!(has_version("2.0.0"));
+        update_repositories(paxy_home.path(), &[], &[], false).unwrap();
+        assert!(has_version("3.0.0"));
+
+        pin_repository(
+            paxy_home.path(),
+            "main",
+            &PinTarget::Date {
+                text: "2999-01-01".to_string(),
+                start_of_day: ui::parse_date("2999-01-01").unwrap(),
+            },
+        )
+        .unwrap();
+        assert!(has_version("3.0.0"));
+        assert!(matches!(
+            pin_repository(
+                paxy_home.path(),
+                "main",
+                &PinTarget::Date {
+                    text: "1970-01-02".to_string(),
+                    start_of_day: ui::parse_date("1970-01-02").unwrap(),
+                },
+            ),
+            Err(Error::NoCommitBeforeDate { .. })
+        ));
+        assert!(matches!(
+            pin_repository(
+                paxy_home.path(),
+                "main",
+                &PinTarget::Reference("no-such-tag".to_string()),
+            ),
+            Err(Error::UnknownReference { .. })
+        ));
+    }
+}
+
+// endregion: TESTS
@@ -1,48 +1,250 @@
-#[allow(unused)]
+//! Installs a repository by cloning it from its URL into
+//! `~/.paxy/repos/<name>` and recording it in the repository registry. An
+//! HTTP(S) URL that serves a package index is downloaded from without git
+//! instead, and a local directory is read in place, without copying it. The
+//! registry entry is only kept if the clone succeeds and looks like a package
+//! repository.
+
 pub fn handle_repository_install_action(
     repository_install_arguments: RepositoryInstallArguments,
 ) -> Result<(), Error> {
-    use crate::app::ui::console_template::cli::*;
+    let paxy_home = data::paxy_home();
+    let local_repository = install_repository(
+        &paxy_home,
+        &repository_install_arguments.repository_name,
+        &repository_install_arguments.url,
+    )?;
 
-    todo!();
+    tracing::info!(
+        "{} Added repository {} from {}",
+        console::Emoji("📚", ""),
+        local_repository
+            .name
+            .green(),
+        repository_install_arguments.url
+    );
+    tracing::info!(
+        target: "PLAIN",
+        "{} {}",
+        local_repository.name,
+        repository_install_arguments.url
+    );
 
-    // Ok(())
+    Ok(())
 }
 
-#[allow(unused)]
-fn add_repo(repo: &str, name: &str) {
-    let mut file = repository::home!();
-    file.push(".paxy");
-    repository::ensure_path(None);
-    file.push("repos.bson");
-    let mut doc = if !file.is_file() {
-        warn!("file not found. Creating");
-        let doc = doc! {"paxy-official": "https://github.com/Pax-Hub/paxy-pkg-repository.git"};
-        let mut buf = vec![];
-        doc.to_writer(&mut buf)
-            .unwrap();
-        write(file.clone(), buf).unwrap();
-        doc
-    } else {
-        info!("Reading from pre-exisiting file");
-        Document::from_reader(File::open(file.clone()).unwrap()).unwrap()
+/// The URL schemes that git can clone from.
+const SUPPORTED_SCHEMES: &[&str] = &["https", "http", "ssh", "git", "file"];
+
+/// Clones the repository at the given URL under the given name, or downloads
+/// it if the URL serves a package index over HTTP(S), and records it in the
+/// registry. If the clone fails, or what was cloned is not a package
+/// repository, both the clone and the registry entry are removed again.
+///
+/// A plain path, or a `file://` URL to a directory that is not a git
+/// repository, is registered as a local repository instead, read in place.
+pub fn install_repository(
+    paxy_home: &Path,
+    repository_name: &str,
+    url: &str,
+) -> Result<LocalRepository, Error> {
+    ensure!(
+        is_valid_name(repository_name),
+        InvalidNameSnafu {
+            name: repository_name
+        }
+    );
+    let (parsed_url, kind) = resolve_source(url)?;
+
+    let registry_filepath = Registry::filepath(paxy_home);
+    let mut registry = Registry::load(&registry_filepath).context(RegistrySnafu {})?;
+    ensure!(
+        registry
+            .get(repository_name)
+            .is_none(),
+        AlreadyInstalledSnafu {
+            name: repository_name
+        }
+    );
+    ensure!(
+        !data::repositories_dirpath(paxy_home)
+            .join(repository_name)
+            .exists(),
+        DirectoryExistsSnafu {
+            path: data::repositories_dirpath(paxy_home).join(repository_name)
+        }
+    );
+
+    let now = installed::now();
+    let registered_repository = RegisteredRepository {
+        url: match kind {
+            RepositoryKind::Local => parsed_url.to_string(),
+            RepositoryKind::Git | RepositoryKind::Http => url.to_string(),
+        },
+        kind,
+        priority: 0,
+        mirrors: Vec::new(),
+        mirror_order: MirrorOrder::default(),
+        added_at: now,
+        synced_at: Some(now),
+        pin: None,
     };
-    doc.insert(name, repo);
-    let mut buf = vec![];
-    doc.to_writer(&mut buf)
-        .unwrap();
-    write(file.clone(), buf).unwrap();
-    file.pop();
-    file.push("repos");
-    file.push(name);
-    repository::ensure_path(Some(&file));
-    Repository::clone(repo, file).unwrap();
+    let dirpath = registered_repository.dirpath(paxy_home, repository_name);
+    registry.insert(repository_name, registered_repository);
+    registry
+        .save(&registry_filepath)
+        .context(RegistrySnafu {})?;
+
+    let result = fetch_and_check(repository_name, &parsed_url, kind, &dirpath);
+    if result.is_err() {
+        roll_back(
+            &mut registry,
+            &registry_filepath,
+            repository_name,
+            (kind != RepositoryKind::Local).then_some(dirpath.as_path()),
+        );
+    }
+    let local_repository = result?;
+
+    if let Err(error) = SearchIndex::rebuild(paxy_home, &local_repository) {
+        tracing::warn!("Could not build the search index of {repository_name}: {error}");
+    }
+
+    Ok(local_repository)
+}
+
+/// Works out how to fetch the repository at the given URL or path. Returns
+/// the URL, which for a local repository is the `file://` URL of its
+/// canonical path.
+fn resolve_source(url: &str) -> Result<(Url, RepositoryKind), Error> {
+    let parsed_url = match Url::parse(url) {
+        // A single letter is the drive of a Windows path, not a scheme
+        Ok(parsed_url)
+            if parsed_url
+                .scheme()
+                .len()
+                > 1 =>
+        {
+            parsed_url
+        }
+        result => {
+            return match (directory_url(Path::new(url)), result) {
+                (Some(directory_url), _) => Ok((directory_url, RepositoryKind::Local)),
+                (None, Err(source)) => Err(source).context(InvalidUrlSnafu { url }),
+                (None, Ok(_)) => NotADirectorySnafu { url }.fail(),
+            };
+        }
+    };
+    ensure!(
+        SUPPORTED_SCHEMES.contains(&parsed_url.scheme()),
+        UnsupportedSchemeSnafu {
+            url,
+            scheme: parsed_url.scheme()
+        }
+    );
+
+    let kind = match parsed_url.scheme() {
+        "http" | "https" if http::probe(&parsed_url) => RepositoryKind::Http,
+        "file" => match parsed_url
+            .to_file_path()
+            .ok()
+            .filter(|dirpath| Repository::open(dirpath).is_err())
+            .and_then(|dirpath| directory_url(&dirpath))
+        {
+            Some(directory_url) => return Ok((directory_url, RepositoryKind::Local)),
+            None => RepositoryKind::Git,
+        },
+        _ => RepositoryKind::Git,
+    };
+
+    Ok((parsed_url, kind))
+}
+
+/// Returns the `file://` URL of the canonical path of the given directory, if
+/// it exists.
+fn directory_url(dirpath: &Path) -> Option<Url> {
+    fs::canonicalize(dirpath)
+        .ok()
+        .filter(|dirpath| dirpath.is_dir())
+        .and_then(|dirpath| Url::from_directory_path(dirpath).ok())
 }
 
-#[allow(dead_code)]
-#[allow(unused_variables)]
-fn plugin(manifest: PathBuf) -> PathBuf {
-    todo!()
+fn fetch_and_check(
+    repository_name: &str,
+    parsed_url: &Url,
+    kind: RepositoryKind,
+    dirpath: &Path,
+) -> Result<LocalRepository, Error> {
+    let url = parsed_url.as_str();
+    if kind != RepositoryKind::Local {
+        if let Some(parent) = dirpath.parent() {
+            fs::create_dir_all(parent).context(CreateDirectorySnafu { path: parent })?;
+        }
+    }
+    match kind {
+        RepositoryKind::Git => {
+            Repository::clone(url, dirpath).context(CloneSnafu { url })?;
+        }
+        RepositoryKind::Http => {
+            fs::create_dir_all(dirpath).context(CreateDirectorySnafu { path: dirpath })?;
+            http::sync(parsed_url, dirpath).context(DownloadSnafu { url })?;
+        }
+        RepositoryKind::Local => {}
+    }
+
+    let manifest_entries = repository::manifest_entries(dirpath).context(RepositorySnafu {})?;
+    ensure!(
+        !manifest_entries.is_empty(),
+        InvalidLayoutSnafu {
+            name: repository_name,
+            url
+        }
+    );
+
+    if layout::RepositoryMetadata::load(dirpath)
+        .context(InvalidMetadataSnafu { url })?
+        .is_none()
+    {
+        tracing::warn!(
+            "{url} has no {}, so it may not be meant as a paxy repository",
+            layout::METADATA_FILE_NAME
+        );
+    }
+
+    Ok(LocalRepository {
+        name: repository_name.to_string(),
+        dirpath: dirpath.to_path_buf(),
+        kind,
+    })
+}
+
+/// Undoes a failed installation, removing the partial clone if there is one.
+/// Failures here are only warned about, so that they do not hide the error
+/// that caused the roll back.
+fn roll_back(
+    registry: &mut Registry,
+    registry_filepath: &Path,
+    repository_name: &str,
+    dirpath: Option<&Path>,
+) {
+    registry.remove(repository_name);
+    if let Err(error) = registry.save(registry_filepath) {
+        tracing::warn!("Could not remove {repository_name} from the registry again: {error}");
+    }
+    if let Some(dirpath) = dirpath.filter(|dirpath| dirpath.exists()) {
+        if let Err(error) = fs::remove_dir_all(dirpath) {
+            tracing::warn!("Could not remove the partial clone at {dirpath:?}: {error}");
+        }
+    }
+}
+
+/// Repository names become directory names, so only plain names are allowed.
+fn is_valid_name(repository_name: &str) -> bool {
+    !repository_name.is_empty()
+        && !repository_name.starts_with('.')
+        && repository_name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
 }
 
 // region: ERRORS
@@ -52,8 +254,68 @@ fn plugin(manifest: PathBuf) -> PathBuf {
 #[non_exhaustive]
 pub enum Error {
     #[non_exhaustive]
-    #[snafu(display(""))]
-    Dummy {},
+    #[snafu(display(
+        "'{name}' is not a valid repository name. Use letters, digits, '-', '_' and '.', not \
+         starting with '.'"
+    ))]
+    InvalidName { name: String },
+
+    #[non_exhaustive]
+    #[snafu(display("'{url}' is neither a valid URL nor an existing directory: {source}"))]
+    InvalidUrl {
+        url: String,
+        source: url::ParseError,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("'{url}' is not an existing directory"))]
+    NotADirectory { url: String },
+
+    #[non_exhaustive]
+    #[snafu(display("cannot clone '{url}': the '{scheme}' scheme is not supported"))]
+    UnsupportedScheme { url: String, scheme: String },
+
+    #[non_exhaustive]
+    #[snafu(display("a repository named '{name}' is already installed"))]
+    AlreadyInstalled { name: String },
+
+    #[non_exhaustive]
+    #[snafu(display("{path:?} already exists. Remove it or choose another name"))]
+    DirectoryExists { path: PathBuf },
+
+    #[non_exhaustive]
+    #[snafu(display("could not create {path:?}: {source}"))]
+    CreateDirectory {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("could not clone '{url}': {source}"))]
+    Clone { url: String, source: git2::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("could not download '{url}': {source}"))]
+    Download { url: String, source: http::Error },
+
+    #[non_exhaustive]
+    #[snafu(display(
+        "'{url}' does not look like a package repository: it has no \
+         '<package>/<version>/manifest.toml'. '{name}' was not added"
+    ))]
+    InvalidLayout { name: String, url: String },
+
+    #[non_exhaustive]
+    #[snafu(display("the description of '{url}' is invalid: {source}"))]
+    InvalidMetadata { url: String, source: layout::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("in the repository registry: {source}"))]
+    Registry { source: registry::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("in the cloned repository: {source}"))]
+    Repository { source: repository::Error },
 }
 
 // endregion: ERRORS
@@ -61,38 +323,189 @@ pub enum Error {
 // region: IMPORTS
 
 use std::{
-    fs::{write, File},
-    path::PathBuf,
+    fs,
+    path::{Path, PathBuf},
 };
 
-use bson::{doc, Document};
 use git2::Repository;
-use log::{info, warn};
-#[allow(unused)]
-use snafu::{ResultExt, Snafu};
+use owo_colors::OwoColorize;
+use snafu::{ensure, ResultExt, Snafu};
+use url::Url;
 
-use crate::action::repository;
-use crate::app::ui::console_template::cli::RepositoryInstallArguments;
+use crate::{
+    app::ui::console_template::cli::RepositoryInstallArguments,
+    data::{
+        self,
+        installed,
+        mirrors::MirrorOrder,
+        registry::{self, RegisteredRepository, Registry, RepositoryKind},
+        repository::{self, http, layout, LocalRepository},
+        search_index::SearchIndex,
+    },
+};
 
 // endregion: IMPORTS
 
 // region: TESTS
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
+    use crate::data::repository::tests::{commit_all, write_manifest};
+
+    /// Commits the given files to the git repository at the given path,
+    /// creating it if needed, and returns its URL.
+    pub(crate) fn source_repository(dirpath: &Path, files: &[(&str, &str)]) -> String {
+        let git_repository = Repository::open(dirpath)
+            .or_else(|_| Repository::init(dirpath))
+            .unwrap();
+        for (relative_path, contents) in files {
+            let filepath = dirpath.join(relative_path);
+            fs::create_dir_all(
+                filepath
+                    .parent()
+                    .unwrap(),
+            )
+            .unwrap();
+            fs::write(filepath, contents).unwrap();
+        }
+        commit_all(&git_repository, &format!("Change {} file(s)", files.len()));
+
+        Url::from_directory_path(dirpath)
+            .unwrap()
+            .to_string()
+    }
 
     #[test]
-    fn add_repo_norm_test() {
-        add_repo("https://github.com/Pax-Hub/paxy-pkg-repository.git", "paxy");
-        let mut file = home!();
-        file.push(".paxy");
-        file.push("repos.bson");
-        let doc = Document::from_reader(File::open(file.clone()).unwrap()).unwrap();
+    fn install_a_repository_and_roll_back_an_invalid_one() {
+        let paxy_home = tempfile::tempdir().unwrap();
+        let sources = tempfile::tempdir().unwrap();
+        let valid_url = source_repository(
+            &sources
+                .path()
+                .join("valid"),
+            &[(
+                "hello/1.0.0/manifest.toml",
+                "format_version = 1\nname = \"hello\"\nversion = \"1.0.0\"\n",
+            )],
+        );
+        let invalid_url = source_repository(
+            &sources
+                .path()
+                .join("invalid"),
+            &[("README.md", "Not a package repository\n")],
+        );
+
+        let local_repository = install_repository(paxy_home.path(), "main", &valid_url).unwrap();
+        assert!(local_repository
+            .dirpath
+            .join("hello/1.0.0/manifest.toml")
+            .is_file());
+        assert!(matches!(
+            install_repository(paxy_home.path(), "main", &valid_url),
+            Err(Error::AlreadyInstalled { .. })
+        ));
+
+        assert!(matches!(
+            install_repository(paxy_home.path(), "other", &invalid_url),
+            Err(Error::InvalidLayout { .. })
+        ));
+        assert!(!data::repositories_dirpath(paxy_home.path())
+            .join("other")
+            .exists());
+        let registry = Registry::load(&Registry::filepath(paxy_home.path())).unwrap();
         assert_eq!(
-            doc,
-            doc! {"paxy-official": "https://github.com/Pax-Hub/paxy-pkg-repository.git", "paxy": "https://github.com/Pax-Hub/paxy-pkg-repository.git"}
+            registry
+                .repositories
+                .keys()
+                .collect::<Vec<_>>(),
+            ["main"]
         );
+
+        assert!(matches!(
+            install_repository(paxy_home.path(), "../escape", &valid_url),
+            Err(Error::InvalidName { .. })
+        ));
+        assert!(matches!(
+            install_repository(paxy_home.path(), "bad", "not a url"),
+            Err(Error::InvalidUrl { .. })
+        ));
+        assert!(matches!(
+            install_repository(paxy_home.path(), "bad", "ftp://example.com/repo"),
+            Err(Error::UnsupportedScheme { .. })
+        ));
+    }
+
+    #[test]
+    fn read_a_local_directory_in_place() {
+        let paxy_home = tempfile::tempdir().unwrap();
+        let source = tempfile::tempdir().unwrap();
+        write_manifest(source.path(), "hello", "1.0.0", "");
+
+        // Plain paths and file:// URLs of directories that are not git
+        // repositories are both read in place
+        let url = Url::from_directory_path(source.path()).unwrap();
+        for (name, url) in [
+            (
+                "by-path",
+                source
+                    .path()
+                    .to_string_lossy()
+                    .into_owned(),
+            ),
+            ("by-url", url.to_string()),
+        ] {
+            let local_repository = install_repository(paxy_home.path(), name, &url).unwrap();
+            assert_eq!(
+                local_repository.dirpath,
+                fs::canonicalize(source.path()).unwrap()
+            );
+        }
+        assert!(!data::repositories_dirpath(paxy_home.path()).exists());
+        let registry = Registry::load(&Registry::filepath(paxy_home.path())).unwrap();
+        assert_eq!(
+            registry
+                .get("by-path")
+                .unwrap()
+                .kind,
+            RepositoryKind::Local
+        );
+
+        // Changes to the directory are seen without updating
+        write_manifest(source.path(), "hello", "1.1.0", "");
+        let local_repositories = repository::local_repositories(paxy_home.path()).unwrap();
+        assert_eq!(local_repositories.len(), 2);
+        assert_eq!(
+            repository::manifest_entries(&local_repositories[0].dirpath)
+                .unwrap()
+                .len(),
+            2
+        );
+
+        assert!(matches!(
+            install_repository(
+                paxy_home.path(),
+                "missing",
+                &source
+                    .path()
+                    .join("missing")
+                    .to_string_lossy()
+            ),
+            Err(Error::InvalidUrl { .. })
+        ));
+        fs::remove_dir_all(
+            source
+                .path()
+                .join("hello"),
+        )
+        .unwrap();
+        assert!(matches!(
+            install_repository(paxy_home.path(), "empty", url.as_str()),
+            Err(Error::InvalidLayout { .. })
+        ));
+        assert!(source
+            .path()
+            .is_dir());
     }
 }
 
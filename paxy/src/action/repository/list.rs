@@ -1,12 +1,236 @@
-#[allow(unused)]
+//! Lists the installed repositories from the repository registry, along with
+//! the state of their clones under `~/.paxy/repos`, or of the directories of
+//! local repositories.
+
 pub fn handle_repository_list_action(
     repository_list_arguments: RepositoryListArguments,
 ) -> Result<(), Error> {
-    use crate::app::ui::console_template::cli::*;
+    let repositories = list_repositories(
+        &data::paxy_home(),
+        &repository_list_arguments.partial_repository_name,
+        &repository_list_arguments.excluded_partial_repository_names,
+    )?;
+
+    if repositories.is_empty() {
+        tracing::info!(
+            "{} No matching repositories are installed.",
+            console::Emoji("📭", "")
+        );
+    }
+    for repository in repositories.iter() {
+        let mut details = vec![
+            match (
+                repository.kind,
+                repository
+                    .commit
+                    .as_deref(),
+            ) {
+                (RepositoryKind::Local, _) => "local".to_string(),
+                (_, Some(commit)) => commit[..commit.len().min(12)].to_string(),
+                (_, None) => "no commit".to_string(),
+            },
+            format!("{} packages", repository.package_count),
+        ];
+        if repository.kind != RepositoryKind::Local {
+            details.push(match repository.synced_at {
+                Some(synced_at) => format!("synced {}", ui::format_unix_timestamp(synced_at)),
+                None => "never synced".to_string(),
+            });
+        }
+        if repository.priority != 0 {
+            details.push(format!("priority {}", repository.priority));
+        }
+        if let Some(pinned_at) = &repository.pinned_at {
+            details.push(format!("pinned at {pinned_at}"));
+        }
+        if repository.state != CloneState::Clean {
+            details.push(
+                repository
+                    .state
+                    .to_string(),
+            );
+        }
+        tracing::info!(
+            "{} {} {} {}",
+            console::Emoji("📚", ""),
+            repository
+                .name
+                .green(),
+            repository.url,
+            format!("[{}]", details.join(", ")).dimmed(),
+        );
+        tracing::info!(
+            target: "PLAIN",
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            repository.name,
+            repository.url,
+            repository
+                .commit
+                .as_deref()
+                .unwrap_or("-"),
+            repository
+                .synced_at
+                .map(|synced_at| synced_at.to_string())
+                .unwrap_or_else(|| "-".to_string()),
+            repository.package_count,
+            repository.state
+        );
+    }
+    tracing::info!(
+        target: "JSON",
+        "{}",
+        serde_json::to_string(&repositories).context(SerializeJsonSnafu {})?
+    );
+
+    Ok(())
+}
+
+/// An installed repository and the state of its clone.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RepositoryStatus {
+    pub name: String,
+    pub url: String,
+    pub kind: RepositoryKind,
+    pub priority: i32,
+    /// The commit checked out in the clone, if there is one.
+    pub commit: Option<String>,
+    /// Seconds since the Unix epoch at which the clone was last fetched.
+    pub synced_at: Option<u64>,
+    pub package_count: usize,
+    pub state: CloneState,
+    /// The tag, commit or date the repository is pinned at, if any.
+    pub pinned_at: Option<String>,
+}
+
+/// How the clone of a repository compares to what was fetched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CloneState {
+    /// The clone matches its checked-out commit.
+    Clean,
+    /// Files in the clone were changed, added or removed locally.
+    Dirty,
+    /// There is no directory for the repository.
+    Missing,
+    /// The directory of the repository is not a git clone, or for a
+    /// repository downloaded over HTTP, its package index is unreadable.
+    Broken,
+}
+
+impl fmt::Display for CloneState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CloneState::Clean => "clean",
+            CloneState::Dirty => "dirty",
+            CloneState::Missing => "missing",
+            CloneState::Broken => "broken",
+        })
+    }
+}
+
+/// Returns the installed repositories whose names contain any of the given
+/// partial names (or all repositories, if none are given), leaving out those
+/// whose names contain any of the excluded partial names. They are sorted by
+/// name.
+pub fn list_repositories(
+    paxy_home: &Path,
+    partial_repository_names: &[String],
+    excluded_partial_repository_names: &[String],
+) -> Result<Vec<RepositoryStatus>, Error> {
+    let registry = Registry::load(&Registry::filepath(paxy_home)).context(RegistrySnafu {})?;
+    let matches_any = |name: &str, partial_names: &[String]| {
+        let name = name.to_lowercase();
+        partial_names
+            .iter()
+            .any(|partial_name| name.contains(&partial_name.to_lowercase()))
+    };
+
+    registry
+        .repositories
+        .iter()
+        .filter(|(name, _)| {
+            partial_repository_names.is_empty() || matches_any(name, partial_repository_names)
+        })
+        .filter(|(name, _)| !matches_any(name, excluded_partial_repository_names))
+        .map(|(name, registered_repository)| {
+            let dirpath = registered_repository.dirpath(paxy_home, name);
+            let (commit, state) = clone_state(&dirpath, registered_repository.kind);
+            let package_count = if state == CloneState::Missing {
+                0
+            } else {
+                repository::manifest_entries(&dirpath)
+                    .context(RepositorySnafu {})?
+                    .into_iter()
+                    .map(|entry| entry.name)
+                    .collect::<BTreeSet<_>>()
+                    .len()
+            };
+
+            Ok(RepositoryStatus {
+                name: name.clone(),
+                url: registered_repository
+                    .url
+                    .clone(),
+                kind: registered_repository.kind,
+                priority: registered_repository.priority,
+                commit,
+                synced_at: registered_repository.synced_at,
+                package_count,
+                state,
+                pinned_at: registered_repository
+                    .pin
+                    .as_ref()
+                    .map(|pin| {
+                        pin.reference
+                            .clone()
+                    }),
+            })
+        })
+        .collect()
+}
+
+/// Returns the commit checked out in the clone at the given path, and whether
+/// the clone has local modifications. Ignored files do not count as
+/// modifications. For a repository downloaded over HTTP, the revision of its
+/// package index stands in for the commit. A local repository has neither,
+/// and is clean as long as its directory exists.
+pub fn clone_state(dirpath: &Path, kind: RepositoryKind) -> (Option<String>, CloneState) {
+    if !dirpath.is_dir() {
+        return (None, CloneState::Missing);
+    }
+    match kind {
+        RepositoryKind::Git => {}
+        RepositoryKind::Http => {
+            let state = match http::is_modified(dirpath) {
+                Ok(false) => CloneState::Clean,
+                Ok(true) => CloneState::Dirty,
+                Err(_) => CloneState::Broken,
+            };
+            return (http::revision(dirpath), state);
+        }
+        RepositoryKind::Local => return (None, CloneState::Clean),
+    }
+    let Ok(git_repository) = Repository::open(dirpath) else {
+        return (None, CloneState::Broken);
+    };
 
-    todo!();
+    let commit = git_repository
+        .head()
+        .ok()
+        .and_then(|head| head.target())
+        .map(|oid| oid.to_string());
+    let mut status_options = StatusOptions::new();
+    status_options
+        .include_untracked(true)
+        .recurse_untracked_dirs(true)
+        .include_ignored(false);
+    let state = match git_repository.statuses(Some(&mut status_options)) {
+        Ok(statuses) if statuses.is_empty() => CloneState::Clean,
+        Ok(_) => CloneState::Dirty,
+        Err(_) => CloneState::Broken,
+    };
 
-    // Ok(())
+    (commit, state)
 }
 
 // region: ERRORS
@@ -16,17 +240,133 @@ pub fn handle_repository_list_action(
 #[non_exhaustive]
 pub enum Error {
     #[non_exhaustive]
-    #[snafu(display(""))]
-    Dummy {},
+    #[snafu(display("in the repository registry: {source}"))]
+    Registry { source: registry::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("in a repository: {source}"))]
+    Repository { source: repository::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("could not serialize the repository list to JSON: {source}"))]
+    SerializeJson { source: serde_json::Error },
 }
 
 // endregion: ERRORS
 
 // region: IMPORTS
 
-#[allow(unused)]
+use std::{collections::BTreeSet, fmt, path::Path};
+
+use git2::{Repository, StatusOptions};
+use owo_colors::OwoColorize;
+use serde::Serialize;
 use snafu::{ResultExt, Snafu};
 
-use crate::app::ui::console_template::cli::RepositoryListArguments;
+use crate::{
+    app::ui::{self, console_template::cli::RepositoryListArguments},
+    data::{
+        self,
+        registry::{self, Registry, RepositoryKind},
+        repository::{self, http},
+    },
+};
 
 // endregion: IMPORTS
+
+// region: TESTS
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::{
+        action::repository::install::{install_repository, tests::source_repository},
+        data::{mirrors::MirrorOrder, registry::RegisteredRepository},
+    };
+
+    #[test]
+    fn list_clean_dirty_and_missing_repositories() {
+        let paxy_home = tempfile::tempdir().unwrap();
+        let sources = tempfile::tempdir().unwrap();
+        let url = source_repository(
+            &sources
+                .path()
+                .join("main"),
+            &[
+                (
+                    "hello/1.0.0/manifest.toml",
+                    "format_version = 1\nname = \"hello\"\nversion = \"1.0.0\"\n",
+                ),
+                (
+                    "hello/1.1.0/manifest.toml",
+                    "format_version = 1\nname = \"hello\"\nversion = \"1.1.0\"\n",
+                ),
+                (
+                    "world/0.1.0/manifest.toml",
+                    "format_version = 1\nname = \"world\"\nversion = \"0.1.0\"\n",
+                ),
+            ],
+        );
+        install_repository(paxy_home.path(), "main", &url).unwrap();
+        let local_repository = install_repository(paxy_home.path(), "main-fork", &url).unwrap();
+        fs::write(
+            local_repository
+                .dirpath
+                .join("hello/1.0.0/manifest.toml"),
+            "format_version = 1\nname = \"hello\"\nversion = \"1.0.1\"\n",
+        )
+        .unwrap();
+        let registry_filepath = Registry::filepath(paxy_home.path());
+        let mut registry = Registry::load(&registry_filepath).unwrap();
+        // Repositories named in the configuration may use capitals
+        registry.insert(
+            "Gone",
+            RegisteredRepository {
+                url: "https://example.com/gone.git".to_string(),
+                kind: RepositoryKind::Git,
+                priority: 0,
+                mirrors: Vec::new(),
+                mirror_order: MirrorOrder::default(),
+                added_at: 0,
+                synced_at: None,
+                pin: None,
+            },
+        );
+        registry
+            .save(&registry_filepath)
+            .unwrap();
+
+        let repositories = list_repositories(paxy_home.path(), &[], &["fork".to_string()]).unwrap();
+        assert_eq!(
+            repositories
+                .iter()
+                .map(|repository| (
+                    repository
+                        .name
+                        .as_str(),
+                    repository.package_count,
+                    repository.state
+                ))
+                .collect::<Vec<_>>(),
+            [
+                ("Gone", 0, CloneState::Missing),
+                ("main", 2, CloneState::Clean)
+            ]
+        );
+        assert!(repositories[1]
+            .commit
+            .is_some());
+
+        let repositories = list_repositories(paxy_home.path(), &["FORK".to_string()], &[]).unwrap();
+        assert_eq!(repositories.len(), 1);
+        assert_eq!(repositories[0].state, CloneState::Dirty);
+
+        let repositories = list_repositories(paxy_home.path(), &["gone".to_string()], &[]).unwrap();
+        assert_eq!(repositories.len(), 1);
+        assert_eq!(repositories[0].name, "Gone");
+    }
+}
+
+// endregion: TESTS
@@ -0,0 +1,285 @@
+//! Lists and changes the mirrors of an installed repository: further URLs
+//! serving the same repository, which updates fall back on when its URL
+//! fails. Each URL is listed along with how it fared when last used.
+
+pub fn handle_repository_mirrors_action(
+    repository_mirrors_arguments: RepositoryMirrorsArguments,
+) -> Result<(), Error> {
+    let paxy_home = data::paxy_home();
+    let mirrors = change_mirrors(
+        &paxy_home,
+        &repository_mirrors_arguments.repository_name,
+        &repository_mirrors_arguments.added_mirror_urls,
+        &repository_mirrors_arguments.removed_mirror_urls,
+        repository_mirrors_arguments.mirror_order,
+    )?;
+
+    for url in repository_mirrors_arguments
+        .added_mirror_urls
+        .iter()
+    {
+        tracing::info!(
+            "{} Added the mirror {}",
+            console::Emoji("➕", ""),
+            url.green()
+        );
+    }
+    for url in repository_mirrors_arguments
+        .removed_mirror_urls
+        .iter()
+    {
+        tracing::info!(
+            "{} Removed the mirror {}",
+            console::Emoji("➖", ""),
+            url.red()
+        );
+    }
+    tracing::info!(
+        "{} The mirrors of {} are tried {}:",
+        console::Emoji("🪞", ""),
+        repository_mirrors_arguments
+            .repository_name
+            .green(),
+        match mirrors.mirror_order {
+            MirrorOrder::Listed => "in the order listed",
+            MirrorOrder::Fastest => "fastest first",
+        }
+    );
+    let now = installed::now();
+    for mirror in mirrors
+        .mirrors
+        .iter()
+    {
+        let mut details = Vec::new();
+        if mirror
+            .health
+            .is_dead(now)
+        {
+            details.push("dead".to_string());
+        }
+        if mirror
+            .health
+            .failures
+            > 0
+        {
+            details.push(format!(
+                "{} {} in a row",
+                mirror
+                    .health
+                    .failures,
+                if mirror
+                    .health
+                    .failures
+                    == 1
+                {
+                    "failure"
+                } else {
+                    "failures"
+                }
+            ));
+        }
+        match mirror
+            .health
+            .last_success_at
+        {
+            Some(last_success_at) => details.push(format!(
+                "last worked {}",
+                ui::format_unix_timestamp(last_success_at)
+            )),
+            None if mirror
+                .health
+                .failures
+                == 0 =>
+            {
+                details.push("never used".to_string())
+            }
+            None => {}
+        }
+        tracing::info!(
+            "  {} {}",
+            mirror.url,
+            format!("[{}]", details.join(", ")).dimmed()
+        );
+        tracing::info!(
+            target: "PLAIN",
+            "{}\t{}\t{}",
+            mirror.url,
+            mirror
+                .health
+                .failures,
+            mirror
+                .health
+                .is_dead(now)
+        );
+    }
+    tracing::info!(
+        target: "JSON",
+        "{}",
+        serde_json::to_string(&mirrors).context(SerializeJsonSnafu {})?
+    );
+
+    Ok(())
+}
+
+/// The URLs a repository is fetched from, in the order they are listed, with
+/// the order they are tried in.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RepositoryMirrors {
+    pub mirror_order: MirrorOrder,
+    /// The URL of the repository followed by its mirrors.
+    pub mirrors: Vec<MirrorStatus>,
+}
+
+/// A URL a repository is fetched from, and how it fared when last used.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MirrorStatus {
+    pub url: String,
+    pub health: MirrorHealth,
+}
+
+/// Adds and removes mirrors of the named repository, and sets the order they
+/// are tried in if given, then returns its mirrors. Adding a mirror already
+/// listed does nothing.
+pub fn change_mirrors(
+    paxy_home: &Path,
+    repository_name: &str,
+    added_mirror_urls: &[String],
+    removed_mirror_urls: &[String],
+    mirror_order: Option<MirrorOrder>,
+) -> Result<RepositoryMirrors, Error> {
+    let registry_filepath = Registry::filepath(paxy_home);
+    let mut registry = Registry::load(&registry_filepath).context(RegistrySnafu {})?;
+    let registered_repository = registry
+        .repositories
+        .get_mut(repository_name)
+        .context(NotInstalledSnafu {
+            name: repository_name,
+        })?;
+
+    let is_changed =
+        !added_mirror_urls.is_empty() || !removed_mirror_urls.is_empty() || mirror_order.is_some();
+    ensure!(
+        !is_changed || registered_repository.kind != RepositoryKind::Local,
+        LocalSnafu {
+            name: repository_name
+        }
+    );
+    for url in removed_mirror_urls {
+        let position = registered_repository
+            .mirrors
+            .iter()
+            .position(|mirror| {
+                mirror == url
+                    || Url::parse(url).is_ok_and(|parsed_url| parsed_url.as_str() == mirror)
+            })
+            .context(NotAMirrorSnafu {
+                name: repository_name,
+                url,
+            })?;
+        registered_repository
+            .mirrors
+            .remove(position);
+    }
+    for url in added_mirror_urls {
+        let url = Url::parse(url)
+            .context(InvalidUrlSnafu { url })?
+            .to_string();
+        if url != registered_repository.url
+            && !registered_repository
+                .mirrors
+                .contains(&url)
+        {
+            registered_repository
+                .mirrors
+                .push(url);
+        }
+    }
+    if let Some(mirror_order) = mirror_order {
+        registered_repository.mirror_order = mirror_order;
+    }
+
+    let health_records = MirrorHealthRecords::load(&MirrorHealthRecords::filepath(paxy_home))
+        .context(HealthSnafu {})?;
+    let mirrors = RepositoryMirrors {
+        mirror_order: registered_repository.mirror_order,
+        mirrors: std::iter::once(&registered_repository.url)
+            .chain(&registered_repository.mirrors)
+            .map(|url| MirrorStatus {
+                url: url.clone(),
+                health: Url::parse(url)
+                    .map(|url| health_records.get(&url))
+                    .unwrap_or_default(),
+            })
+            .collect(),
+    };
+    if is_changed {
+        registry
+            .save(&registry_filepath)
+            .context(RegistrySnafu {})?;
+    }
+
+    Ok(mirrors)
+}
+
+// region: ERRORS
+
+#[derive(Debug, Snafu)]
+#[snafu(visibility(pub(crate)))]
+#[non_exhaustive]
+pub enum Error {
+    #[non_exhaustive]
+    #[snafu(display("the repository '{name}' is not installed"))]
+    NotInstalled { name: String },
+
+    #[non_exhaustive]
+    #[snafu(display(
+        "'{name}' is a local directory, read in place, so it is never fetched from a mirror"
+    ))]
+    Local { name: String },
+
+    #[non_exhaustive]
+    #[snafu(display("'{url}' is not a mirror of '{name}'"))]
+    NotAMirror { name: String, url: String },
+
+    #[non_exhaustive]
+    #[snafu(display("'{url}' is not a valid URL: {source}"))]
+    InvalidUrl {
+        url: String,
+        source: url::ParseError,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("in the mirror health records: {source}"))]
+    Health { source: mirrors::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("in the repository registry: {source}"))]
+    Registry { source: registry::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("could not serialize the mirrors to JSON: {source}"))]
+    SerializeJson { source: serde_json::Error },
+}
+
+// endregion: ERRORS
+
+// region: IMPORTS
+
+use std::path::Path;
+
+use owo_colors::OwoColorize;
+use serde::Serialize;
+use snafu::{ensure, OptionExt, ResultExt, Snafu};
+use url::Url;
+
+use crate::{
+    app::ui::{self, console_template::cli::RepositoryMirrorsArguments},
+    data::{
+        self,
+        installed,
+        mirrors::{self, MirrorHealth, MirrorHealthRecords, MirrorOrder},
+        registry::{self, Registry, RepositoryKind},
+    },
+};
+
+// endregion: IMPORTS
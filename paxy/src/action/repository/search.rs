@@ -1,12 +1,86 @@
-#[allow(unused)]
+//! Searches the index of known repositories for repositories to install, by
+//! name, description and tags. The index is fetched from the URL in the
+//! configuration and cached, so that searching works offline.
+
 pub fn handle_repository_search_action(
     repository_search_arguments: RepositorySearchArguments,
 ) -> Result<(), Error> {
-    use crate::app::ui::console_template::cli::*;
+    let paxy_home = data::paxy_home();
+    let url = match config::load_conf().repository_index_url {
+        Some(url) => url,
+        None => Url::parse(DEFAULT_REPOSITORY_INDEX_URL).context(InvalidDefaultUrlSnafu {})?,
+    };
+    let index = RepositoryIndex::load(&paxy_home, &url, repository_search_arguments.refresh)
+        .context(IndexSnafu {})?;
+    let registry = Registry::load(&Registry::filepath(&paxy_home)).context(RegistrySnafu {})?;
+
+    let excluded_partial_repository_names: Vec<String> = repository_search_arguments
+        .excluded_partial_repository_names
+        .iter()
+        .map(|partial_name| partial_name.to_lowercase())
+        .collect();
+    let matches: Vec<&KnownRepository> = index
+        .search(&repository_search_arguments.partial_repository_name)
+        .into_iter()
+        .filter(|repository| {
+            let name = repository
+                .name
+                .to_lowercase();
+            !excluded_partial_repository_names
+                .iter()
+                .any(|partial_name| name.contains(partial_name))
+        })
+        .collect();
 
-    todo!();
+    if matches.is_empty() {
+        tracing::info!(
+            "{} No matching repositories are known.",
+            console::Emoji("📭", "")
+        );
+    }
+    for repository in matches.iter() {
+        let is_installed = registry
+            .get(&repository.name)
+            .is_some();
+        tracing::info!(
+            "{} {} {} {}{}",
+            console::Emoji("📚", ""),
+            repository
+                .name
+                .green(),
+            repository
+                .url
+                .dimmed(),
+            repository
+                .description
+                .as_deref()
+                .unwrap_or_default(),
+            if is_installed { " (installed)" } else { "" }
+        );
+        tracing::info!(target: "PLAIN", "{} {}", repository.name, repository.url);
+    }
+    if let Some(repository) = matches
+        .iter()
+        .find(|repository| {
+            registry
+                .get(&repository.name)
+                .is_none()
+        })
+    {
+        tracing::info!(
+            "{} Add one with `paxy repository install {} {}`",
+            console::Emoji("💡", ""),
+            repository.name,
+            repository.url
+        );
+    }
+    tracing::info!(
+        target: "JSON",
+        "{}",
+        serde_json::to_string(&matches).context(SerializeJsonSnafu {})?
+    );
 
-    // Ok(())
+    Ok(())
 }
 
 // region: ERRORS
@@ -16,17 +90,38 @@ pub fn handle_repository_search_action(
 #[non_exhaustive]
 pub enum Error {
     #[non_exhaustive]
-    #[snafu(display(""))]
-    Dummy {},
+    #[snafu(display("the default URL of the index of repositories is invalid: {source}"))]
+    InvalidDefaultUrl { source: url::ParseError },
+
+    #[non_exhaustive]
+    #[snafu(display("in the index of repositories: {source}"))]
+    Index { source: repository_index::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("in the repository registry: {source}"))]
+    Registry { source: registry::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("could not serialize the search results to JSON: {source}"))]
+    SerializeJson { source: serde_json::Error },
 }
 
 // endregion: ERRORS
 
 // region: IMPORTS
 
-#[allow(unused)]
+use owo_colors::OwoColorize;
 use snafu::{ResultExt, Snafu};
+use url::Url;
 
-use crate::app::ui::console_template::cli::RepositorySearchArguments;
+use crate::{
+    app::ui::console_template::cli::RepositorySearchArguments,
+    data::{
+        self,
+        config,
+        registry::{self, Registry},
+        repository_index::{self, KnownRepository, RepositoryIndex, DEFAULT_REPOSITORY_INDEX_URL},
+    },
+};
 
 // endregion: IMPORTS
@@ -0,0 +1,85 @@
+//! Sets the priority of an installed repository in the repository registry.
+//! When several repositories offer a package, it is installed from the one
+//! with the highest priority. Packages already installed keep coming from
+//! the repository they were installed from.
+
+pub fn handle_repository_set_priority_action(
+    repository_set_priority_arguments: RepositorySetPriorityArguments,
+) -> Result<(), Error> {
+    let previous_priority = set_priority(
+        &data::paxy_home(),
+        &repository_set_priority_arguments.repository_name,
+        repository_set_priority_arguments.priority,
+    )?;
+
+    tracing::info!(
+        "{} Set the priority of {} to {} {}",
+        console::Emoji("🔢", ""),
+        repository_set_priority_arguments
+            .repository_name
+            .green(),
+        repository_set_priority_arguments.priority,
+        format!("(was {previous_priority})").dimmed()
+    );
+    tracing::info!(
+        target: "PLAIN",
+        "{} {}",
+        repository_set_priority_arguments.repository_name,
+        repository_set_priority_arguments.priority
+    );
+
+    Ok(())
+}
+
+/// Sets the priority of the named repository, returning its previous
+/// priority.
+pub fn set_priority(paxy_home: &Path, repository_name: &str, priority: i32) -> Result<i32, Error> {
+    let registry_filepath = Registry::filepath(paxy_home);
+    let mut registry = Registry::load(&registry_filepath).context(RegistrySnafu {})?;
+    let registered_repository = registry
+        .repositories
+        .get_mut(repository_name)
+        .context(NotInstalledSnafu {
+            name: repository_name,
+        })?;
+    let previous_priority = std::mem::replace(&mut registered_repository.priority, priority);
+    registry
+        .save(&registry_filepath)
+        .context(RegistrySnafu {})?;
+
+    Ok(previous_priority)
+}
+
+// region: ERRORS
+
+#[derive(Debug, Snafu)]
+#[snafu(visibility(pub(crate)))]
+#[non_exhaustive]
+pub enum Error {
+    #[non_exhaustive]
+    #[snafu(display("the repository '{name}' is not installed"))]
+    NotInstalled { name: String },
+
+    #[non_exhaustive]
+    #[snafu(display("in the repository registry: {source}"))]
+    Registry { source: registry::Error },
+}
+
+// endregion: ERRORS
+
+// region: IMPORTS
+
+use std::path::Path;
+
+use owo_colors::OwoColorize;
+use snafu::{OptionExt, ResultExt, Snafu};
+
+use crate::{
+    app::ui::console_template::cli::RepositorySetPriorityArguments,
+    data::{
+        self,
+        registry::{self, Registry},
+    },
+};
+
+// endregion: IMPORTS
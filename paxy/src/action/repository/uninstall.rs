@@ -1,12 +1,232 @@
-#[allow(unused)]
-pub fn handle_repository_uninstall_action(
+//! Uninstalls repositories by removing them from the repository registry and
+//! deleting their clones and search indexes. The directories of local
+//! repositories are left alone. Packages that were installed
+//! from the repositories are either uninstalled too, or kept as orphans that
+//! no longer receive updates. Installed packages that depend on the packages
+//! uninstalled are uninstalled along with them, or nothing is.
+
+pub fn handle_repository_uninstall_action<G: GlobalArguments>(
     repository_uninstall_arguments: RepositoryUninstallArguments,
+    global_arguments: G,
 ) -> Result<(), Error> {
-    use crate::app::ui::console_template::cli::*;
+    let paxy_home = data::paxy_home();
+    let registry = Registry::load(&Registry::filepath(&paxy_home)).context(RegistrySnafu {})?;
+    for repository_name in repository_uninstall_arguments
+        .repository_names
+        .iter()
+    {
+        ensure!(
+            registry
+                .get(repository_name)
+                .is_some(),
+            NotInstalledSnafu {
+                name: repository_name
+            }
+        );
+    }
+
+    let database = Database::load(&Database::filepath(&paxy_home)).context(DatabaseSnafu {})?;
+    let orphans = orphans(&database, &repository_uninstall_arguments.repository_names);
+    if !orphans.is_empty() {
+        tracing::warn!(
+            "{} These packages were installed from the repositories being uninstalled:",
+            console::Emoji("⚠️", "")
+        );
+        for package in orphans.iter() {
+            tracing::warn!(
+                "  {} {} {}",
+                package.name,
+                package.version,
+                format!(
+                    "[{}]",
+                    package
+                        .repository
+                        .as_deref()
+                        .unwrap_or_default()
+                )
+                .dimmed()
+            );
+        }
+    }
+    if global_arguments.is_test() {
+        return Ok(());
+    }
+
+    let uninstall_orphans = if orphans.is_empty() || repository_uninstall_arguments.keep_packages {
+        false
+    } else if repository_uninstall_arguments.uninstall_packages {
+        true
+    } else {
+        match ui::confirm("Uninstall them too? Otherwise they are kept, but no longer updated.")
+            .context(ConfirmSnafu {})?
+        {
+            Some(answer) => answer,
+            None => return OrphansNeedDecisionSnafu {}.fail(),
+        }
+    };
+
+    let orphan_names: Vec<String> = orphans
+        .iter()
+        .map(|package| package.name.clone())
+        .collect();
+    let dependents = database.dependents(&orphan_names);
+    let uninstall_dependents = if !uninstall_orphans || dependents.is_empty() {
+        false
+    } else {
+        tracing::warn!(
+            "{} These installed packages depend on the packages being uninstalled:",
+            console::Emoji("⚠️", "")
+        );
+        for package in dependents.iter() {
+            tracing::warn!("  {} {}", package.name, package.version);
+        }
+        repository_uninstall_arguments.uninstall_packages
+            || ui::confirm("Uninstall them too? Otherwise nothing is uninstalled.")
+                .context(ConfirmSnafu {})?
+                .unwrap_or_default()
+    };
+
+    let kept = uninstall_repositories(
+        &paxy_home,
+        &repository_uninstall_arguments.repository_names,
+        uninstall_orphans,
+        uninstall_dependents,
+    )?;
+    for repository_name in repository_uninstall_arguments
+        .repository_names
+        .iter()
+    {
+        tracing::info!(
+            "{} Uninstalled repository {}",
+            console::Emoji("🗑️", ""),
+            repository_name.green()
+        );
+        tracing::info!(target: "PLAIN", "{}", repository_name);
+    }
+    for package in kept.iter() {
+        tracing::info!(
+            "{} Kept {} {} as an orphan",
+            console::Emoji("📦", ""),
+            package.name.green(),
+            package.version
+        );
+    }
+
+    Ok(())
+}
+
+/// Returns the installed packages that came from any of the given
+/// repositories.
+pub fn orphans<'a>(
+    database: &'a Database,
+    repository_names: &[String],
+) -> Vec<&'a InstalledPackage> {
+    database
+        .packages
+        .values()
+        .filter(|package| {
+            package
+                .repository
+                .as_ref()
+                .is_some_and(|repository| repository_names.contains(repository))
+        })
+        .collect()
+}
+
+/// Uninstalls the given repositories, along with the packages installed from
+/// them if asked to. Packages installed from elsewhere that depend on those
+/// are uninstalled as well if asked to, and otherwise nothing is uninstalled.
+/// The packages are uninstalled first, as one transaction, so that a failure
+/// leaves the repositories in place. Returns the packages kept as orphans.
+pub fn uninstall_repositories(
+    paxy_home: &Path,
+    repository_names: &[String],
+    uninstall_orphans: bool,
+    uninstall_dependents: bool,
+) -> Result<Vec<InstalledPackage>, Error> {
+    let database_filepath = Database::filepath(paxy_home);
+    let mut database = Database::load(&database_filepath).context(DatabaseSnafu {})?;
+    let orphans: Vec<InstalledPackage> = orphans(&database, repository_names)
+        .into_iter()
+        .cloned()
+        .collect();
 
-    todo!();
+    if uninstall_orphans && !orphans.is_empty() {
+        let dependents: Vec<InstalledPackage> = database
+            .dependents(
+                &orphans
+                    .iter()
+                    .map(|package| package.name.clone())
+                    .collect::<Vec<_>>(),
+            )
+            .into_iter()
+            .cloned()
+            .collect();
+        ensure!(
+            dependents.is_empty() || uninstall_dependents,
+            DependentsSnafu {
+                names: dependents
+                    .iter()
+                    .map(|package| { package.name.clone() })
+                    .collect::<Vec<_>>()
+            }
+        );
 
-    // Ok(())
+        let mut transaction = Transaction::begin(paxy_home).context(TransactionSnafu {})?;
+        transaction
+            .check_database(&database)
+            .context(TransactionSnafu {})?;
+        for package in orphans
+            .iter()
+            .chain(dependents.iter())
+        {
+            let plan = UninstallPlan::new(package).context(UninstallPackageSnafu {})?;
+            uninstall::uninstall_package(package, &plan, &mut transaction)
+                .context(UninstallPackageSnafu {})?;
+            database.remove(&package.name);
+            database.unpin(&package.name);
+            tracing::info!(
+                "{} Uninstalled {} {}",
+                console::Emoji("🗑️", ""),
+                package.name.green(),
+                package.version
+            );
+        }
+        transaction
+            .commit(
+                &database,
+                &format!("uninstall packages of {}", repository_names.join(" ")),
+            )
+            .context(TransactionSnafu {})?;
+    }
+
+    let registry_filepath = Registry::filepath(paxy_home);
+    let mut registry = Registry::load(&registry_filepath).context(RegistrySnafu {})?;
+    for repository_name in repository_names.iter() {
+        registry.remove(repository_name);
+    }
+    registry
+        .save(&registry_filepath)
+        .context(RegistrySnafu {})?;
+
+    for repository_name in repository_names.iter() {
+        let dirpath = data::repositories_dirpath(paxy_home).join(repository_name);
+        if dirpath.exists() {
+            fs::remove_dir_all(&dirpath).context(RemoveSnafu { path: &dirpath })?;
+        }
+        let index_filepath = SearchIndex::filepath(paxy_home, repository_name);
+        if index_filepath.exists() {
+            fs::remove_file(&index_filepath).context(RemoveSnafu {
+                path: &index_filepath,
+            })?;
+        }
+    }
+
+    Ok(if uninstall_orphans {
+        Vec::new()
+    } else {
+        orphans
+    })
 }
 
 // region: ERRORS
@@ -16,17 +236,198 @@ pub fn handle_repository_uninstall_action(
 #[non_exhaustive]
 pub enum Error {
     #[non_exhaustive]
-    #[snafu(display(""))]
-    Dummy {},
+    #[snafu(display("the repository '{name}' is not installed"))]
+    NotInstalled { name: String },
+
+    #[non_exhaustive]
+    #[snafu(display("could not ask whether to uninstall the packages: {source}"))]
+    Confirm { source: std::io::Error },
+
+    #[non_exhaustive]
+    #[snafu(display(
+        "packages were installed from these repositories. Pass --uninstall-packages or \
+         --keep-packages to decide what happens to them"
+    ))]
+    OrphansNeedDecision {},
+
+    #[non_exhaustive]
+    #[snafu(display(
+        "{} depend(s) on the packages of these repositories. Pass --uninstall-packages to \
+         uninstall them too",
+        names.join(", ")
+    ))]
+    Dependents { names: Vec<String> },
+
+    #[non_exhaustive]
+    #[snafu(display("could not uninstall a package of the repositories: {source}"))]
+    UninstallPackage {
+        #[snafu(source(from(uninstall::Error, Box::new)))]
+        source: Box<uninstall::Error>,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("could not remove {path:?}: {source}"))]
+    Remove {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("in the transaction: {source}"))]
+    Transaction { source: transaction::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("in the installed-package database: {source}"))]
+    Database { source: installed::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("in the repository registry: {source}"))]
+    Registry { source: registry::Error },
 }
 
 // endregion: ERRORS
 
 // region: IMPORTS
 
-#[allow(unused)]
-use snafu::{ResultExt, Snafu};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use owo_colors::OwoColorize;
+use snafu::{ensure, ResultExt, Snafu};
 
-use crate::app::ui::console_template::cli::RepositoryUninstallArguments;
+use crate::{
+    action::package::{
+        transaction::{self, Transaction},
+        uninstall::{self, UninstallPlan},
+    },
+    app::ui::{self, console_template::cli::RepositoryUninstallArguments, GlobalArguments},
+    data::{
+        self,
+        installed::{self, Database, InstalledPackage},
+        registry::{self, Registry},
+        search_index::SearchIndex,
+    },
+};
 
 // endregion: IMPORTS
+
+// region: TESTS
+
+#[cfg(test)]
+mod tests {
+    use semver::VersionReq;
+
+    use super::*;
+    use crate::{
+        action::repository::install::{install_repository, tests::source_repository},
+        data::installed::tests::installed_package,
+    };
+
+    #[test]
+    fn uninstall_repositories_and_their_packages() {
+        let paxy_home = tempfile::tempdir().unwrap();
+        let install_location = tempfile::tempdir().unwrap();
+        let sources = tempfile::tempdir().unwrap();
+        let url = source_repository(
+            &sources
+                .path()
+                .join("main"),
+            &[(
+                "hello/1.0.0/manifest.toml",
+                "format_version = 1\nname = \"hello\"\nversion = \"1.0.0\"\n",
+            )],
+        );
+        install_repository(paxy_home.path(), "main", &url).unwrap();
+        install_repository(paxy_home.path(), "other", &url).unwrap();
+
+        fs::create_dir_all(
+            install_location
+                .path()
+                .join("bin"),
+        )
+        .unwrap();
+        fs::write(
+            install_location
+                .path()
+                .join("bin/hello"),
+            "hello",
+        )
+        .unwrap();
+        let mut database = Database::default();
+        for (name, repository, dependencies) in [
+            ("hello", Some("main"), &[][..]),
+            ("world", Some("other"), &[]),
+            ("app", None, &["hello"]),
+        ] {
+            database.insert(InstalledPackage {
+                repository: repository.map(str::to_string),
+                install_location: install_location
+                    .path()
+                    .to_path_buf(),
+                files: if name == "hello" {
+                    vec![PathBuf::from("bin/hello")]
+                } else {
+                    Vec::new()
+                },
+                dependencies: dependencies
+                    .iter()
+                    .map(|dependency| (dependency.to_string(), VersionReq::STAR))
+                    .collect(),
+                ..installed_package(name, "1.0.0")
+            });
+        }
+        database
+            .save(&Database::filepath(paxy_home.path()))
+            .unwrap();
+
+        let kept =
+            uninstall_repositories(paxy_home.path(), &["other".to_string()], false, false).unwrap();
+        assert_eq!(
+            kept.iter()
+                .map(|package| package
+                    .name
+                    .as_str())
+                .collect::<Vec<_>>(),
+            ["world"]
+        );
+        assert!(!data::repositories_dirpath(paxy_home.path())
+            .join("other")
+            .exists());
+        assert!(!SearchIndex::filepath(paxy_home.path(), "other").exists());
+
+        // app, installed from a manifest file, needs hello from main
+        assert!(matches!(
+            uninstall_repositories(paxy_home.path(), &["main".to_string()], true, false),
+            Err(Error::Dependents { ref names }) if names == &["app"]
+        ));
+        assert!(install_location
+            .path()
+            .join("bin/hello")
+            .exists());
+        let kept =
+            uninstall_repositories(paxy_home.path(), &["main".to_string()], true, true).unwrap();
+        assert!(kept.is_empty());
+        assert!(!install_location
+            .path()
+            .join("bin/hello")
+            .exists());
+        let database = Database::load(&Database::filepath(paxy_home.path())).unwrap();
+        assert!(database
+            .get("hello")
+            .is_none());
+        assert!(database
+            .get("app")
+            .is_none());
+        assert!(database
+            .get("world")
+            .is_some());
+        assert!(Registry::load(&Registry::filepath(paxy_home.path()))
+            .unwrap()
+            .repositories
+            .is_empty());
+    }
+}
+
+// endregion: TESTS
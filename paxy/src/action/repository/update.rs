@@ -1,12 +1,556 @@
-#[allow(unused)]
+//! Updates the clones of installed repositories by fetching from their URLs
+//! and fast-forwarding to what was fetched. Repositories served over HTTP
+//! without git are synced with their package index instead. The repositories
+//! are fetched concurrently, since they do not depend on each other.
+
 pub fn handle_repository_update_action(
     repository_update_arguments: RepositoryUpdateArguments,
 ) -> Result<(), Error> {
-    use crate::app::ui::console_template::cli::*;
+    let paxy_home = data::paxy_home();
+    let UpdateReport {
+        updates,
+        failures,
+        pinned,
+        local,
+    } = update_repositories(
+        &paxy_home,
+        &repository_update_arguments.repository_names,
+        &repository_update_arguments.excluded_repository_names,
+        repository_update_arguments.force,
+    )?;
+
+    for repository_name in pinned.iter() {
+        tracing::info!(
+            "{} Skipping {}: it is pinned (see `paxy repository downgrade --unpin`)",
+            console::Emoji("📌", ""),
+            repository_name.green()
+        );
+    }
+    for repository_name in local.iter() {
+        tracing::info!(
+            "{} Skipping {}: it is a local directory, read in place",
+            console::Emoji("📂", ""),
+            repository_name.green()
+        );
+    }
+    for update in updates.iter() {
+        emit(update);
+    }
+    tracing::info!(
+        target: "JSON",
+        "{}",
+        serde_json::to_string(&updates).context(SerializeJsonSnafu {})?
+    );
+
+    for (name, error) in failures.iter() {
+        tracing::error!("Could not update {}: {}", name, error);
+    }
+    ensure!(
+        failures.is_empty(),
+        SomeFailedSnafu {
+            names: failures
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        }
+    );
+
+    Ok(())
+}
+
+/// What updating the clone of a repository changed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RepositoryUpdate {
+    pub name: String,
+    /// The commit before the update, or for a repository downloaded over
+    /// HTTP, the revision of its package index.
+    pub old_commit: Option<String>,
+    pub new_commit: String,
+    /// The commits pulled, newest first. Always empty for a repository
+    /// downloaded over HTTP.
+    pub commits: Vec<PulledCommit>,
+    /// Package versions that were not in the repository before.
+    pub added: Vec<PackageVersion>,
+    /// Package versions whose manifests changed.
+    pub changed: Vec<PackageVersion>,
+    /// Package versions that are no longer in the repository.
+    pub removed: Vec<PackageVersion>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PulledCommit {
+    pub id: String,
+    pub summary: String,
+}
 
-    todo!();
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct PackageVersion {
+    pub name: String,
+    pub version: Version,
+}
+
+impl fmt::Display for PackageVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.name, self.version)
+    }
+}
 
-    // Ok(())
+/// The outcome of updating several repositories.
+#[derive(Debug)]
+pub struct UpdateReport {
+    pub updates: Vec<RepositoryUpdate>,
+    /// The names of the repositories that could not be updated, and why.
+    pub failures: Vec<(String, Error)>,
+    /// The names of the repositories left alone because they are pinned.
+    pub pinned: Vec<String>,
+    /// The names of the local repositories, which have nothing to fetch.
+    pub local: Vec<String>,
+}
+
+fn emit(update: &RepositoryUpdate) {
+    let short = |commit: &str| commit[..commit.len().min(12)].to_string();
+
+    if !update.is_change() {
+        tracing::info!(
+            "{} {} is up to date",
+            console::Emoji("✅", ""),
+            update.name.green()
+        );
+    } else {
+        tracing::info!(
+            "{} Updated {} {}{}",
+            console::Emoji("📚", ""),
+            update.name.green(),
+            format!(
+                "{}..{}",
+                update
+                    .old_commit
+                    .as_deref()
+                    .map(short)
+                    .unwrap_or_default(),
+                short(&update.new_commit)
+            )
+            .dimmed(),
+            match update.commits.len() {
+                0 => String::new(),
+                1 => " (1 commit)".to_string(),
+                count => format!(" ({count} commits)"),
+            }
+        );
+        for commit in update
+            .commits
+            .iter()
+        {
+            tracing::info!("    {} {}", short(&commit.id).dimmed(), commit.summary);
+        }
+    }
+    for (sign, package_versions) in [
+        ("+", &update.added),
+        ("~", &update.changed),
+        ("-", &update.removed),
+    ] {
+        for package_version in package_versions.iter() {
+            tracing::info!("    {} {}", sign, package_version);
+        }
+    }
+    tracing::info!(
+        target: "PLAIN",
+        "{} {} {}",
+        update.name,
+        update
+            .old_commit
+            .as_deref()
+            .unwrap_or("-"),
+        update.new_commit
+    );
+}
+
+/// Fetches and fast-forwards the clones of the named repositories, or of all
+/// installed repositories if none are named, leaving out the excluded ones.
+/// Pinned and local repositories are skipped. Unless forced, clones with local
+/// modifications are refused before anything is fetched from them. When
+/// forced, the clones are reset to what was fetched, even if that is not a
+/// fast-forward.
+///
+/// Repositories that fail to update, including clones that are missing, broken
+/// or refused, do not stop the others. The registry records when each
+/// repository was synced.
+pub fn update_repositories(
+    paxy_home: &Path,
+    repository_names: &[String],
+    excluded_repository_names: &[String],
+    force: bool,
+) -> Result<UpdateReport, Error> {
+    let registry_filepath = Registry::filepath(paxy_home);
+    let mut registry = Registry::load(&registry_filepath).context(RegistrySnafu {})?;
+
+    for repository_name in repository_names.iter() {
+        ensure!(
+            registry
+                .get(repository_name)
+                .is_some(),
+            NotInstalledSnafu {
+                name: repository_name
+            }
+        );
+    }
+    let (pinned, selected): (Vec<&String>, Vec<&String>) = registry
+        .repositories
+        .keys()
+        .filter(|name| repository_names.is_empty() || repository_names.contains(name))
+        .filter(|name| !excluded_repository_names.contains(name))
+        .partition(|name| {
+            registry
+                .get(name)
+                .is_some_and(|registered_repository| {
+                    registered_repository
+                        .pin
+                        .is_some()
+                })
+        });
+    let (local, selected): (Vec<&String>, Vec<&String>) = selected
+        .into_iter()
+        .partition(|name| {
+            registry
+                .get(name)
+                .is_some_and(|registered_repository| {
+                    registered_repository.kind == RepositoryKind::Local
+                })
+        });
+    let pinned: Vec<String> = pinned
+        .into_iter()
+        .cloned()
+        .collect();
+    let local: Vec<String> = local
+        .into_iter()
+        .cloned()
+        .collect();
+    let selected: Vec<(String, PathBuf, RegisteredRepository)> = selected
+        .into_iter()
+        .filter_map(|name| {
+            registry
+                .get(name)
+                .map(|registered_repository| {
+                    (
+                        name.clone(),
+                        registered_repository.dirpath(paxy_home, name),
+                        registered_repository.clone(),
+                    )
+                })
+        })
+        .collect();
+
+    // A repository that cannot be updated as it is on disk is reported along
+    // with the others, without holding them back
+    let mut failures = Vec::new();
+    let selected: Vec<(String, PathBuf, RegisteredRepository)> = selected
+        .into_iter()
+        .filter(|(name, dirpath, registered_repository)| {
+            let error = match list::clone_state(dirpath, registered_repository.kind) {
+                (_, CloneState::Missing) => MissingSnafu { name }.build(),
+                (_, CloneState::Broken) => BrokenSnafu { name }.build(),
+                (_, CloneState::Dirty) if !force => LocalModificationsSnafu { name }.build(),
+                _ => return true,
+            };
+            failures.push((name.clone(), error));
+            false
+        })
+        .collect();
+
+    let results: Vec<(String, Result<RepositoryUpdate, Error>)> = thread::scope(|scope| {
+        let handles: Vec<_> = selected
+            .iter()
+            .map(|(name, dirpath, registered_repository)| {
+                (
+                    name,
+                    scope.spawn(move || match registered_repository.kind {
+                        RepositoryKind::Git => {
+                            update_clone(paxy_home, name, registered_repository, dirpath, force)
+                        }
+                        RepositoryKind::Http => {
+                            update_download(paxy_home, name, registered_repository, dirpath)
+                        }
+                        RepositoryKind::Local => LocalSnafu { name }.fail(),
+                    }),
+                )
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|(name, handle)| {
+                (
+                    name.clone(),
+                    handle
+                        .join()
+                        .unwrap_or_else(|_| PanickedSnafu { name }.fail()),
+                )
+            })
+            .collect()
+    });
+
+    let now = installed::now();
+    let mut updates = Vec::new();
+    for (name, result) in results {
+        match result {
+            Ok(update) => {
+                if let Some(registered_repository) = registry
+                    .repositories
+                    .get_mut(&name)
+                {
+                    registered_repository.synced_at = Some(now);
+                }
+                if update.is_change() {
+                    let local_repository = LocalRepository {
+                        name: name.clone(),
+                        dirpath: data::repositories_dirpath(paxy_home).join(&name),
+                        kind: registry
+                            .get(&name)
+                            .map_or(RepositoryKind::Git, |registered_repository| {
+                                registered_repository.kind
+                            }),
+                    };
+                    if let Err(error) = SearchIndex::rebuild(paxy_home, &local_repository) {
+                        tracing::warn!("Could not rebuild the search index of {name}: {error}");
+                    }
+                }
+                updates.push(update);
+            }
+            Err(error) => failures.push((name, error)),
+        }
+    }
+    registry
+        .save(&registry_filepath)
+        .context(RegistrySnafu {})?;
+
+    Ok(UpdateReport {
+        updates,
+        failures,
+        pinned,
+        local,
+    })
+}
+
+/// Fetches the upstream of the checked-out branch of the clone at the given
+/// path and moves the branch to it. The branches are fetched from the URL of
+/// the repository or, failing that, from its mirrors.
+fn update_clone(
+    paxy_home: &Path,
+    name: &str,
+    registered_repository: &RegisteredRepository,
+    dirpath: &Path,
+    force: bool,
+) -> Result<RepositoryUpdate, Error> {
+    let git_repository = Repository::open(dirpath).context(GitSnafu { name })?;
+    let packages_before = package_fingerprints(dirpath)?;
+
+    let head = git_repository
+        .head()
+        .context(GitSnafu { name })?;
+    let old_commit = head.target();
+    let branch = Branch::wrap(head);
+    let upstream_name = branch
+        .upstream()
+        .context(GitSnafu { name })?
+        .get()
+        .name()
+        .map(str::to_string)
+        .context(NoUpstreamSnafu { name })?;
+
+    mirrors::try_mirrors(
+        paxy_home,
+        &registered_repository.urls(),
+        registered_repository.mirror_order,
+        |url| fetch_branches(&git_repository, url),
+        mirrors::is_git_mirror_fault,
+    )
+    .context(FetchSnafu { name })?;
+
+    let new_commit = git_repository
+        .refname_to_id(&upstream_name)
+        .context(GitSnafu { name })?;
+    if let Some(old_commit) = old_commit {
+        let is_fast_forward = new_commit == old_commit
+            || git_repository
+                .graph_descendant_of(new_commit, old_commit)
+                .context(GitSnafu { name })?;
+        ensure!(force || is_fast_forward, NotFastForwardSnafu { name });
+    }
+
+    let commits = match old_commit {
+        Some(old_commit) if old_commit == new_commit => Vec::new(),
+        _ => pulled_commits(&git_repository, old_commit, new_commit).context(GitSnafu { name })?,
+    };
+    let target = git_repository
+        .find_object(new_commit, None)
+        .context(GitSnafu { name })?;
+    git_repository
+        .reset(&target, ResetType::Hard, None)
+        .context(GitSnafu { name })?;
+    if force {
+        // A hard reset leaves untracked files alone
+        git_repository
+            .checkout_head(Some(
+                CheckoutBuilder::new()
+                    .force()
+                    .remove_untracked(true),
+            ))
+            .context(GitSnafu { name })?;
+    }
+
+    let packages_after = package_fingerprints(dirpath)?;
+
+    Ok(RepositoryUpdate::new(
+        name,
+        old_commit.map(|oid| oid.to_string()),
+        new_commit.to_string(),
+        commits,
+        packages_before,
+        packages_after,
+    ))
+}
+
+/// Fetches the branches of the repository at the given URL into the
+/// remote-tracking branches of `origin`.
+fn fetch_branches(git_repository: &Repository, url: &Url) -> Result<(), git2::Error> {
+    let mut fetch_options = mirrors::git_fetch_options(url)?;
+    git_repository
+        .remote_anonymous(url.as_str())?
+        .fetch(
+            &["+refs/heads/*:refs/remotes/origin/*"],
+            Some(&mut fetch_options),
+            None,
+        )
+}
+
+/// Syncs the repository at the given path with the package index served at
+/// its URL or, failing that, at one of its mirrors. Manifests changed locally
+/// are downloaded again.
+fn update_download(
+    paxy_home: &Path,
+    name: &str,
+    registered_repository: &RegisteredRepository,
+    dirpath: &Path,
+) -> Result<RepositoryUpdate, Error> {
+    let packages_before = package_fingerprints(dirpath)?;
+    let old_revision = http::revision(dirpath);
+    let new_revision = mirrors::try_mirrors(
+        paxy_home,
+        &registered_repository.urls(),
+        registered_repository.mirror_order,
+        |url| http::sync(url, dirpath),
+        http::Error::is_mirror_fault,
+    )
+    .context(DownloadSnafu { name })?;
+    let packages_after = package_fingerprints(dirpath)?;
+
+    Ok(RepositoryUpdate::new(
+        name,
+        old_revision,
+        new_revision,
+        Vec::new(),
+        packages_before,
+        packages_after,
+    ))
+}
+
+impl RepositoryUpdate {
+    fn new(
+        name: &str,
+        old_commit: Option<String>,
+        new_commit: String,
+        commits: Vec<PulledCommit>,
+        packages_before: BTreeMap<PackageVersion, String>,
+        packages_after: BTreeMap<PackageVersion, String>,
+    ) -> Self {
+        let mut update = Self {
+            name: name.to_string(),
+            old_commit,
+            new_commit,
+            commits,
+            added: Vec::new(),
+            changed: Vec::new(),
+            removed: Vec::new(),
+        };
+        for (package_version, hash) in packages_after.iter() {
+            match packages_before.get(package_version) {
+                None => update
+                    .added
+                    .push(package_version.clone()),
+                Some(old_hash) if old_hash != hash => update
+                    .changed
+                    .push(package_version.clone()),
+                Some(_) => {}
+            }
+        }
+        update.removed = packages_before
+            .into_keys()
+            .filter(|package_version| !packages_after.contains_key(package_version))
+            .collect();
+
+        update
+    }
+
+    /// Returns whether the repository moved to another commit or revision.
+    pub fn is_change(&self) -> bool {
+        self.old_commit
+            .as_deref()
+            != Some(
+                self.new_commit
+                    .as_str(),
+            )
+    }
+}
+
+/// Lists the commits reachable from the new commit but not from the old one,
+/// newest first.
+fn pulled_commits(
+    git_repository: &Repository,
+    old_commit: Option<Oid>,
+    new_commit: Oid,
+) -> Result<Vec<PulledCommit>, git2::Error> {
+    let mut revwalk = git_repository.revwalk()?;
+    revwalk.set_sorting(Sort::TOPOLOGICAL | Sort::TIME)?;
+    revwalk.push(new_commit)?;
+    if let Some(old_commit) = old_commit {
+        revwalk.hide(old_commit)?;
+    }
+
+    revwalk
+        .map(|oid| {
+            let commit = git_repository.find_commit(oid?)?;
+            Ok(PulledCommit {
+                id: commit
+                    .id()
+                    .to_string(),
+                summary: commit
+                    .summary()
+                    .unwrap_or_default()
+                    .to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Maps every package version in the repository at the given path to the
+/// hash of its manifest.
+fn package_fingerprints(dirpath: &Path) -> Result<BTreeMap<PackageVersion, String>, Error> {
+    repository::manifest_entries(dirpath)
+        .context(RepositorySnafu {})?
+        .into_iter()
+        .map(|entry| {
+            let hash = data::sha256_of_file(&entry.manifest_filepath).context(HashSnafu {
+                path: &entry.manifest_filepath,
+            })?;
+            Ok((
+                PackageVersion {
+                    name: entry.name,
+                    version: entry.version,
+                },
+                hash,
+            ))
+        })
+        .collect()
 }
 
 // region: ERRORS
@@ -16,17 +560,319 @@ pub fn handle_repository_update_action(
 #[non_exhaustive]
 pub enum Error {
     #[non_exhaustive]
-    #[snafu(display(""))]
-    Dummy {},
+    #[snafu(display("the repository '{name}' is not installed"))]
+    NotInstalled { name: String },
+
+    #[non_exhaustive]
+    #[snafu(display(
+        "the clone of '{name}' is missing. Uninstall the repository and install it again"
+    ))]
+    Missing { name: String },
+
+    #[non_exhaustive]
+    #[snafu(display(
+        "the directory of '{name}' is not a git clone. Uninstall the repository and install it \
+         again"
+    ))]
+    Broken { name: String },
+
+    #[non_exhaustive]
+    #[snafu(display(
+        "the clone of '{name}' has local modifications. Use --force to discard them and update \
+         anyway"
+    ))]
+    LocalModifications { name: String },
+
+    #[non_exhaustive]
+    #[snafu(display(
+        "the checked-out branch of '{name}' does not track a remote branch to update from"
+    ))]
+    NoUpstream { name: String },
+
+    #[non_exhaustive]
+    #[snafu(display(
+        "the clone of '{name}' has diverged from its remote and cannot be fast-forwarded. Use \
+         --force to reset it to the remote"
+    ))]
+    NotFastForward { name: String },
+
+    #[non_exhaustive]
+    #[snafu(display("git failed on '{name}': {source}"))]
+    Git { name: String, source: git2::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("'{name}' is a local directory, read in place, so it has nothing to fetch"))]
+    Local { name: String },
+
+    #[non_exhaustive]
+    #[snafu(display("could not fetch '{name}': {source}"))]
+    Fetch {
+        name: String,
+        source: mirrors::Failures<git2::Error>,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("could not download '{name}': {source}"))]
+    Download {
+        name: String,
+        source: mirrors::Failures<http::Error>,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("updating '{name}' panicked"))]
+    Panicked { name: String },
+
+    #[non_exhaustive]
+    #[snafu(display("could not hash {path:?}: {source}"))]
+    Hash {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("in a repository: {source}"))]
+    Repository { source: repository::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("in the repository registry: {source}"))]
+    Registry { source: registry::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("could not update {names}"))]
+    SomeFailed { names: String },
+
+    #[non_exhaustive]
+    #[snafu(display("could not serialize the repository updates to JSON: {source}"))]
+    SerializeJson { source: serde_json::Error },
 }
 
 // endregion: ERRORS
 
 // region: IMPORTS
 
-#[allow(unused)]
-use snafu::{ResultExt, Snafu};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    path::{Path, PathBuf},
+    thread,
+};
+
+use git2::{build::CheckoutBuilder, Branch, Oid, Repository, ResetType, Sort};
+use owo_colors::OwoColorize;
+use semver::Version;
+use serde::Serialize;
+use snafu::{ensure, OptionExt, ResultExt, Snafu};
+use url::Url;
 
-use crate::app::ui::console_template::cli::RepositoryUpdateArguments;
+use super::list::{self, CloneState};
+use crate::{
+    app::ui::console_template::cli::RepositoryUpdateArguments,
+    data::{
+        self,
+        installed,
+        mirrors,
+        registry::{self, RegisteredRepository, Registry, RepositoryKind},
+        repository::{self, http, LocalRepository},
+        search_index::SearchIndex,
+    },
+};
 
 // endregion: IMPORTS
+
+// region: TESTS
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::{
+        action::repository::{
+            install::{install_repository, tests::source_repository},
+            mirrors::change_mirrors,
+        },
+        data::mirrors::MirrorHealthRecords,
+    };
+
+    #[test]
+    fn fast_forward_and_report_package_changes() {
+        let paxy_home = tempfile::tempdir().unwrap();
+        let sources = tempfile::tempdir().unwrap();
+        let source_dirpath = sources
+            .path()
+            .join("main");
+        let url = source_repository(
+            &source_dirpath,
+            &[
+                (
+                    "hello/1.0.0/manifest.toml",
+                    "format_version = 1\nname = \"hello\"\nversion = \"1.0.0\"\n",
+                ),
+                (
+                    "world/0.1.0/manifest.toml",
+                    "format_version = 1\nname = \"world\"\nversion = \"0.1.0\"\n",
+                ),
+            ],
+        );
+        let local_repository = install_repository(paxy_home.path(), "main", &url).unwrap();
+
+        let UpdateReport {
+            updates, failures, ..
+        } = update_repositories(paxy_home.path(), &[], &[], false).unwrap();
+        assert!(failures.is_empty());
+        assert!(updates[0]
+            .commits
+            .is_empty());
+
+        source_repository(
+            &source_dirpath,
+            &[
+                (
+                    "hello/1.0.0/manifest.toml",
+                    "format_version = 1\nname = \"hello\"\nversion = \"1.0.0\"\ndescription = \
+                     \"Hi\"\n",
+                ),
+                (
+                    "hello/1.1.0/manifest.toml",
+                    "format_version = 1\nname = \"hello\"\nversion = \"1.1.0\"\n",
+                ),
+            ],
+        );
+        let updates = update_repositories(paxy_home.path(), &[], &[], false)
+            .unwrap()
+            .updates;
+        assert_eq!(
+            updates[0]
+                .commits
+                .len(),
+            1
+        );
+        let names = |package_versions: &[PackageVersion]| {
+            package_versions
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(&updates[0].added), ["hello 1.1.0"]);
+        assert_eq!(names(&updates[0].changed), ["hello 1.0.0"]);
+        assert!(updates[0]
+            .removed
+            .is_empty());
+
+        fs::write(
+            local_repository
+                .dirpath
+                .join("notes.txt"),
+            "local change\n",
+        )
+        .unwrap();
+        source_repository(
+            &source_dirpath,
+            &[(
+                "world/0.2.0/manifest.toml",
+                "format_version = 1\nname = \"world\"\nversion = \"0.2.0\"\n",
+            )],
+        );
+        // A modified clone fails on its own, leaving the others to update
+        install_repository(paxy_home.path(), "other", &url).unwrap();
+        let UpdateReport {
+            updates, failures, ..
+        } = update_repositories(paxy_home.path(), &[], &[], false).unwrap();
+        assert!(matches!(
+            failures[..],
+            [(ref name, Error::LocalModifications { .. })] if name == "main"
+        ));
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].name, "other");
+        let updates = update_repositories(paxy_home.path(), &["main".to_string()], &[], true)
+            .unwrap()
+            .updates;
+        assert_eq!(names(&updates[0].added), ["world 0.2.0"]);
+        assert!(!local_repository
+            .dirpath
+            .join("notes.txt")
+            .exists());
+
+        let updates = update_repositories(
+            paxy_home.path(),
+            &[],
+            &["main".to_string(), "other".to_string()],
+            false,
+        )
+        .unwrap()
+        .updates;
+        assert!(updates.is_empty());
+    }
+
+    #[test]
+    fn fall_back_on_a_mirror_when_the_url_fails() {
+        let paxy_home = tempfile::tempdir().unwrap();
+        let sources = tempfile::tempdir().unwrap();
+        let url = source_repository(
+            &sources
+                .path()
+                .join("main"),
+            &[(
+                "hello/1.0.0/manifest.toml",
+                "format_version = 1\nname = \"hello\"\nversion = \"1.0.0\"\n",
+            )],
+        );
+        install_repository(paxy_home.path(), "main", &url).unwrap();
+
+        // The original goes away, leaving only the mirror
+        let mirror_dirpath = sources
+            .path()
+            .join("mirror");
+        fs::rename(
+            sources
+                .path()
+                .join("main"),
+            &mirror_dirpath,
+        )
+        .unwrap();
+        let mirror_url = source_repository(
+            &mirror_dirpath,
+            &[(
+                "hello/1.1.0/manifest.toml",
+                "format_version = 1\nname = \"hello\"\nversion = \"1.1.0\"\n",
+            )],
+        );
+        assert!(matches!(
+            update_repositories(paxy_home.path(), &[], &[], false)
+                .unwrap()
+                .failures[..],
+            [(_, Error::Fetch { .. })]
+        ));
+        change_mirrors(
+            paxy_home.path(),
+            "main",
+            std::slice::from_ref(&mirror_url),
+            &[],
+            None,
+        )
+        .unwrap();
+
+        let UpdateReport {
+            updates, failures, ..
+        } = update_repositories(paxy_home.path(), &[], &[], false).unwrap();
+        assert!(failures.is_empty());
+        assert_eq!(
+            updates[0].added,
+            [PackageVersion {
+                name: "hello".to_string(),
+                version: Version::new(1, 1, 0),
+            }]
+        );
+
+        let records =
+            MirrorHealthRecords::load(&MirrorHealthRecords::filepath(paxy_home.path())).unwrap();
+        let health = |url: &str| records.get(&Url::parse(url).unwrap());
+        assert_eq!(health(&url).failures, 2);
+        assert_eq!(health(&mirror_url).failures, 0);
+        assert!(health(&mirror_url)
+            .last_success_at
+            .is_some());
+    }
+}
+
+// endregion: TESTS
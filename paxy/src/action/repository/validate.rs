@@ -0,0 +1,122 @@
+//! Checks a package repository against the repository layout, so that its
+//! maintainers can catch mistakes before publishing it. It can also generate
+//! the package index of the repository.
+
+pub fn handle_repository_validate_action(
+    repository_validate_arguments: RepositoryValidateArguments,
+) -> Result<(), Error> {
+    let repository_dirpath = &repository_validate_arguments.repository_path;
+    ensure!(
+        repository_dirpath.is_dir(),
+        NotADirectorySnafu {
+            path: repository_dirpath
+        }
+    );
+
+    if repository_validate_arguments.write_index {
+        let index = PackageIndex::generate(repository_dirpath).context(LayoutSnafu {})?;
+        index
+            .save(repository_dirpath)
+            .context(LayoutSnafu {})?;
+        tracing::info!(
+            "{} Wrote the index of {} package version(s)",
+            console::Emoji("🗂️", ""),
+            index.packages.len()
+        );
+    }
+
+    let problems = layout::validate(repository_dirpath).context(LayoutSnafu {})?;
+    for problem in problems.iter() {
+        let location = repository_dirpath.join(&problem.path);
+        match problem.severity {
+            Severity::Error => {
+                tracing::error!("{}: {}", location.display(), problem.message)
+            }
+            Severity::Warning => {
+                tracing::warn!("{}: {}", location.display(), problem.message)
+            }
+        }
+        tracing::info!(
+            target: "PLAIN",
+            "{}\t{}\t{}",
+            match problem.severity {
+                Severity::Error => "error",
+                Severity::Warning => "warning",
+            },
+            problem
+                .path
+                .display(),
+            problem.message
+        );
+    }
+    tracing::info!(
+        target: "JSON",
+        "{}",
+        serde_json::to_string(&problems).context(SerializeJsonSnafu {})?
+    );
+
+    let error_count = problems
+        .iter()
+        .filter(|problem| problem.severity == Severity::Error)
+        .count();
+    ensure!(
+        error_count == 0,
+        InvalidSnafu {
+            path: repository_dirpath,
+            error_count
+        }
+    );
+    tracing::info!(
+        "{} {} is a valid repository{}",
+        console::Emoji("✅", ""),
+        repository_dirpath
+            .display()
+            .green(),
+        if problems.is_empty() {
+            String::new()
+        } else {
+            format!(", with {} warning(s)", problems.len())
+        }
+    );
+
+    Ok(())
+}
+
+// region: ERRORS
+
+#[derive(Debug, Snafu)]
+#[snafu(visibility(pub(crate)))]
+#[non_exhaustive]
+pub enum Error {
+    #[non_exhaustive]
+    #[snafu(display("{path:?} is not a directory"))]
+    NotADirectory { path: PathBuf },
+
+    #[non_exhaustive]
+    #[snafu(display("{path:?} has {error_count} problem(s) to fix before publishing"))]
+    Invalid { path: PathBuf, error_count: usize },
+
+    #[non_exhaustive]
+    #[snafu(display("in the repository layout: {source}"))]
+    Layout { source: layout::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("could not serialize the problems to JSON: {source}"))]
+    SerializeJson { source: serde_json::Error },
+}
+
+// endregion: ERRORS
+
+// region: IMPORTS
+
+use std::path::PathBuf;
+
+use owo_colors::OwoColorize;
+use snafu::{ensure, ResultExt, Snafu};
+
+use crate::{
+    app::ui::console_template::cli::RepositoryValidateArguments,
+    data::repository::layout::{self, PackageIndex, Severity},
+};
+
+// endregion: IMPORTS
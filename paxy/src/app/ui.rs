@@ -177,6 +177,136 @@ where
     );
 }
 
+/// Formats seconds since the Unix epoch as a UTC date and time, like
+/// `2024-05-19 14:03 UTC`.
+pub fn format_unix_timestamp(seconds: u64) -> String {
+    let days = (seconds / 86_400) as i64;
+    let seconds_of_day = seconds % 86_400;
+
+    // Convert days since the epoch to a civil date, following
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02} UTC",
+        seconds_of_day / 3_600,
+        seconds_of_day % 3_600 / 60
+    )
+}
+
+/// Parses a UTC date like `2024-05-19` into seconds since the Unix epoch at
+/// the start of that day.
+pub fn parse_date(text: &str) -> Option<u64> {
+    let mut parts = text
+        .trim()
+        .splitn(3, '-');
+    let year: i64 = parts
+        .next()?
+        .parse()
+        .ok()?;
+    let month: i64 = parts
+        .next()?
+        .parse()
+        .ok()?;
+    let day: i64 = parts
+        .next()?
+        .parse()
+        .ok()?;
+    let is_leap_year = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let month_length = match month {
+        2 if is_leap_year => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        1..=12 => 31,
+        _ => return None,
+    };
+    if !(1..=month_length).contains(&day) {
+        return None;
+    }
+
+    // The inverse of the conversion in `format_unix_timestamp`, following
+    // http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month_index = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * month_index + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+
+    u64::try_from(days * 86_400).ok()
+}
+
+/// Formats a number of bytes with a binary unit, like `1.5 MiB`.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+    let mut size = bytes as f64;
+    let mut unit_index = 0;
+    while size >= 1024.0 && unit_index < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit_index += 1;
+    }
+    if unit_index == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{size:.1} {}", UNITS[unit_index])
+    }
+}
+
+/// Formats a change in size in bytes with its sign, like `+1.5 MiB` or
+/// `-200 B`, or `unknown` when it is not known.
+pub fn format_size_delta(delta: Option<i64>) -> String {
+    match delta {
+        Some(delta) if delta < 0 => format!("-{}", format_size(delta.unsigned_abs())),
+        Some(delta) => format!("+{}", format_size(delta.unsigned_abs())),
+        None => "unknown".to_string(),
+    }
+}
+
+/// Asks the user a question on the terminal and returns the trimmed answer.
+/// Returns `None` if there is no terminal to ask on.
+pub fn ask(question: &str) -> io::Result<Option<String>> {
+    let terminal = console::Term::stderr();
+    if !terminal.is_term() {
+        return Ok(None);
+    }
+
+    terminal.write_str(&format!("{question} "))?;
+    let answer = terminal.read_line()?;
+    Ok(Some(
+        answer
+            .trim()
+            .to_string(),
+    ))
+}
+
+/// Asks the user a yes-or-no question on the terminal, with no as the
+/// default. Returns `None` if there is no terminal to ask on.
+pub fn confirm(question: &str) -> io::Result<Option<bool>> {
+    Ok(ask(&format!("{question} [y/N]"))?.map(|answer| {
+        matches!(
+            answer
+                .to_lowercase()
+                .as_str(),
+            "y" | "yes"
+        )
+    }))
+}
+
 /// Configurable settings that handle how the console output is displayed.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConsoleOutputFormat {
@@ -331,7 +461,7 @@ impl<T: GlobalArguments> GlobalArguments for &T {
 
 // region: IMPORTS
 
-use std::{fmt, path::PathBuf};
+use std::{fmt, io, path::PathBuf};
 
 use owo_colors::OwoColorize;
 use serde::{Deserialize, Serialize};
@@ -340,6 +470,37 @@ use crate::app::config::ConfigTemplate;
 
 // endregion: IMPORTS
 
+// region: TESTS
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_dates_only_within_their_month() {
+        assert_eq!(parse_date("1970-01-01"), Some(0));
+        assert_eq!(parse_date("2024-05-19"), Some(1_716_076_800));
+        assert_eq!(parse_date("2024-02-29"), Some(1_709_164_800));
+        for text in [
+            "2023-02-29",
+            "1900-02-29",
+            "2024-02-30",
+            "2024-04-31",
+            "2024-13-01",
+            "2024-05-00",
+            "2024-05",
+        ] {
+            assert_eq!(parse_date(text), None, "{text}");
+        }
+        assert_eq!(
+            parse_date("2000-02-29").map(format_unix_timestamp),
+            Some("2000-02-29 00:00 UTC".to_string())
+        );
+    }
+}
+
+// endregion: TESTS
+
 // region: EXTERNAL-SUBMODULES
 
 pub mod console_template;
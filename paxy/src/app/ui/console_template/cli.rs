@@ -85,6 +85,15 @@ pub enum EntitySubcommand {
         display_order = 2
     )]
     Repository(RepositorySubcommand),
+
+    #[command(
+        subcommand,
+        name = "generation",
+        alias = "gen",
+        about = "Perform actions on generations - numbered snapshots of the installed packages.",
+        display_order = 3
+    )]
+    Generation(GenerationSubcommand),
 }
 
 #[derive(Debug, Subcommand)]
@@ -125,8 +134,35 @@ pub enum PackageSubcommand {
     )]
     Uninstall(PackageUninstallArguments),
 
-    #[command(name = "downgrade", about = "Downgrade a package.", display_order = 5)]
+    #[command(
+        name = "downgrade",
+        about = "Downgrade a package.",
+        display_order = 5,
+        disable_version_flag = true
+    )]
     Downgrade(PackageDowngradeArguments),
+
+    #[command(
+        name = "pin",
+        alias = "hold",
+        about = "Pin a package, so that updates leave it within a version constraint.",
+        display_order = 6
+    )]
+    Pin(PackagePinArguments),
+
+    #[command(
+        name = "unpin",
+        about = "Release the pin of packages.",
+        display_order = 7
+    )]
+    Unpin(PackageUnpinArguments),
+
+    #[command(
+        name = "owns",
+        about = "Find the installed package that owns a file.",
+        display_order = 8
+    )]
+    Owns(PackageOwnsArguments),
 }
 
 #[derive(Debug, Subcommand)]
@@ -150,7 +186,7 @@ pub enum RepositorySubcommand {
     #[command(
         name = "install",
         alias = "add",
-        about = "Install repositories.",
+        about = "Install a repository from a URL.",
         display_order = 3
     )]
     Install(RepositoryInstallArguments),
@@ -173,10 +209,58 @@ pub enum RepositorySubcommand {
 
     #[command(
         name = "downgrade",
-        about = "Downgrade a repositories.",
-        display_order = 5
+        alias = "pin",
+        about = "Pin a repository at an earlier tag, commit or date, so that updates leave it there.",
+        display_order = 5,
+        disable_version_flag = true
     )]
     Downgrade(RepositoryDowngradeArguments),
+
+    #[command(
+        name = "validate",
+        alias = "lint",
+        about = "Check a repository against the repository layout before publishing it.",
+        display_order = 6
+    )]
+    Validate(RepositoryValidateArguments),
+
+    #[command(
+        name = "set-priority",
+        alias = "priority",
+        about = "Set which repository packages are installed from when several offer them.",
+        display_order = 7
+    )]
+    SetPriority(RepositorySetPriorityArguments),
+
+    #[command(
+        name = "mirrors",
+        about = "List, add or remove the mirrors of a repository, or choose the order they are \
+                 tried in.",
+        alias = "mirror",
+        display_order = 8
+    )]
+    Mirrors(RepositoryMirrorsArguments),
+}
+
+#[derive(Debug, Subcommand)]
+#[command(args_conflicts_with_subcommands = true)]
+pub enum GenerationSubcommand {
+    #[command(name = "list", about = "List generations.", display_order = 1)]
+    List(GenerationListArguments),
+
+    #[command(
+        name = "rollback",
+        about = "Restore the installed packages of an earlier generation.",
+        display_order = 2
+    )]
+    Rollback(GenerationRollbackArguments),
+
+    #[command(
+        name = "prune",
+        about = "Delete older generations, along with the cached builds only they hold.",
+        display_order = 3
+    )]
+    Prune(GenerationPruneArguments),
 }
 
 #[derive(Debug, Args)]
@@ -218,12 +302,42 @@ pub struct PackageSearchArguments {
 
 #[derive(Debug, Args)]
 pub struct PackageInstallArguments {
-    #[arg(help = "Full name(s) of the packages to install.", display_order = usize::MAX - 1)]
+    #[arg(
+        long = "user",
+        help = "Install the packages for the current user only, whatever the configured default \
+                install type.",
+        conflicts_with = "system",
+        display_order = 1
+    )]
+    pub user: bool,
+
+    #[arg(
+        long = "system",
+        help = "Install the packages for every user of the system, whatever the configured \
+                default install type.",
+        display_order = 2
+    )]
+    pub system: bool,
+
+    #[arg(
+        help = "Full name(s) of the packages to install, each optionally prefixed by the \
+                repository to install it from and followed by the features to enable, as in \
+                `repository/name[feature1,feature2]`.",
+        display_order = usize::MAX - 1
+    )]
     pub package_names: Vec<String>,
 }
 
 #[derive(Debug, Args)]
 pub struct PackageUpdateArguments {
+    #[arg(
+        long = "yes",
+        short = 'y',
+        help = "Update without asking for confirmation.",
+        display_order = 2
+    )]
+    pub assume_yes: bool,
+
     #[arg(
         long = "exclude",
         alias = "ignore",
@@ -243,6 +357,13 @@ pub struct PackageUpdateArguments {
 
 #[derive(Debug, Args)]
 pub struct PackageUninstallArguments {
+    #[arg(
+        long = "uninstall-dependents",
+        help = "Also uninstall the installed packages that depend on the packages, without asking.",
+        display_order = 1
+    )]
+    pub uninstall_dependents: bool,
+
     #[arg(
             help = "Full name(s) of the packages to uninstall.",
             last = true,
@@ -256,11 +377,18 @@ pub struct PackageDowngradeArguments {
     #[arg(
         long = "version",
         alias = "ver",
-        help = "The version to downgrade to.",
+        help = "The version to downgrade to. Without it, the available versions are listed to pick from.",
         display_order = 1
     )]
     pub version: Option<String>,
 
+    #[arg(
+        long = "pin",
+        help = "Pin the package to the version downgraded to, so that updates leave it there.",
+        display_order = 2
+    )]
+    pub pin: bool,
+
     #[arg(
             help = "Full name of the package to downgrade.",
             last = true,
@@ -269,6 +397,34 @@ pub struct PackageDowngradeArguments {
     pub package_name: String,
 }
 
+#[derive(Debug, Args)]
+pub struct PackagePinArguments {
+    #[arg(help = "Full name of the package to pin.", display_order = 1)]
+    pub package_name: String,
+
+    #[arg(
+        help = "The versions to allow, like `^1.2`. Defaults to exactly the installed version.",
+        display_order = 2
+    )]
+    pub version_constraint: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct PackageUnpinArguments {
+    #[arg(
+        help = "Full name(s) of the packages to unpin.",
+        required = true,
+        display_order = 1
+    )]
+    pub package_names: Vec<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct PackageOwnsArguments {
+    #[arg(help = "Path of the file to look up.", display_order = usize::MAX - 1)]
+    pub path: PathBuf,
+}
+
 #[derive(Debug, Args)]
 pub struct RepositoryListArguments {
     #[arg(
@@ -290,6 +446,13 @@ pub struct RepositoryListArguments {
 
 #[derive(Debug, Args)]
 pub struct RepositorySearchArguments {
+    #[arg(
+        long = "refresh",
+        help = "Fetch the index of known repositories again, even if the cached one is recent.",
+        display_order = 2
+    )]
+    pub refresh: bool,
+
     #[arg(
         long = "exclude",
         alias = "ignore",
@@ -300,21 +463,38 @@ pub struct RepositorySearchArguments {
     pub excluded_partial_repository_names: Vec<String>,
 
     #[arg(
-            help = "Partial or full name(s) of the repositories to search among available repositories.",
+            help = "Words to search for in the names, descriptions and tags of available repositories. Not specifying this argument will list all of them.",
             last = true,
             display_order = usize::MAX - 1
         )]
-    pub partial_repository_name: String,
+    pub partial_repository_name: Vec<String>,
 }
 
 #[derive(Debug, Args)]
 pub struct RepositoryInstallArguments {
-    #[arg(help = "Full name(s) of the repositories to install.", display_order = usize::MAX - 1)]
-    pub repository_names: Vec<String>,
+    #[arg(
+        help = "Name to install the repository under.",
+        display_order = usize::MAX - 2
+    )]
+    pub repository_name: String,
+
+    #[arg(
+        help = "URL of the repository: a git repository to clone, a server that serves a package index over HTTP(S), or the path of a local directory to read in place.",
+        display_order = usize::MAX - 1
+    )]
+    pub url: String,
 }
 
 #[derive(Debug, Args)]
 pub struct RepositoryUpdateArguments {
+    #[arg(
+        long = "force",
+        short = 'f',
+        help = "Update even over local modifications of the repositories, discarding them.",
+        display_order = 2
+    )]
+    pub force: bool,
+
     #[arg(
         long = "exclude",
         alias = "ignore",
@@ -334,6 +514,21 @@ pub struct RepositoryUpdateArguments {
 
 #[derive(Debug, Args)]
 pub struct RepositoryUninstallArguments {
+    #[arg(
+        long = "uninstall-packages",
+        help = "Also uninstall the packages that were installed from the repositories, and the packages that depend on them, without asking.",
+        conflicts_with = "keep_packages",
+        display_order = 1
+    )]
+    pub uninstall_packages: bool,
+
+    #[arg(
+        long = "keep-packages",
+        help = "Keep the packages that were installed from the repositories, without asking. They will no longer receive updates.",
+        display_order = 2
+    )]
+    pub keep_packages: bool,
+
     #[arg(
             help = "Full name(s) of the repositories to uninstall.",
             last = true,
@@ -347,11 +542,28 @@ pub struct RepositoryDowngradeArguments {
     #[arg(
         long = "version",
         alias = "ver",
-        help = "The version to downgrade to.",
+        help = "The tag or commit to check the repository out at.",
+        conflicts_with_all = ["date", "unpin"],
+        required_unless_present_any = ["date", "unpin"],
         display_order = 1
     )]
     pub version: Option<String>,
 
+    #[arg(
+        long = "date",
+        help = "Check the repository out at its last commit on or before this UTC date, like 2024-05-19.",
+        conflicts_with = "unpin",
+        display_order = 2
+    )]
+    pub date: Option<String>,
+
+    #[arg(
+        long = "unpin",
+        help = "Release the pin, returning the repository to its branch so that updates move it again.",
+        display_order = 3
+    )]
+    pub unpin: bool,
+
     #[arg(
             help = "Full name of the repository to downgrade.",
             last = true,
@@ -360,12 +572,115 @@ pub struct RepositoryDowngradeArguments {
     pub repository_name: String,
 }
 
+#[derive(Debug, Args)]
+pub struct RepositoryValidateArguments {
+    #[arg(
+        long = "write-index",
+        help = "Generate the package index of the repository again before checking it.",
+        display_order = 1
+    )]
+    pub write_index: bool,
+
+    #[arg(
+        help = "Path of the repository to check.",
+        display_order = usize::MAX - 1
+    )]
+    pub repository_path: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct RepositorySetPriorityArguments {
+    #[arg(
+        help = "Full name of the repository.",
+        display_order = usize::MAX - 2
+    )]
+    pub repository_name: String,
+
+    #[arg(
+        help = "The new priority. Packages come from the repository with the highest priority \
+                that offers them. Repositories start at 0.",
+        allow_negative_numbers = true,
+        display_order = usize::MAX - 1
+    )]
+    pub priority: i32,
+}
+
+#[derive(Debug, Args)]
+pub struct RepositoryMirrorsArguments {
+    #[arg(
+        long = "add",
+        short = 'a',
+        help = "URL(s) of mirrors to add. Mirrors are tried after the URL of the repository, in \
+                the order they were added.",
+        display_order = 1
+    )]
+    pub added_mirror_urls: Vec<String>,
+
+    #[arg(
+        long = "remove",
+        short = 'r',
+        help = "URL(s) of mirrors to remove.",
+        display_order = 2
+    )]
+    pub removed_mirror_urls: Vec<String>,
+
+    #[arg(
+        long = "order",
+        help = "Try the URL and the mirrors in the order they are listed (`listed`), or the \
+                quickest to answer first (`fastest`). Mirrors that keep failing are tried last \
+                either way.",
+        display_order = 3
+    )]
+    pub mirror_order: Option<MirrorOrder>,
+
+    #[arg(
+        help = "Full name of the repository.",
+        display_order = usize::MAX - 1
+    )]
+    pub repository_name: String,
+}
+
+#[derive(Debug, Args)]
+pub struct GenerationListArguments {
+    #[arg(
+        long = "packages",
+        short = 'p',
+        help = "Also list the packages of each generation.",
+        display_order = 1
+    )]
+    pub with_packages: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct GenerationRollbackArguments {
+    #[arg(
+        help = "Number of the generation to restore.",
+        display_order = usize::MAX - 1
+    )]
+    pub generation_number: u64,
+}
+
+#[derive(Debug, Args)]
+pub struct GenerationPruneArguments {
+    #[arg(
+        long = "keep",
+        help = "How many of the most recent generations to keep. The current generation is always \
+                kept.",
+        required = true,
+        display_order = 1
+    )]
+    pub kept_count: usize,
+}
+
 // region: IMPORTS
 
 use std::path::PathBuf;
 
 use clap::{Args, Parser, Subcommand};
 
-use crate::app::ui::{self, console_template::GlobalArgs};
+use crate::{
+    app::ui::{self, console_template::GlobalArgs},
+    data::mirrors::MirrorOrder,
+};
 
 // endregion: IMPORTS
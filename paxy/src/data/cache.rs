@@ -0,0 +1,307 @@
+//! The cache of built packages, kept under `~/.paxy/cache`. The files staged
+//! by the install steps of a package are kept along with its manifest, as in
+//! `<package name>/<version>/<install type>/<features>/`, so that the package
+//! can be installed again without fetching or building its sources, such as
+//! when rolling back to an earlier generation. A build made for the user
+//! install location is never installed under the system one, or the other way
+//! around, since its files may refer to where they were installed. Builds
+//! that no generation holds any more are pruned.
+
+/// A built package in the cache.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedArtifact {
+    pub manifest_filepath: PathBuf,
+    /// The directory holding the staged files, laid out as they are under the
+    /// install location.
+    pub files_dirpath: PathBuf,
+}
+
+/// Returns the directory in which the given build of a package is cached.
+pub fn artifact_dirpath(
+    paxy_home: &Path,
+    name: &str,
+    version: &Version,
+    install_type: InstallType,
+    features: &BTreeSet<String>,
+) -> PathBuf {
+    cache_dirpath(paxy_home)
+        .join(name)
+        .join(version.to_string())
+        .join(install_type_dirname(install_type))
+        .join(features_dirname(features))
+}
+
+fn cache_dirpath(paxy_home: &Path) -> PathBuf {
+    paxy_home.join("cache")
+}
+
+/// Names the directory of the builds for the given install type.
+fn install_type_dirname(install_type: InstallType) -> &'static str {
+    match install_type {
+        InstallType::User => "user",
+        InstallType::System => "system",
+    }
+}
+
+/// Names the directory of a build with the given features. Feature names
+/// cannot contain commas, so joining them with commas tells every set of
+/// features apart.
+fn features_dirname(features: &BTreeSet<String>) -> String {
+    if features.is_empty() {
+        "base".to_string()
+    } else {
+        format!(
+            "with-{}",
+            features
+                .iter()
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(",")
+        )
+    }
+}
+
+/// Caches the files staged for a package, replacing any earlier copy of the
+/// same build.
+pub fn store(
+    paxy_home: &Path,
+    manifest_filepath: &Path,
+    manifest: &PackageManifest,
+    install_type: InstallType,
+    features: &BTreeSet<String>,
+    staged_dirpath: &Path,
+) -> io::Result<CachedArtifact> {
+    let dirpath = artifact_dirpath(
+        paxy_home,
+        &manifest.name,
+        &manifest.version,
+        install_type,
+        features,
+    );
+    if dirpath.exists() {
+        fs::remove_dir_all(&dirpath)?;
+    }
+    fs::create_dir_all(&dirpath)?;
+
+    let extension = manifest_filepath
+        .extension()
+        .unwrap_or_default();
+    let artifact = CachedArtifact {
+        manifest_filepath: dirpath
+            .join("manifest")
+            .with_extension(extension),
+        files_dirpath: dirpath.join("files"),
+    };
+    fs::copy(manifest_filepath, &artifact.manifest_filepath)?;
+    copy_directory(staged_dirpath, &artifact.files_dirpath)?;
+
+    Ok(artifact)
+}
+
+/// Finds the given build of a package in the cache.
+pub fn lookup(
+    paxy_home: &Path,
+    name: &str,
+    version: &Version,
+    install_type: InstallType,
+    features: &BTreeSet<String>,
+) -> Option<CachedArtifact> {
+    let dirpath = artifact_dirpath(paxy_home, name, version, install_type, features);
+    let files_dirpath = dirpath.join("files");
+    if !files_dirpath.is_dir() {
+        return None;
+    }
+
+    Some(CachedArtifact {
+        manifest_filepath: manifest::manifest_filepath_in(&dirpath)?,
+        files_dirpath,
+    })
+}
+
+/// Removes every build in the cache that none of the generations in the
+/// history holds, along with the directories left empty, and returns how many
+/// builds were removed.
+pub fn prune(paxy_home: &Path, history: &History) -> io::Result<usize> {
+    let kept: BTreeSet<[String; 4]> = history
+        .generations
+        .iter()
+        .flat_map(|generation| {
+            generation
+                .packages
+                .iter()
+        })
+        .map(|(name, state)| {
+            [
+                name.clone(),
+                state
+                    .version
+                    .to_string(),
+                install_type_dirname(state.install_type).to_string(),
+                features_dirname(&state.features),
+            ]
+        })
+        .collect();
+
+    let mut removed_count = 0;
+    let Ok(name_entries) = fs::read_dir(cache_dirpath(paxy_home)) else {
+        return Ok(0);
+    };
+    for name_entry in name_entries {
+        let name_dirpath = name_entry?.path();
+        for version_entry in fs::read_dir(&name_dirpath)? {
+            let version_dirpath = version_entry?.path();
+            for install_type_entry in fs::read_dir(&version_dirpath)? {
+                let install_type_dirpath = install_type_entry?.path();
+                for features_entry in fs::read_dir(&install_type_dirpath)? {
+                    let features_dirpath = features_entry?.path();
+                    let key = [
+                        &name_dirpath,
+                        &version_dirpath,
+                        &install_type_dirpath,
+                        &features_dirpath,
+                    ]
+                    .map(|dirpath| {
+                        dirpath
+                            .file_name()
+                            .unwrap_or_default()
+                            .to_string_lossy()
+                            .into_owned()
+                    });
+                    if !kept.contains(&key) {
+                        fs::remove_dir_all(&features_dirpath)?;
+                        removed_count += 1;
+                    }
+                }
+                remove_if_empty(&install_type_dirpath)?;
+            }
+            remove_if_empty(&version_dirpath)?;
+        }
+        remove_if_empty(&name_dirpath)?;
+    }
+
+    Ok(removed_count)
+}
+
+fn remove_if_empty(dirpath: &Path) -> io::Result<()> {
+    if fs::read_dir(dirpath)?
+        .next()
+        .is_none()
+    {
+        fs::remove_dir(dirpath)?;
+    }
+
+    Ok(())
+}
+
+fn copy_directory(from: &Path, to: &Path) -> io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry
+            .file_type()?
+            .is_dir()
+        {
+            copy_directory(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), target)?;
+        }
+    }
+
+    Ok(())
+}
+
+// region: IMPORTS
+
+use std::{
+    collections::BTreeSet,
+    fs,
+    io,
+    path::{Path, PathBuf},
+};
+
+use semver::Version;
+
+use crate::data::{
+    config::InstallType,
+    generation::History,
+    manifest::{self, PackageManifest},
+};
+
+// endregion: IMPORTS
+
+// region: TESTS
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::data::generation::{Generation, PackageState};
+
+    fn features(names: &[&str]) -> BTreeSet<String> {
+        names
+            .iter()
+            .map(|name| name.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn prune_builds_no_generation_holds() {
+        let paxy_home = tempfile::tempdir().unwrap();
+        let version = Version::new(1, 0, 0);
+        let build = |name: &str, install_type: InstallType, names: &[&str]| {
+            artifact_dirpath(
+                paxy_home.path(),
+                name,
+                &version,
+                install_type,
+                &features(names),
+            )
+        };
+        assert_ne!(
+            build("app", InstallType::User, &["a+b"]),
+            build("app", InstallType::User, &["a", "b"])
+        );
+        assert_ne!(
+            build("app", InstallType::User, &["a+b"]),
+            build("app", InstallType::System, &["a+b"])
+        );
+
+        for (name, install_type, names) in [
+            ("app", InstallType::User, &["a+b"][..]),
+            ("app", InstallType::System, &["a+b"]),
+            ("app", InstallType::User, &["a", "b"]),
+            ("old", InstallType::User, &[]),
+        ] {
+            fs::create_dir_all(build(name, install_type, names).join("files")).unwrap();
+        }
+        let history = History {
+            generations: vec![Generation {
+                number: 1,
+                created_at: 0,
+                description: "install app".to_string(),
+                packages: BTreeMap::from([(
+                    "app".to_string(),
+                    PackageState {
+                        version: version.clone(),
+                        repository: None,
+                        install_type: InstallType::User,
+                        features: features(&["a+b"]),
+                    },
+                )]),
+            }],
+        };
+
+        assert_eq!(prune(paxy_home.path(), &history).unwrap(), 3);
+        assert!(build("app", InstallType::User, &["a+b"]).is_dir());
+        assert!(!build("app", InstallType::System, &["a+b"]).exists());
+        assert!(!build("app", InstallType::User, &["a", "b"]).exists());
+        assert!(!paxy_home
+            .path()
+            .join("cache/old")
+            .exists());
+    }
+}
+
+// endregion: TESTS
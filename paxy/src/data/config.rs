@@ -1,7 +1,7 @@
 use std::{
     fs::{self, create_dir_all, File},
     io::Write,
-    path::PathBuf,
+    path::{Path, PathBuf},
 };
 
 use serde::{Deserialize, Serialize};
@@ -13,6 +13,10 @@ pub struct Config {
     pub system_install_location: PathBuf,
     pub user_install_location: PathBuf,
     pub default_install_type: InstallType,
+    /// Where the index of known repositories is fetched from, if not from
+    /// the default location.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repository_index_url: Option<Url>,
 }
 
 impl Default for Config {
@@ -37,6 +41,7 @@ impl Default for Config {
                                                   * very next line */
             system_install_location: system,
             default_install_type: InstallType::default(),
+            repository_index_url: None,
         };
         if !user.is_dir() {
             create_dir_all(user.clone()).expect("No permission"); // Not harmful since the value is dropped in the soon
@@ -59,27 +64,35 @@ impl Default for Config {
     }
 }
 
-#[derive(Serialize, Deserialize, Default)]
+impl Config {
+    /// Returns the prefix under which packages of the given install type are
+    /// installed.
+    pub fn install_location(&self, install_type: InstallType) -> &Path {
+        match install_type {
+            InstallType::User => &self.user_install_location,
+            InstallType::System => &self.system_install_location,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum InstallType {
     User,
     #[default]
     System,
 }
 
-#[allow(dead_code)]
-fn load_conf() -> Config {
+/// Loads the configuration from `~/.paxy/config.ini`, falling back to the
+/// defaults if the file is missing or cannot be parsed.
+pub fn load_conf() -> Config {
     let mut conf_path: PathBuf = match home::home_dir() {
         Some(path) => path,
         None => panic!("Impossible to get your home dir!"),
     };
     conf_path.push(".paxy");
     conf_path.push("config.ini");
-    match toml::from_str::<Config>(
-        fs::read_to_string(&conf_path)
-            .unwrap()
-            .as_str(),
-    ) {
-        Ok(val) => val,
-        Err(_) => Config::default(),
-    }
+    fs::read_to_string(&conf_path)
+        .ok()
+        .and_then(|contents| toml::from_str::<Config>(&contents).ok())
+        .unwrap_or_default()
 }
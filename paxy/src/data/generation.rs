@@ -0,0 +1,254 @@
+//! The history of the installed packages, kept at `~/.paxy/generations.bson`.
+//! Every committed change to the installed packages is recorded as a new,
+//! numbered generation holding the version of every package installed after
+//! the change, so that any earlier state can be restored later.
+
+/// The state of the installed packages after a change.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Generation {
+    pub number: u64,
+    /// Seconds since the Unix epoch at which the generation was created.
+    pub created_at: u64,
+    /// What was done to create the generation, like `install ripgrep`.
+    pub description: String,
+    pub packages: BTreeMap<String, PackageState>,
+}
+
+/// What is needed to install a package again exactly as it was.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PackageState {
+    pub version: Version,
+    pub repository: Option<String>,
+    pub install_type: InstallType,
+    #[serde(default)]
+    pub features: BTreeSet<String>,
+}
+
+impl PackageState {
+    pub fn of(package: &InstalledPackage) -> Self {
+        Self {
+            version: package
+                .version
+                .clone(),
+            repository: package
+                .repository
+                .clone(),
+            install_type: package.install_type,
+            features: package
+                .features
+                .clone(),
+        }
+    }
+}
+
+/// Every generation, oldest first. The last one is the current state.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct History {
+    pub generations: Vec<Generation>,
+}
+
+impl History {
+    /// Returns the path of the history file inside the given paxy home.
+    pub fn filepath(paxy_home: &Path) -> PathBuf {
+        paxy_home.join("generations.bson")
+    }
+
+    /// Loads the history from the given file. A missing file is an empty
+    /// history.
+    pub fn load(filepath: &Path) -> Result<Self, Error> {
+        if !filepath.is_file() {
+            return Ok(Self::default());
+        }
+
+        let file = File::open(filepath).context(ReadHistorySnafu { path: filepath })?;
+        let document =
+            Document::from_reader(file).context(DecodeHistorySnafu { path: filepath })?;
+        bson::from_document(document).context(DeserializeHistorySnafu { path: filepath })
+    }
+
+    /// Writes the history to the given file atomically.
+    pub fn save(&self, filepath: &Path) -> Result<(), Error> {
+        let document = bson::to_document(self).context(SerializeHistorySnafu {})?;
+        let mut buffer = Vec::new();
+        document
+            .to_writer(&mut buffer)
+            .context(EncodeHistorySnafu {})?;
+        data::write_atomically(filepath, &buffer).context(WriteHistorySnafu { path: filepath })
+    }
+
+    pub fn current(&self) -> Option<&Generation> {
+        self.generations
+            .last()
+    }
+
+    pub fn get(&self, number: u64) -> Option<&Generation> {
+        self.generations
+            .iter()
+            .find(|generation| generation.number == number)
+    }
+
+    /// Removes all but the given number of most recent generations, never
+    /// removing the current one, and returns the numbers of those removed.
+    pub fn prune(&mut self, kept_count: usize) -> Vec<u64> {
+        let removed_count = self
+            .generations
+            .len()
+            .saturating_sub(kept_count.max(1));
+        self.generations
+            .drain(..removed_count)
+            .map(|generation| generation.number)
+            .collect()
+    }
+
+    /// Records the packages in the database as a new generation, unless they
+    /// are the same as in the current generation.
+    pub fn record(&mut self, database: &Database, description: &str) -> Option<&Generation> {
+        let packages: BTreeMap<String, PackageState> = database
+            .packages
+            .iter()
+            .map(|(name, package)| (name.clone(), PackageState::of(package)))
+            .collect();
+        if self
+            .current()
+            .is_some_and(|current| current.packages == packages)
+        {
+            return None;
+        }
+
+        let number = self
+            .current()
+            .map_or(1, |current| current.number + 1);
+        self.generations
+            .push(Generation {
+                number,
+                created_at: installed::now(),
+                description: description.to_string(),
+                packages,
+            });
+
+        self.current()
+    }
+}
+
+// region: ERRORS
+
+#[derive(Debug, Snafu)]
+#[snafu(visibility(pub(crate)))]
+#[non_exhaustive]
+pub enum Error {
+    #[non_exhaustive]
+    #[snafu(display("could not read the generation history at {path:?}: {source}"))]
+    ReadHistory {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("the generation history at {path:?} is corrupt: {source}"))]
+    DecodeHistory {
+        path: PathBuf,
+        source: bson::de::Error,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("the generation history at {path:?} has unexpected contents: {source}"))]
+    DeserializeHistory {
+        path: PathBuf,
+        source: bson::de::Error,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("could not serialize the generation history: {source}"))]
+    SerializeHistory {
+        #[snafu(source(from(bson::ser::Error, Box::new)))]
+        source: Box<bson::ser::Error>,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("could not encode the generation history: {source}"))]
+    EncodeHistory {
+        #[snafu(source(from(bson::ser::Error, Box::new)))]
+        source: Box<bson::ser::Error>,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("could not write the generation history to {path:?}: {source}"))]
+    WriteHistory {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+}
+
+// endregion: ERRORS
+
+// region: IMPORTS
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::File,
+    path::{Path, PathBuf},
+};
+
+use bson::Document;
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use snafu::{ResultExt, Snafu};
+
+use crate::data::{
+    self,
+    config::InstallType,
+    installed::{self, Database, InstalledPackage},
+};
+
+// endregion: IMPORTS
+
+// region: TESTS
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::installed::tests::installed_package;
+
+    #[test]
+    fn unchanged_packages_do_not_make_a_new_generation() {
+        let paxy_home = tempfile::tempdir().unwrap();
+        let filepath = History::filepath(paxy_home.path());
+        let mut history = History::load(&filepath).unwrap();
+        let mut database = Database::default();
+        database.insert(installed_package("hello", "1.0.0"));
+
+        assert_eq!(
+            history
+                .record(&database, "install hello")
+                .map(|generation| generation.number),
+            Some(1)
+        );
+        assert!(history
+            .record(&database, "install hello")
+            .is_none());
+        database.remove("hello");
+        assert_eq!(
+            history
+                .record(&database, "uninstall hello")
+                .map(|generation| generation.number),
+            Some(2)
+        );
+
+        history
+            .save(&filepath)
+            .unwrap();
+        let history = History::load(&filepath).unwrap();
+        assert!(history
+            .get(1)
+            .is_some_and(|generation| generation
+                .packages
+                .contains_key("hello")));
+        assert!(history
+            .current()
+            .is_some_and(|generation| generation
+                .packages
+                .is_empty()));
+    }
+}
+
+// endregion: TESTS
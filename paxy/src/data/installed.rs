@@ -0,0 +1,417 @@
+//! The database of installed packages, kept at `~/.paxy/installed.bson`. It
+//! is read whole, modified in memory, and written back atomically, so that a
+//! crash while saving leaves either the old or the new database on disk,
+//! never a mix of both.
+
+/// A package that paxy has installed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InstalledPackage {
+    pub name: String,
+    pub version: Version,
+    /// The repository the package was found in, or `None` if it was installed
+    /// from a manifest given by path.
+    pub repository: Option<String>,
+    pub install_type: InstallType,
+    pub install_location: PathBuf,
+    /// Seconds since the Unix epoch at which the package was installed.
+    pub installed_at: u64,
+    /// Paths of the files owned by the package, relative to the install
+    /// location.
+    pub files: Vec<PathBuf>,
+    /// The configuration files among `files`, mapped to their SHA-256
+    /// checksums at the time of installation.
+    #[serde(default)]
+    pub config_files: BTreeMap<PathBuf, String>,
+    /// The optional features of the package that were installed.
+    #[serde(default)]
+    pub features: BTreeSet<String>,
+    /// The dependencies declared by the manifest the package was installed
+    /// from, including those of its installed features, so that they can be
+    /// checked without the manifest at hand.
+    #[serde(default)]
+    pub dependencies: BTreeMap<String, VersionReq>,
+}
+
+impl InstalledPackage {
+    /// Returns the absolute paths of the files owned by the package.
+    pub fn absolute_filepaths(&self) -> impl Iterator<Item = PathBuf> + '_ {
+        self.files
+            .iter()
+            .map(|file| {
+                self.install_location
+                    .join(file)
+            })
+    }
+
+    /// Returns whether a file of the package, relative to the install
+    /// location, is a configuration file that was edited since it was
+    /// installed. A configuration file that no longer exists was not edited.
+    pub fn is_edited_config_file(&self, file: &Path) -> std::io::Result<bool> {
+        let Some(checksum) = self
+            .config_files
+            .get(file)
+        else {
+            return Ok(false);
+        };
+        let filepath = self
+            .install_location
+            .join(file);
+        if std::fs::symlink_metadata(&filepath).is_err() {
+            return Ok(false);
+        }
+
+        Ok(&data::sha256_of_file(&filepath)? != checksum)
+    }
+}
+
+/// All installed packages, keyed by name.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Database {
+    pub packages: BTreeMap<String, InstalledPackage>,
+    /// The absolute path of every installed file, mapped to the name of the
+    /// package that owns it. Kept in step with `packages` by
+    /// [`Database::insert`] and [`Database::remove`].
+    #[serde(default)]
+    pub owners: BTreeMap<PathBuf, String>,
+    /// The installed packages held to the versions matching a requirement,
+    /// keyed by name. Updates leave a pinned package within its pin.
+    #[serde(default)]
+    pub pins: BTreeMap<String, VersionReq>,
+}
+
+impl Database {
+    /// Returns the path of the database file inside the given paxy home.
+    pub fn filepath(paxy_home: &Path) -> PathBuf {
+        paxy_home.join("installed.bson")
+    }
+
+    /// Loads the database from the given file. A missing file is an empty
+    /// database.
+    pub fn load(filepath: &Path) -> Result<Self, Error> {
+        if !filepath.is_file() {
+            return Ok(Self::default());
+        }
+
+        let file = File::open(filepath).context(ReadDatabaseSnafu { path: filepath })?;
+        let document =
+            Document::from_reader(file).context(DecodeDatabaseSnafu { path: filepath })?;
+        bson::from_document(document).context(DeserializeDatabaseSnafu { path: filepath })
+    }
+
+    /// Writes the database to the given file atomically.
+    pub fn save(&self, filepath: &Path) -> Result<(), Error> {
+        let document = bson::to_document(self).context(SerializeDatabaseSnafu {})?;
+        let mut buffer = Vec::new();
+        document
+            .to_writer(&mut buffer)
+            .context(EncodeDatabaseSnafu {})?;
+        data::write_atomically(filepath, &buffer).context(WriteDatabaseSnafu { path: filepath })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&InstalledPackage> {
+        self.packages
+            .get(name)
+    }
+
+    /// Records a package as installed, replacing any earlier record of it, and
+    /// takes ownership of its files.
+    pub fn insert(&mut self, package: InstalledPackage) {
+        self.remove(&package.name);
+        for filepath in package.absolute_filepaths() {
+            self.owners
+                .insert(filepath, package.name.clone());
+        }
+        self.packages
+            .insert(package.name.clone(), package);
+    }
+
+    /// Forgets an installed package along with its ownership of files.
+    pub fn remove(&mut self, name: &str) -> Option<InstalledPackage> {
+        let package = self
+            .packages
+            .remove(name)?;
+        for filepath in package.absolute_filepaths() {
+            if self
+                .owners
+                .get(&filepath)
+                .is_some_and(|owner| owner == name)
+            {
+                self.owners
+                    .remove(&filepath);
+            }
+        }
+
+        Some(package)
+    }
+
+    /// Holds the named package to the versions matching the requirement,
+    /// replacing any earlier pin of it.
+    pub fn pin(&mut self, name: &str, requirement: VersionReq) {
+        self.pins
+            .insert(name.to_string(), requirement);
+    }
+
+    /// Releases the pin of the named package, returning it.
+    pub fn unpin(&mut self, name: &str) -> Option<VersionReq> {
+        self.pins
+            .remove(name)
+    }
+
+    pub fn pin_of(&self, name: &str) -> Option<&VersionReq> {
+        self.pins.get(name)
+    }
+
+    /// Returns the installed packages, other than the named ones, that depend
+    /// on any of them, either directly or through other such packages. These
+    /// would be left broken if the named packages were uninstalled alone.
+    pub fn dependents(&self, names: &[String]) -> Vec<&InstalledPackage> {
+        let mut removed: BTreeSet<&str> = names
+            .iter()
+            .map(String::as_str)
+            .collect();
+        let mut dependents = Vec::new();
+        loop {
+            let found: Vec<&InstalledPackage> = self
+                .packages
+                .values()
+                .filter(|package| {
+                    !removed.contains(
+                        package
+                            .name
+                            .as_str(),
+                    ) && package
+                        .dependencies
+                        .keys()
+                        .any(|dependency| removed.contains(dependency.as_str()))
+                })
+                .collect();
+            if found.is_empty() {
+                break;
+            }
+            for package in found {
+                removed.insert(&package.name);
+                dependents.push(package);
+            }
+        }
+        dependents.sort_by(|a, b| a.name.cmp(&b.name));
+
+        dependents
+    }
+
+    /// Returns the package that owns the file at the given absolute path.
+    pub fn owner_of(&self, filepath: &Path) -> Option<&InstalledPackage> {
+        self.owners
+            .get(filepath)
+            .and_then(|owner| self.get(owner))
+    }
+}
+
+/// Returns the current time as seconds since the Unix epoch.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+// region: ERRORS
+
+#[derive(Debug, Snafu)]
+#[snafu(visibility(pub(crate)))]
+#[non_exhaustive]
+pub enum Error {
+    #[non_exhaustive]
+    #[snafu(display("could not read the installed-package database at {path:?}: {source}"))]
+    ReadDatabase {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("the installed-package database at {path:?} is corrupt: {source}"))]
+    DecodeDatabase {
+        path: PathBuf,
+        source: bson::de::Error,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display(
+        "the installed-package database at {path:?} has unexpected contents: {source}"
+    ))]
+    DeserializeDatabase {
+        path: PathBuf,
+        source: bson::de::Error,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("could not serialize the installed-package database: {source}"))]
+    SerializeDatabase {
+        #[snafu(source(from(bson::ser::Error, Box::new)))]
+        source: Box<bson::ser::Error>,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("could not encode the installed-package database: {source}"))]
+    EncodeDatabase {
+        #[snafu(source(from(bson::ser::Error, Box::new)))]
+        source: Box<bson::ser::Error>,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("could not write the installed-package database to {path:?}: {source}"))]
+    WriteDatabase {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+}
+
+// endregion: ERRORS
+
+// region: IMPORTS
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::File,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bson::Document;
+use semver::{Version, VersionReq};
+use serde::{Deserialize, Serialize};
+use snafu::{ResultExt, Snafu};
+
+use crate::data::{self, config::InstallType};
+
+// endregion: IMPORTS
+
+// region: TESTS
+
+/// Fixtures shared by the tests that need installed packages.
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Returns a package installed by the user under `/prefix`, from no
+    /// repository, without any files, features or dependencies. Tests set the
+    /// fields they care about with struct update syntax.
+    pub(crate) fn installed_package(name: &str, version: &str) -> InstalledPackage {
+        InstalledPackage {
+            name: name.to_string(),
+            version: Version::parse(version).unwrap(),
+            repository: None,
+            install_type: InstallType::User,
+            install_location: PathBuf::from("/prefix"),
+            installed_at: 0,
+            files: Vec::new(),
+            config_files: BTreeMap::new(),
+            features: BTreeSet::new(),
+            dependencies: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let paxy_home = tempfile::tempdir().unwrap();
+        let filepath = Database::filepath(paxy_home.path());
+        let mut database = Database::load(&filepath).unwrap();
+        assert!(database
+            .packages
+            .is_empty());
+
+        database.insert(InstalledPackage {
+            repository: Some("main".to_string()),
+            install_location: PathBuf::from("/home/paxy_chan/.paxy/pkgs"),
+            installed_at: 1_700_000_000,
+            files: vec![PathBuf::from("bin/hello")],
+            ..installed_package("hello", "1.0.0")
+        });
+        database
+            .save(&filepath)
+            .unwrap();
+
+        assert_eq!(Database::load(&filepath).unwrap(), database);
+        assert_eq!(
+            database
+                .owner_of(Path::new("/home/paxy_chan/.paxy/pkgs/bin/hello"))
+                .map(|package| package
+                    .name
+                    .as_str()),
+            Some("hello")
+        );
+        assert!(!filepath
+            .with_extension("bson.tmp")
+            .exists());
+    }
+
+    #[test]
+    fn ownership_follows_installed_files() {
+        let package = |name: &str, files: &[&str]| InstalledPackage {
+            files: files
+                .iter()
+                .map(PathBuf::from)
+                .collect(),
+            ..installed_package(name, "1.0.0")
+        };
+        let mut database = Database::default();
+        database.insert(package("hello", &["bin/hello", "share/hello"]));
+        database.insert(package("hello", &["bin/hello"]));
+        database.insert(package("goodbye", &["bin/goodbye"]));
+
+        assert_eq!(
+            database
+                .owners
+                .keys()
+                .collect::<Vec<_>>(),
+            [
+                Path::new("/prefix/bin/goodbye"),
+                Path::new("/prefix/bin/hello")
+            ]
+        );
+
+        database.remove("hello");
+        assert!(database
+            .owner_of(Path::new("/prefix/bin/hello"))
+            .is_none());
+        assert!(database
+            .owner_of(Path::new("/prefix/bin/goodbye"))
+            .is_some());
+    }
+
+    #[test]
+    fn find_dependents_through_other_dependents() {
+        let package = |name: &str, dependencies: &[&str]| InstalledPackage {
+            dependencies: dependencies
+                .iter()
+                .map(|dependency| (dependency.to_string(), VersionReq::STAR))
+                .collect(),
+            ..installed_package(name, "1.0.0")
+        };
+        let mut database = Database::default();
+        database.insert(package("libc", &[]));
+        database.insert(package("zlib", &["libc"]));
+        database.insert(package("app", &["zlib"]));
+        database.insert(package("tool", &["app", "libc"]));
+        database.insert(package("other", &[]));
+
+        let names = |packages: Vec<&InstalledPackage>| {
+            packages
+                .into_iter()
+                .map(|package| package.name.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names(database.dependents(&["zlib".to_string()])),
+            ["app", "tool"]
+        );
+        assert_eq!(
+            names(database.dependents(&["libc".to_string(), "zlib".to_string()])),
+            ["app", "tool"]
+        );
+        assert!(database
+            .dependents(&["tool".to_string()])
+            .is_empty());
+    }
+}
+
+// endregion: TESTS
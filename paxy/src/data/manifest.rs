@@ -0,0 +1,865 @@
+//! The package manifest format. A manifest describes a single version of a
+//! package - its metadata, where its sources come from, what it depends on,
+//! and the steps that install it. Manifests may be written in either TOML or
+//! YAML, and are checked against the rules in this module before paxy acts on
+//! them.
+
+lazy_static! {
+    /// Global variable representing supported manifest file extensions
+    pub static ref MANIFEST_FILE_EXTENSIONS: &'static [&'static str] =
+        &["toml", "yaml", "yml"];
+}
+
+/// The version of the manifest format understood by this build of paxy.
+/// Manifests declaring any other `format_version` are rejected.
+pub const MANIFEST_FORMAT_VERSION: u32 = 1;
+
+/// A parsed and validated package manifest.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PackageManifest {
+    /// The version of the manifest format the manifest is written against.
+    pub format_version: u32,
+    pub name: String,
+    pub version: Version,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub license: Option<String>,
+    /// Keywords the package can be found by when searching.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sources: Vec<Source>,
+    /// Names of other packages mapped to the versions of them that are
+    /// acceptable.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub dependencies: BTreeMap<String, VersionReq>,
+    /// Steps run in order to install the package from its sources. Each step
+    /// is written as a single-key map, like `copy = { from = "a", to = "b" }`
+    /// or `run = "make install"`, in both TOML and YAML.
+    #[serde(
+        default,
+        skip_serializing_if = "Vec::is_empty",
+        with = "serde_yaml::with::singleton_map_recursive"
+    )]
+    pub install: Vec<InstallStep>,
+    /// Optional parts of the package, by name. A feature is only installed,
+    /// along with its dependencies, when it is asked for.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub features: BTreeMap<String, Feature>,
+    /// Installed files, relative to the install location, that users are
+    /// expected to edit. Edited configuration files are kept when the package
+    /// is uninstalled.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub config_files: Vec<PathBuf>,
+    /// The total size in bytes of the files the package installs, if known.
+    /// It is shown when planning updates.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub installed_size: Option<u64>,
+    /// The name of the plugin that handles this package, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub plugin: Option<String>,
+}
+
+impl PackageManifest {
+    /// Reads, parses and validates the manifest at the given path. The format
+    /// is chosen from the file extension.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let format =
+            ManifestFormat::from_path(path).context(UnsupportedManifestExtensionSnafu {
+                path: path.to_path_buf(),
+            })?;
+        let text = fs::read_to_string(path).context(ReadManifestSnafu {
+            path: path.to_path_buf(),
+        })?;
+
+        Self::from_str_with_format(&text, format)
+    }
+
+    /// Parses and validates a manifest from text in the given format.
+    pub fn from_str_with_format(text: &str, format: ManifestFormat) -> Result<Self, Error> {
+        let manifest: PackageManifest = match format {
+            ManifestFormat::Toml => serde_path_to_error::deserialize(toml::Deserializer::new(text))
+                .map_err(|error| {
+                    let line = error
+                        .inner()
+                        .span()
+                        .map(|span| line_of_offset(text, span.start));
+                    let message = error
+                        .inner()
+                        .message()
+                        .trim()
+                        .to_string();
+                    malformed(error.path(), line, message)
+                })?,
+            ManifestFormat::Yaml => {
+                serde_path_to_error::deserialize(serde_yaml::Deserializer::from_str(text)).map_err(
+                    |error| {
+                        let line = error
+                            .inner()
+                            .location()
+                            .map(|location| location.line());
+                        let message = error
+                            .inner()
+                            .to_string();
+                        // The field and location are reported separately, so
+                        // drop them from the message
+                        let field_prefix = format!("{}: ", error.path());
+                        let message = message
+                            .strip_prefix(&field_prefix)
+                            .unwrap_or(&message)
+                            .split(" at line ")
+                            .next()
+                            .unwrap_or_default()
+                            .to_string();
+                        malformed(error.path(), line, message)
+                    },
+                )?
+            }
+        };
+
+        manifest
+            .validate()
+            .map_err(|error| match error {
+                Error::InvalidField {
+                    field,
+                    line: None,
+                    reason,
+                } => Error::InvalidField {
+                    line: line_of_field(text, &field),
+                    field,
+                    reason,
+                },
+                error => error,
+            })?;
+
+        Ok(manifest)
+    }
+
+    /// Checks the rules that cannot be expressed through the types alone. The
+    /// returned error does not carry a line number since the manifest may not
+    /// have been read from text.
+    pub fn validate(&self) -> Result<(), Error> {
+        ensure!(
+            self.format_version == MANIFEST_FORMAT_VERSION,
+            UnsupportedFormatVersionSnafu {
+                found: self.format_version,
+                supported: MANIFEST_FORMAT_VERSION,
+            }
+        );
+
+        validate_package_name("name", &self.name)?;
+
+        for dependency_name in self
+            .dependencies
+            .keys()
+        {
+            validate_package_name(&format!("dependencies.{dependency_name}"), dependency_name)?;
+            ensure!(
+                dependency_name != &self.name,
+                InvalidFieldSnafu {
+                    field: format!("dependencies.{dependency_name}"),
+                    line: None,
+                    reason: "a package cannot depend on itself",
+                }
+            );
+        }
+
+        for (index, source) in self
+            .sources
+            .iter()
+            .enumerate()
+        {
+            source.validate(&format!("sources[{index}]"))?;
+        }
+
+        for (index, step) in self
+            .install
+            .iter()
+            .enumerate()
+        {
+            step.validate(&format!("install[{index}]"))?;
+        }
+
+        for (feature_name, feature) in self.features.iter() {
+            feature.validate(&self.name, feature_name)?;
+        }
+
+        for (index, config_file) in self
+            .config_files
+            .iter()
+            .enumerate()
+        {
+            validate_relative_path(&format!("config_files[{index}]"), config_file)?;
+        }
+
+        if let Some(plugin) = &self.plugin {
+            ensure!(
+                !plugin
+                    .trim()
+                    .is_empty(),
+                InvalidFieldSnafu {
+                    field: "plugin",
+                    line: None,
+                    reason: "the plugin name cannot be empty",
+                }
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Returns the dependencies of the package with the given features
+    /// enabled. A package required both by the package and by a feature must
+    /// satisfy both requirements.
+    pub fn dependencies_with(&self, features: &BTreeSet<String>) -> BTreeMap<String, VersionReq> {
+        let mut dependencies = self
+            .dependencies
+            .clone();
+        for feature in self.enabled_features(features) {
+            for (name, requirement) in feature
+                .dependencies
+                .iter()
+            {
+                dependencies
+                    .entry(name.clone())
+                    .and_modify(|existing| {
+                        existing
+                            .comparators
+                            .extend(
+                                requirement
+                                    .comparators
+                                    .iter()
+                                    .cloned(),
+                            )
+                    })
+                    .or_insert_with(|| requirement.clone());
+            }
+        }
+
+        dependencies
+    }
+
+    /// Returns the install steps of the package followed by those of each of
+    /// the given features, in order of feature name.
+    pub fn install_steps_with<'a>(
+        &'a self,
+        features: &'a BTreeSet<String>,
+    ) -> impl Iterator<Item = &'a InstallStep> + 'a {
+        self.install
+            .iter()
+            .chain(
+                self.enabled_features(features)
+                    .flat_map(|feature| {
+                        feature
+                            .install
+                            .iter()
+                    }),
+            )
+    }
+
+    fn enabled_features<'a>(
+        &'a self,
+        features: &'a BTreeSet<String>,
+    ) -> impl Iterator<Item = &'a Feature> + 'a {
+        features
+            .iter()
+            .filter_map(|name| {
+                self.features
+                    .get(name)
+            })
+    }
+}
+
+/// An optional part of a package, with its own dependencies and install steps.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Feature {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub dependencies: BTreeMap<String, VersionReq>,
+    /// Steps run after the install steps of the package when the feature is
+    /// enabled.
+    #[serde(
+        default,
+        skip_serializing_if = "Vec::is_empty",
+        with = "serde_yaml::with::singleton_map_recursive"
+    )]
+    pub install: Vec<InstallStep>,
+}
+
+impl Feature {
+    fn validate(&self, package_name: &str, feature_name: &str) -> Result<(), Error> {
+        let field = format!("features.{feature_name}");
+        validate_feature_name(&field, feature_name)?;
+
+        for dependency_name in self
+            .dependencies
+            .keys()
+        {
+            validate_package_name(
+                &format!("{field}.dependencies.{dependency_name}"),
+                dependency_name,
+            )?;
+            ensure!(
+                dependency_name != package_name,
+                InvalidFieldSnafu {
+                    field: format!("{field}.dependencies.{dependency_name}"),
+                    line: None,
+                    reason: "a package cannot depend on itself",
+                }
+            );
+        }
+
+        for (index, step) in self
+            .install
+            .iter()
+            .enumerate()
+        {
+            step.validate(&format!("{field}.install[{index}]"))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the path of the manifest inside the given directory, which is the
+/// first of `manifest.toml`, `manifest.yaml` and `manifest.yml` that exists.
+pub fn manifest_filepath_in(dirpath: &Path) -> Option<PathBuf> {
+    MANIFEST_FILE_EXTENSIONS
+        .iter()
+        .map(|extension| dirpath.join(format!("manifest.{extension}")))
+        .find(|filepath| filepath.is_file())
+}
+
+/// The text formats a manifest can be written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ManifestFormat {
+    Toml,
+    Yaml,
+}
+
+impl ManifestFormat {
+    /// Determines the manifest format from the extension of the given path.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path
+            .extension()?
+            .to_string_lossy()
+            .to_lowercase();
+        match extension.as_str() {
+            "toml" => Some(Self::Toml),
+            "yaml" | "yml" => Some(Self::Yaml),
+            _ => None,
+        }
+    }
+}
+
+/// Where the sources of a package come from. Exactly one of `path`, `url` and
+/// `git` must be given.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Source {
+    /// A local file or directory. Relative paths are resolved against the
+    /// directory containing the manifest.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    /// A single file to download.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<Url>,
+    /// A git repository to clone.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git: Option<Url>,
+    /// Further URLs serving the same file or git repository, tried in turn
+    /// when `url` or `git` fails or times out.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mirrors: Vec<Url>,
+    /// A directory, relative to the package's source directory, to place the
+    /// source in. Defaults to the source directory itself.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub destination: Option<PathBuf>,
+}
+
+impl Source {
+    /// Returns the location of the source, or `None` if it has none, which
+    /// only happens for a source that was not validated.
+    pub fn location(&self) -> Option<SourceLocation<'_>> {
+        match (&self.path, &self.url, &self.git) {
+            (Some(path), _, _) => Some(SourceLocation::Path(path)),
+            (_, Some(url), _) => Some(SourceLocation::Url(url)),
+            (_, _, Some(git)) => Some(SourceLocation::Git(git)),
+            (None, None, None) => None,
+        }
+    }
+
+    /// Returns the URL of the source followed by its mirrors, or nothing for
+    /// a local source.
+    pub fn urls(&self) -> Vec<Url> {
+        self.url
+            .iter()
+            .chain(&self.git)
+            .chain(&self.mirrors)
+            .cloned()
+            .collect()
+    }
+
+    fn validate(&self, field: &str) -> Result<(), Error> {
+        let location_count = [self.path.is_some(), self.url.is_some(), self.git.is_some()]
+            .into_iter()
+            .filter(|is_some| *is_some)
+            .count();
+        ensure!(
+            location_count == 1,
+            InvalidFieldSnafu {
+                field,
+                line: None,
+                reason: "exactly one of `path`, `url` and `git` must be given",
+            }
+        );
+
+        ensure!(
+            self.mirrors
+                .is_empty()
+                || self.path.is_none(),
+            InvalidFieldSnafu {
+                field: format!("{field}.mirrors"),
+                line: None,
+                reason: "mirrors can only be given along with `url` or `git`",
+            }
+        );
+
+        if let Some(destination) = &self.destination {
+            validate_relative_path(&format!("{field}.destination"), destination)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A borrowed view of the location of a [`Source`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SourceLocation<'a> {
+    Path(&'a Path),
+    Url(&'a Url),
+    Git(&'a Url),
+}
+
+/// A single step of installing a package.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum InstallStep {
+    /// Copy a file or directory from the source directory to a path relative
+    /// to the installation prefix.
+    Copy { from: PathBuf, to: PathBuf },
+    /// Run a shell command from within the source directory.
+    Run(String),
+}
+
+impl InstallStep {
+    fn validate(&self, field: &str) -> Result<(), Error> {
+        match self {
+            InstallStep::Copy { from, to } => {
+                validate_relative_path(&format!("{field}.copy.from"), from)?;
+                validate_relative_path(&format!("{field}.copy.to"), to)?;
+            }
+            InstallStep::Run(command) => {
+                ensure!(
+                    !command
+                        .trim()
+                        .is_empty(),
+                    InvalidFieldSnafu {
+                        field: format!("{field}.run"),
+                        line: None,
+                        reason: "the command cannot be empty",
+                    }
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns whether the name follows the rules for package names given below,
+/// which, among other things, keep it from being empty, `.` or `..`.
+pub(crate) fn is_valid_package_name(name: &str) -> bool {
+    let mut characters = name.chars();
+    characters
+        .next()
+        .is_some_and(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+        && characters.all(|c| {
+            c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '-' | '_' | '.' | '+')
+        })
+}
+
+/// Package names are restricted to lowercase ASCII letters, digits and
+/// `-_.+`, and must start with a letter or digit, so that they are safe to use
+/// as file names and on the commandline.
+fn validate_package_name(field: &str, name: &str) -> Result<(), Error> {
+    ensure!(
+        is_valid_package_name(name),
+        InvalidFieldSnafu {
+            field,
+            line: None,
+            reason: format!(
+                "'{name}' is not a valid package name. Use lowercase letters, digits and '-_.+', \
+                 starting with a letter or digit"
+            ),
+        }
+    );
+
+    Ok(())
+}
+
+/// Feature names follow the same rules as package names, so that they can be
+/// written in a list like `name[feature1,feature2]` on the commandline.
+fn validate_feature_name(field: &str, name: &str) -> Result<(), Error> {
+    validate_package_name(field, name).map_err(|_| {
+        InvalidFieldSnafu {
+            field,
+            line: None,
+            reason: format!(
+                "'{name}' is not a valid feature name. Use lowercase letters, digits and '-_.+', \
+                 starting with a letter or digit"
+            ),
+        }
+        .build()
+    })
+}
+
+/// Paths inside a package must be relative and must not climb out of the
+/// directory they are relative to.
+fn validate_relative_path(field: &str, path: &Path) -> Result<(), Error> {
+    let is_valid = !path
+        .as_os_str()
+        .is_empty()
+        && path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+    ensure!(
+        is_valid,
+        InvalidFieldSnafu {
+            field,
+            line: None,
+            reason: format!(
+                "{path:?} must be a relative path that stays within its parent directory"
+            ),
+        }
+    );
+
+    Ok(())
+}
+
+fn malformed(path: &serde_path_to_error::Path, line: Option<usize>, message: String) -> Error {
+    let field = path.to_string();
+    Error::Malformed {
+        field: (field != ".").then_some(field),
+        line,
+        message,
+    }
+}
+
+/// Converts a byte offset into a 1-based line number.
+fn line_of_offset(text: &str, offset: usize) -> usize {
+    text[..offset.min(text.len())]
+        .matches('\n')
+        .count()
+        + 1
+}
+
+/// Finds the 1-based line on which a field such as `sources[1].url` or
+/// `dependencies.foo` is written, by looking for each key of the path in turn.
+/// This works for both TOML and YAML since it only relies on keys being
+/// followed by `=` or `:`, or appearing in a table header.
+fn line_of_field(text: &str, field: &str) -> Option<usize> {
+    let keys = field
+        .split('.')
+        .map(|key| {
+            key.split('[')
+                .next()
+                .unwrap_or(key)
+        })
+        .filter(|key| !key.is_empty());
+
+    let lines: Vec<&str> = text
+        .lines()
+        .collect();
+    let mut current_line = None;
+    for key in keys {
+        let start = current_line.unwrap_or(0);
+        match lines[start..]
+            .iter()
+            .position(|line| is_key_line(line, key))
+        {
+            Some(offset) => current_line = Some(start + offset),
+            // Keys of inline tables share the line of their parent, which is the
+            // most specific line that can be found
+            None => break,
+        }
+    }
+
+    current_line.map(|line| line + 1)
+}
+
+fn is_key_line(line: &str, key: &str) -> bool {
+    let line = line.trim_start();
+    let line = line
+        .strip_prefix("- ")
+        .unwrap_or(line)
+        .trim_start();
+
+    if line.starts_with('[') {
+        return line
+            .trim_matches(|c| c == '[' || c == ']')
+            .rsplit('.')
+            .next()
+            .is_some_and(|header| header.trim() == key);
+    }
+
+    let rest = if let Some(rest) = line.strip_prefix(&format!("\"{key}\"")) {
+        rest
+    } else if let Some(rest) = line.strip_prefix(&format!("'{key}'")) {
+        rest
+    } else if let Some(rest) = line.strip_prefix(key) {
+        rest
+    } else {
+        return false;
+    };
+    let rest = rest.trim_start();
+    rest.starts_with('=') || rest.starts_with(':')
+}
+
+fn describe_location(field: &Option<String>, line: &Option<usize>) -> String {
+    match (field, line) {
+        (Some(field), Some(line)) => format!(" in `{field}` at line {line}"),
+        (Some(field), None) => format!(" in `{field}`"),
+        (None, Some(line)) => format!(" at line {line}"),
+        (None, None) => String::new(),
+    }
+}
+
+// region: ERRORS
+
+#[derive(Debug, Snafu)]
+#[snafu(visibility(pub(crate)))]
+#[non_exhaustive]
+pub enum Error {
+    #[non_exhaustive]
+    #[snafu(display("could not read the manifest at {path:?}: {source}"))]
+    ReadManifest {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display(
+        "the manifest at {path:?} does not have one of the supported extensions {:?}",
+        *MANIFEST_FILE_EXTENSIONS
+    ))]
+    UnsupportedManifestExtension { path: PathBuf },
+
+    #[non_exhaustive]
+    #[snafu(display("malformed manifest{}: {message}", describe_location(field, line)))]
+    Malformed {
+        field: Option<String>,
+        line: Option<usize>,
+        message: String,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display(
+        "invalid manifest{}: {reason}",
+        describe_location(&Some(field.clone()), line)
+    ))]
+    InvalidField {
+        field: String,
+        line: Option<usize>,
+        reason: String,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display(
+        "the manifest format version {found} is not supported. The supported version is \
+         {supported}"
+    ))]
+    UnsupportedFormatVersion { found: u32, supported: u32 },
+}
+
+// endregion: ERRORS
+
+// region: IMPORTS
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+use lazy_static::lazy_static;
+use semver::{Version, VersionReq};
+use serde::{Deserialize, Serialize};
+use snafu::{ensure, OptionExt, ResultExt, Snafu};
+use url::Url;
+
+// endregion: IMPORTS
+
+// region: TESTS
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOML_MANIFEST: &str = r#"
+format_version = 1
+name = "ripgrep"
+version = "14.1.0"
+description = "Recursively search directories for a regex pattern"
+license = "MIT"
+
+[dependencies]
+pcre2 = ">=10.42"
+
+[[sources]]
+path = "src"
+
+[[install]]
+copy = { from = "rg", to = "bin/rg" }
+
+[[install]]
+run = "strip bin/rg"
+"#;
+
+    #[test]
+    fn parse_toml_manifest() {
+        let manifest =
+            PackageManifest::from_str_with_format(TOML_MANIFEST, ManifestFormat::Toml).unwrap();
+        assert_eq!(manifest.name, "ripgrep");
+        assert_eq!(manifest.version, Version::new(14, 1, 0));
+        assert_eq!(
+            manifest.dependencies["pcre2"],
+            VersionReq::parse(">=10.42").unwrap()
+        );
+        assert_eq!(
+            manifest.sources[0].location(),
+            Some(SourceLocation::Path(Path::new("src")))
+        );
+        assert_eq!(
+            manifest.install[1],
+            InstallStep::Run("strip bin/rg".to_string())
+        );
+    }
+
+    #[test]
+    fn parse_yaml_manifest() {
+        let text = r#"
+format_version: 1
+name: ripgrep
+version: 14.1.0
+sources:
+  - git: https://github.com/BurntSushi/ripgrep.git
+install:
+  - copy:
+      from: rg
+      to: bin/rg
+"#;
+        let manifest = PackageManifest::from_str_with_format(text, ManifestFormat::Yaml).unwrap();
+        assert_eq!(manifest.version, Version::new(14, 1, 0));
+        assert!(matches!(
+            manifest.sources[0].location(),
+            Some(SourceLocation::Git(_))
+        ));
+        assert!(matches!(manifest.install[0], InstallStep::Copy { .. }));
+    }
+
+    #[test]
+    fn features_add_dependencies_and_install_steps() {
+        let text = format!(
+            r#"{TOML_MANIFEST}
+[features.completions]
+install = [{{ copy = {{ from = "complete", to = "share/rg" }} }}]
+
+[features.gui]
+description = "A graphical front-end"
+dependencies = {{ pcre2 = "<11", gtk = "^4" }}
+"#
+        );
+        let manifest = PackageManifest::from_str_with_format(&text, ManifestFormat::Toml).unwrap();
+
+        let features = BTreeSet::from(["gui".to_string()]);
+        let dependencies = manifest.dependencies_with(&features);
+        assert_eq!(
+            dependencies["pcre2"],
+            VersionReq::parse(">=10.42, <11").unwrap()
+        );
+        assert!(dependencies.contains_key("gtk"));
+        assert_eq!(
+            manifest
+                .install_steps_with(&BTreeSet::from(["completions".to_string()]))
+                .count(),
+            3
+        );
+
+        let text = text.replace("[features.gui]", "[features.GUI]");
+        let error = PackageManifest::from_str_with_format(&text, ManifestFormat::Toml).unwrap_err();
+        assert!(matches!(
+            error,
+            Error::InvalidField { ref field, .. } if field == "features.GUI"
+        ));
+    }
+
+    #[test]
+    fn reject_bad_version_with_field_and_line() {
+        let text = TOML_MANIFEST.replace("\"14.1.0\"", "\"fourteen\"");
+        let error = PackageManifest::from_str_with_format(&text, ManifestFormat::Toml).unwrap_err();
+        assert!(matches!(
+            error,
+            Error::Malformed { ref field, line: Some(4), .. } if field.as_deref() == Some("version")
+        ));
+    }
+
+    #[test]
+    fn reject_missing_field() {
+        let text = TOML_MANIFEST.replace("name = \"ripgrep\"\n", "");
+        let error = PackageManifest::from_str_with_format(&text, ManifestFormat::Toml).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("missing field `name`"));
+    }
+
+    #[test]
+    fn reject_source_with_two_locations() {
+        let text = TOML_MANIFEST.replace(
+            "path = \"src\"",
+            "path = \"src\"\nurl = \"https://example.com/rg.tar\"",
+        );
+        let error = PackageManifest::from_str_with_format(&text, ManifestFormat::Toml).unwrap_err();
+        assert!(matches!(
+            error,
+            Error::InvalidField { ref field, line: Some(11), .. } if field == "sources[0]"
+        ));
+    }
+
+    #[test]
+    fn reject_escaping_install_path() {
+        let text = TOML_MANIFEST.replace("bin/rg\" }", "../../bin/rg\" }");
+        let error = PackageManifest::from_str_with_format(&text, ManifestFormat::Toml).unwrap_err();
+        assert!(matches!(
+            error,
+            Error::InvalidField { ref field, line: Some(15), .. } if field == "install[0].copy.to"
+        ));
+    }
+
+    #[test]
+    fn reject_unsupported_format_version() {
+        let text = TOML_MANIFEST.replace("format_version = 1", "format_version = 2");
+        let error = PackageManifest::from_str_with_format(&text, ManifestFormat::Toml).unwrap_err();
+        assert!(matches!(
+            error,
+            Error::UnsupportedFormatVersion { found: 2, .. }
+        ));
+    }
+}
+
+// endregion: TESTS
@@ -0,0 +1,580 @@
+//! Mirrors are URLs that serve the same repository or file. They are tried
+//! one after another until one of them works, either in the order they are
+//! listed or the fastest to answer first. How each mirror fared is recorded
+//! in `~/.paxy/mirrors.bson`, so that a mirror that keeps failing is left
+//! until last for a while, instead of being waited on every time.
+
+/// How long to wait for a mirror to accept a connection.
+pub const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long a single download from a mirror may take.
+pub const TRANSFER_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// How long a mirror may go without sending or accepting any data during a
+/// git fetch before it is given up on.
+pub const STALL_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How many failures in a row make a mirror dead.
+pub const FAILURES_UNTIL_DEAD: u32 = 3;
+
+/// How many seconds after its last failure a dead mirror is given another
+/// chance.
+pub const DEAD_MIRROR_RETRY_AFTER: u64 = 60 * 60;
+
+/// The order in which mirrors are tried.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MirrorOrder {
+    /// In the order they are listed.
+    #[default]
+    Listed,
+    /// The quickest to accept a connection first.
+    Fastest,
+}
+
+impl fmt::Display for MirrorOrder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            MirrorOrder::Listed => "listed",
+            MirrorOrder::Fastest => "fastest",
+        })
+    }
+}
+
+impl FromStr for MirrorOrder {
+    type Err = data::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "listed" => Ok(MirrorOrder::Listed),
+            "fastest" => Ok(MirrorOrder::Fastest),
+            _ => data::InvalidVariantSnafu {
+                value,
+                enum_name: "MirrorOrder",
+                allowed_values: vec!["listed".to_string(), "fastest".to_string()],
+            }
+            .fail(),
+        }
+    }
+}
+
+/// How a mirror fared when last used.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MirrorHealth {
+    /// Failures since the last success.
+    pub failures: u32,
+    /// Seconds since the Unix epoch at which the mirror last failed.
+    pub last_failure_at: Option<u64>,
+    /// Seconds since the Unix epoch at which the mirror last worked.
+    pub last_success_at: Option<u64>,
+}
+
+impl MirrorHealth {
+    /// Returns whether the mirror failed too often in a row, too recently, to
+    /// be tried before the others.
+    pub fn is_dead(&self, now: u64) -> bool {
+        self.failures >= FAILURES_UNTIL_DEAD
+            && self
+                .last_failure_at
+                .is_some_and(|last_failure_at| {
+                    now.saturating_sub(last_failure_at) < DEAD_MIRROR_RETRY_AFTER
+                })
+    }
+}
+
+/// The health of every mirror used so far, keyed by URL.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MirrorHealthRecords {
+    #[serde(default)]
+    pub mirrors: BTreeMap<String, MirrorHealth>,
+}
+
+/// Serializes reading and writing the health records, which mirrors used
+/// concurrently would otherwise overwrite.
+static RECORDS_LOCK: Mutex<()> = Mutex::new(());
+
+impl MirrorHealthRecords {
+    /// Returns the path of the health records inside the given paxy home.
+    pub fn filepath(paxy_home: &Path) -> PathBuf {
+        paxy_home.join("mirrors.bson")
+    }
+
+    /// Loads the health records from the given file. A missing file holds no
+    /// records.
+    pub fn load(filepath: &Path) -> Result<Self, Error> {
+        if !filepath.is_file() {
+            return Ok(Self::default());
+        }
+
+        let file = File::open(filepath).context(ReadSnafu { path: filepath })?;
+        let document = Document::from_reader(file).context(DecodeSnafu { path: filepath })?;
+        bson::from_document(document).context(DeserializeSnafu { path: filepath })
+    }
+
+    /// Writes the health records to the given file atomically.
+    pub fn save(&self, filepath: &Path) -> Result<(), Error> {
+        let document = bson::to_document(self).context(SerializeSnafu {})?;
+        let mut buffer = Vec::new();
+        document
+            .to_writer(&mut buffer)
+            .context(EncodeSnafu {})?;
+        data::write_atomically(filepath, &buffer).context(WriteSnafu { path: filepath })
+    }
+
+    /// Returns the health of the mirror at the given URL.
+    pub fn get(&self, url: &Url) -> MirrorHealth {
+        self.mirrors
+            .get(url.as_str())
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Records whether using the mirror at the given URL worked.
+    pub fn record(&mut self, url: &Url, worked: bool, now: u64) {
+        let health = self
+            .mirrors
+            .entry(url.to_string())
+            .or_default();
+        if worked {
+            health.failures = 0;
+            health.last_success_at = Some(now);
+        } else {
+            health.failures += 1;
+            health.last_failure_at = Some(now);
+        }
+    }
+
+    /// Returns the given URLs in the order to try them: the live mirrors in
+    /// the given order, followed by the dead ones in the same order.
+    pub fn order(&self, urls: &[Url], order: MirrorOrder, now: u64) -> Vec<Url> {
+        let mut urls = urls.to_vec();
+        if order == MirrorOrder::Fastest && urls.len() > 1 {
+            let latencies: Vec<Option<Duration>> = thread::scope(|scope| {
+                let handles: Vec<_> = urls
+                    .iter()
+                    .map(|url| scope.spawn(move || connect_latency(url)))
+                    .collect();
+                handles
+                    .into_iter()
+                    .map(|handle| {
+                        handle
+                            .join()
+                            .ok()
+                            .flatten()
+                    })
+                    .collect()
+            });
+            let mut ranked: Vec<(Option<Duration>, Url)> = latencies
+                .into_iter()
+                .zip(urls)
+                .collect();
+            // Unreachable mirrors go last
+            ranked.sort_by_key(|(latency, _)| (latency.is_none(), *latency));
+            urls = ranked
+                .into_iter()
+                .map(|(_, url)| url)
+                .collect();
+        }
+        // Stable, so that the order within live and dead mirrors is kept
+        urls.sort_by_key(|url| {
+            self.get(url)
+                .is_dead(now)
+        });
+
+        urls
+    }
+}
+
+/// Every failure met when trying a list of mirrors, in the order the mirrors
+/// were tried.
+#[derive(Debug)]
+pub struct Failures<E>(pub Vec<(Url, E)>);
+
+impl<E: fmt::Display> fmt::Display for Failures<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0.as_slice() {
+            [(_, error)] => write!(f, "{error}"),
+            failures => {
+                write!(f, "every mirror failed:")?;
+                for (url, error) in failures {
+                    write!(f, "\n    {url}: {error}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl<E: std::error::Error> std::error::Error for Failures<E> {}
+
+/// Runs the operation against each of the given mirrors in turn, until it
+/// succeeds on one of them, and records how each mirror tried fared. Only the
+/// errors `is_mirror_fault` picks out, such as network and HTTP errors, count
+/// against a mirror; others, such as a failure to write locally, still move on
+/// to the next mirror, but leave the record of the mirror alone. Failing to
+/// record is only warned about.
+pub fn try_mirrors<T, E>(
+    paxy_home: &Path,
+    urls: &[Url],
+    order: MirrorOrder,
+    mut operation: impl FnMut(&Url) -> Result<T, E>,
+    is_mirror_fault: impl Fn(&E) -> bool,
+) -> Result<T, Failures<E>> {
+    let filepath = MirrorHealthRecords::filepath(paxy_home);
+    let urls = {
+        let _lock = RECORDS_LOCK
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        MirrorHealthRecords::load(&filepath)
+            .unwrap_or_default()
+            .order(urls, order, installed::now())
+    };
+
+    let mut failures = Vec::new();
+    for url in urls {
+        let result = operation(&url);
+        let worked = match &result {
+            Ok(_) => Some(true),
+            Err(error) if is_mirror_fault(error) => Some(false),
+            Err(_) => None,
+        };
+        if let Some(worked) = worked {
+            let _lock = RECORDS_LOCK
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            let recorded = MirrorHealthRecords::load(&filepath).and_then(|mut records| {
+                records.record(&url, worked, installed::now());
+                records.save(&filepath)
+            });
+            if let Err(error) = recorded {
+                tracing::warn!("Could not record the health of the mirror {url}: {error}");
+            }
+        }
+        match result {
+            Ok(value) => return Ok(value),
+            Err(error) => {
+                tracing::debug!("The mirror {url} failed");
+                failures.push((url, error));
+            }
+        }
+    }
+
+    Err(Failures(failures))
+}
+
+/// Returns how long the host of the given URL takes to accept a connection,
+/// or `None` if it does not within [`CONNECT_TIMEOUT`]. Local URLs take no
+/// time at all, as long as what they point to exists.
+pub fn connect_latency(url: &Url) -> Option<Duration> {
+    if url.scheme() == "file" {
+        return url
+            .to_file_path()
+            .is_ok_and(|path| path.exists())
+            .then_some(Duration::ZERO);
+    }
+    let address = url
+        .socket_addrs(|| match url.scheme() {
+            "http" => Some(80),
+            "https" => Some(443),
+            "ssh" => Some(22),
+            "git" => Some(9418),
+            _ => None,
+        })
+        .ok()?
+        .into_iter()
+        .next()?;
+
+    let started_at = Instant::now();
+    TcpStream::connect_timeout(&address, CONNECT_TIMEOUT).ok()?;
+    Some(started_at.elapsed())
+}
+
+/// Returns whether the host of the given URL accepts a connection within
+/// [`CONNECT_TIMEOUT`], or for a local URL, whether what it points to exists.
+/// Other URLs without a host, or with a scheme whose port is not known, are
+/// assumed to be reachable.
+pub fn is_reachable(url: &Url) -> bool {
+    if url.scheme() == "file" {
+        return connect_latency(url).is_some();
+    }
+    let has_known_port = url
+        .port_or_known_default()
+        .is_some()
+        || matches!(url.scheme(), "ssh" | "git");
+    !url.has_host() || !has_known_port || connect_latency(url).is_some()
+}
+
+/// Returns options for fetching from the git repository at the given URL
+/// that give up on a transfer taking longer than [`TRANSFER_TIMEOUT`], or on
+/// a server that stalls for longer than [`STALL_TIMEOUT`]. Fails if the URL
+/// cannot be reached at all.
+pub fn git_fetch_options<'a>(url: &Url) -> Result<FetchOptions<'a>, git2::Error> {
+    set_git_server_timeouts();
+    if !is_reachable(url) {
+        return Err(git2::Error::new(
+            git2::ErrorCode::GenericError,
+            git2::ErrorClass::Net,
+            format!(
+                "could not reach {url} within {} seconds",
+                CONNECT_TIMEOUT.as_secs()
+            ),
+        ));
+    }
+
+    let started_at = Instant::now();
+    let mut callbacks = RemoteCallbacks::new();
+    callbacks.transfer_progress(move |_| started_at.elapsed() < TRANSFER_TIMEOUT);
+    let mut fetch_options = FetchOptions::new();
+    fetch_options.remote_callbacks(callbacks);
+
+    Ok(fetch_options)
+}
+
+/// Returns whether a git fetch failed because of the mirror it fetched from,
+/// rather than because of the local repository: the server could not be
+/// reached, broke off or stalled, or answered with an error.
+pub fn is_git_mirror_fault(error: &git2::Error) -> bool {
+    // Giving up on a transfer that takes too long aborts it from a callback
+    error.code() == git2::ErrorCode::User
+        || matches!(
+            error.class(),
+            git2::ErrorClass::Net
+                | git2::ErrorClass::Http
+                | git2::ErrorClass::Ssl
+                | git2::ErrorClass::Ssh
+        )
+}
+
+/// Makes libgit2 give up on connecting to a server after [`CONNECT_TIMEOUT`],
+/// and on reading from or writing to one after [`STALL_TIMEOUT`]. Without
+/// these, a server that stops sending data blocks a fetch for good, since the
+/// transfer progress callback only runs when data arrives. git2 does not wrap
+/// these options, so they are set through libgit2 directly, once per process.
+fn set_git_server_timeouts() {
+    // Follow `GIT_OPT_SET_OWNER_VALIDATION` in libgit2's `git_libgit2_opt_t`,
+    // after the two home directory options
+    const GIT_OPT_SET_SERVER_CONNECT_TIMEOUT: c_int =
+        libgit2_sys::GIT_OPT_SET_OWNER_VALIDATION as c_int + 3;
+    const GIT_OPT_SET_SERVER_TIMEOUT: c_int =
+        libgit2_sys::GIT_OPT_SET_OWNER_VALIDATION as c_int + 5;
+    static ONCE: Once = Once::new();
+
+    ONCE.call_once(|| {
+        libgit2_sys::init();
+        for (option, timeout) in [
+            (GIT_OPT_SET_SERVER_CONNECT_TIMEOUT, CONNECT_TIMEOUT),
+            (GIT_OPT_SET_SERVER_TIMEOUT, STALL_TIMEOUT),
+        ] {
+            // SAFETY: both options take a single int, the timeout in
+            // milliseconds, and libgit2 has been initialized
+            let result =
+                unsafe { libgit2_sys::git_libgit2_opts(option, timeout.as_millis() as c_int) };
+            if result < 0 {
+                tracing::warn!("Could not set a timeout for git servers");
+            }
+        }
+    });
+}
+
+// region: ERRORS
+
+#[derive(Debug, Snafu)]
+#[snafu(visibility(pub(crate)))]
+#[non_exhaustive]
+pub enum Error {
+    #[non_exhaustive]
+    #[snafu(display("could not read the mirror health records at {path:?}: {source}"))]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("could not decode the mirror health records at {path:?}: {source}"))]
+    Decode {
+        path: PathBuf,
+        source: bson::de::Error,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("the mirror health records at {path:?} are invalid: {source}"))]
+    Deserialize {
+        path: PathBuf,
+        source: bson::de::Error,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("could not serialize the mirror health records: {source}"))]
+    Serialize {
+        #[snafu(source(from(bson::ser::Error, Box::new)))]
+        source: Box<bson::ser::Error>,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("could not encode the mirror health records: {source}"))]
+    Encode {
+        #[snafu(source(from(bson::ser::Error, Box::new)))]
+        source: Box<bson::ser::Error>,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("could not write the mirror health records to {path:?}: {source}"))]
+    Write {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+}
+
+// endregion: ERRORS
+
+// region: IMPORTS
+
+use std::{
+    collections::BTreeMap,
+    ffi::c_int,
+    fmt,
+    fs::File,
+    net::TcpStream,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Mutex, Once, PoisonError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use bson::Document;
+use git2::{FetchOptions, RemoteCallbacks};
+use serde::{Deserialize, Serialize};
+use snafu::{ResultExt, Snafu};
+use url::Url;
+
+use crate::data::{self, installed};
+
+// endregion: IMPORTS
+
+// region: TESTS
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fail_over_and_leave_dead_mirrors_until_last() {
+        let paxy_home = tempfile::tempdir().unwrap();
+        let urls: Vec<Url> = ["https://a.example.com/", "https://b.example.com/"]
+            .iter()
+            .map(|url| Url::parse(url).unwrap())
+            .collect();
+
+        let mut tried = Vec::new();
+        for _ in 0..FAILURES_UNTIL_DEAD {
+            tried.clear();
+            let result = try_mirrors(
+                paxy_home.path(),
+                &urls,
+                MirrorOrder::Listed,
+                |url| {
+                    tried.push(url.to_string());
+                    match url.host_str() {
+                        Some("a.example.com") => Err("down"),
+                        _ => Ok(url.to_string()),
+                    }
+                },
+                |_| true,
+            );
+            assert_eq!(result.unwrap(), "https://b.example.com/");
+        }
+        assert_eq!(tried, ["https://a.example.com/", "https://b.example.com/"]);
+
+        // The first mirror is now dead, so it is only tried once the other
+        // fails too
+        let records =
+            MirrorHealthRecords::load(&MirrorHealthRecords::filepath(paxy_home.path())).unwrap();
+        assert!(records
+            .get(&urls[0])
+            .is_dead(installed::now()));
+        assert!(!records
+            .get(&urls[0])
+            .is_dead(installed::now() + DEAD_MIRROR_RETRY_AFTER));
+        let failures = try_mirrors(
+            paxy_home.path(),
+            &urls,
+            MirrorOrder::Listed,
+            |url| {
+                Err::<(), _>(format!(
+                    "{} is down",
+                    url.host_str()
+                        .unwrap()
+                ))
+            },
+            |_| true,
+        )
+        .unwrap_err();
+        assert_eq!(
+            failures.to_string(),
+            "every mirror failed:\n    https://b.example.com/: b.example.com is down\n    \
+             https://a.example.com/: a.example.com is down"
+        );
+
+        assert_eq!(
+            "fastest"
+                .parse::<MirrorOrder>()
+                .unwrap(),
+            MirrorOrder::Fastest
+        );
+        assert!("quickest"
+            .parse::<MirrorOrder>()
+            .is_err());
+    }
+
+    #[test]
+    fn fail_over_without_blaming_mirrors_for_other_errors() {
+        let paxy_home = tempfile::tempdir().unwrap();
+        let urls: Vec<Url> = ["https://a.example.com/", "https://b.example.com/"]
+            .iter()
+            .map(|url| Url::parse(url).unwrap())
+            .collect();
+
+        for _ in 0..FAILURES_UNTIL_DEAD {
+            let result = try_mirrors(
+                paxy_home.path(),
+                &urls,
+                MirrorOrder::Listed,
+                |url| match url.host_str() {
+                    Some("a.example.com") => Err("disk full"),
+                    _ => Ok(()),
+                },
+                |error| *error != "disk full",
+            );
+            assert!(result.is_ok());
+        }
+
+        let records =
+            MirrorHealthRecords::load(&MirrorHealthRecords::filepath(paxy_home.path())).unwrap();
+        assert_eq!(
+            records
+                .get(&urls[0])
+                .failures,
+            0
+        );
+        assert!(!records
+            .get(&urls[0])
+            .is_dead(installed::now()));
+    }
+
+    #[test]
+    fn count_unreachable_git_servers_against_mirrors() {
+        let missing_dirpath = tempfile::tempdir()
+            .unwrap()
+            .path()
+            .join("missing");
+        let url = Url::from_directory_path(&missing_dirpath).unwrap();
+        let error = git_fetch_options(&url)
+            .err()
+            .unwrap();
+        assert!(is_git_mirror_fault(&error));
+        assert!(!is_git_mirror_fault(&git2::Error::from_str("disk full")));
+    }
+}
+
+// endregion: TESTS
@@ -1,4 +1,50 @@
-// TODO: Write code here
+//! Data that paxy reads and writes - its own configuration, package
+//! manifests, and the layout of repositories on disk.
+
+/// Returns the directory in which paxy keeps its own state, i.e. `~/.paxy`.
+pub fn paxy_home() -> PathBuf {
+    let mut paxy_home: PathBuf = match home::home_dir() {
+        Some(path) => path,
+        None => panic!("Impossible to get your home dir!"),
+    };
+    paxy_home.push(".paxy");
+    paxy_home
+}
+
+/// Returns the directory under which repositories are kept, one directory
+/// per repository.
+pub fn repositories_dirpath(paxy_home: &Path) -> PathBuf {
+    paxy_home.join("repos")
+}
+
+/// Writes the contents to a temporary file next to the given path, flushes it
+/// to disk, and then renames it over the given path. Readers therefore see
+/// either the old or the new contents in full, even if paxy is interrupted
+/// midway.
+pub fn write_atomically(filepath: &Path, contents: &[u8]) -> io::Result<()> {
+    if let Some(parent) = filepath.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut temporary_filepath = filepath
+        .as_os_str()
+        .to_owned();
+    temporary_filepath.push(".tmp");
+    let temporary_filepath = PathBuf::from(temporary_filepath);
+
+    let mut file = File::create(&temporary_filepath)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    fs::rename(&temporary_filepath, filepath)
+}
+
+/// Returns the SHA-256 checksum of the file at the given path as a lowercase
+/// hexadecimal string.
+pub fn sha256_of_file(filepath: &Path) -> io::Result<String> {
+    let mut file = File::open(filepath)?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
 
 // region: ERRORS
 
@@ -21,10 +67,16 @@ pub enum Error {
 // region: IMPORTS
 
 // use std::{fmt, str::FromStr};
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
 
 // use serde::{Deserialize, Serialize};
 // use serde_aux::prelude::*;
 // use speedy::{Readable, Writable};
+use sha2::{Digest, Sha256};
 use snafu::Snafu;
 
 // endregion: IMPORTS
@@ -32,6 +84,15 @@ use snafu::Snafu;
 // region: EXTERNAL-SUBMODULES
 
 // pub mod some_module;
-mod config;
+pub mod cache;
+pub mod config;
+pub mod generation;
+pub mod installed;
+pub mod manifest;
+pub mod mirrors;
+pub mod registry;
+pub mod repository;
+pub mod repository_index;
+pub mod search_index;
 
 // endregion: EXTERNAL-SUBMODULES
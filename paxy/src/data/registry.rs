@@ -0,0 +1,301 @@
+//! The registry of installed repositories, kept at `~/.paxy/repos.bson`. It
+//! maps the name of each repository to where it comes from, while the
+//! contents of the repository are kept in `~/.paxy/repos/<name>`, or for a
+//! local repository, read in place from its directory.
+
+/// A repository as recorded in the registry.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RegisteredRepository {
+    pub url: String,
+    /// How the repository is fetched from its URL.
+    #[serde(default)]
+    pub kind: RepositoryKind,
+    /// Which repository a package is installed from when several offer it.
+    /// Higher priorities win, and repositories default to 0.
+    #[serde(default)]
+    pub priority: i32,
+    /// Further URLs serving the same repository, tried after `url` when it
+    /// fails or times out.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mirrors: Vec<String>,
+    /// The order in which `url` and the mirrors are tried.
+    #[serde(default)]
+    pub mirror_order: MirrorOrder,
+    /// Seconds since the Unix epoch at which the repository was installed.
+    #[serde(default)]
+    pub added_at: u64,
+    /// Seconds since the Unix epoch at which the clone was last fetched.
+    #[serde(default)]
+    pub synced_at: Option<u64>,
+    /// Where the clone is held, if it was pinned by `paxy repository
+    /// downgrade`. Pinned repositories are left alone by updates.
+    #[serde(default)]
+    pub pin: Option<RepositoryPin>,
+}
+
+/// How a repository is fetched.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RepositoryKind {
+    /// A git repository, cloned and then fetched.
+    #[default]
+    Git,
+    /// A static file server, from which the package index and the manifests
+    /// it lists are downloaded.
+    Http,
+    /// A directory on this machine, read in place so that changes to it are
+    /// seen immediately.
+    Local,
+}
+
+impl fmt::Display for RepositoryKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            RepositoryKind::Git => "git",
+            RepositoryKind::Http => "http",
+            RepositoryKind::Local => "local",
+        })
+    }
+}
+
+impl RegisteredRepository {
+    /// Returns the URL of the repository followed by its mirrors, skipping
+    /// any that do not parse.
+    pub fn urls(&self) -> Vec<Url> {
+        std::iter::once(&self.url)
+            .chain(&self.mirrors)
+            .filter_map(|url| Url::parse(url).ok())
+            .collect()
+    }
+
+    /// Returns the directory the contents of the repository are read from.
+    pub fn dirpath(&self, paxy_home: &Path, repository_name: &str) -> PathBuf {
+        match self.kind {
+            RepositoryKind::Local => Url::parse(&self.url)
+                .ok()
+                .and_then(|url| {
+                    url.to_file_path()
+                        .ok()
+                })
+                .unwrap_or_else(|| PathBuf::from(&self.url)),
+            RepositoryKind::Git | RepositoryKind::Http => {
+                data::repositories_dirpath(paxy_home).join(repository_name)
+            }
+        }
+    }
+}
+
+/// A commit that the clone of a repository is held at.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RepositoryPin {
+    /// The tag, commit or date the pin was asked for.
+    pub reference: String,
+    pub commit: String,
+    /// The branch that was checked out before, to return to when unpinned.
+    pub branch: String,
+}
+
+/// All installed repositories, keyed by name.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Registry {
+    pub repositories: BTreeMap<String, RegisteredRepository>,
+}
+
+impl Registry {
+    /// Returns the path of the registry file inside the given paxy home.
+    pub fn filepath(paxy_home: &Path) -> PathBuf {
+        paxy_home.join("repos.bson")
+    }
+
+    /// Loads the registry from the given file. A missing file is an empty
+    /// registry. Entries written by older versions of paxy, which only held
+    /// the URL, are read too.
+    pub fn load(filepath: &Path) -> Result<Self, Error> {
+        if !filepath.is_file() {
+            return Ok(Self::default());
+        }
+
+        let file = File::open(filepath).context(ReadRegistrySnafu { path: filepath })?;
+        let document =
+            Document::from_reader(file).context(DecodeRegistrySnafu { path: filepath })?;
+        let mut repositories = BTreeMap::new();
+        for (name, value) in document {
+            let repository = match value {
+                Bson::String(url) => RegisteredRepository {
+                    url,
+                    kind: RepositoryKind::Git,
+                    priority: 0,
+                    mirrors: Vec::new(),
+                    mirror_order: MirrorOrder::default(),
+                    added_at: 0,
+                    synced_at: None,
+                    pin: None,
+                },
+                value => bson::from_bson(value).context(DeserializeRegistrySnafu {
+                    path: filepath,
+                    name: &name,
+                })?,
+            };
+            repositories.insert(name, repository);
+        }
+
+        Ok(Self { repositories })
+    }
+
+    /// Writes the registry to the given file atomically.
+    pub fn save(&self, filepath: &Path) -> Result<(), Error> {
+        let mut document = Document::new();
+        for (name, repository) in self
+            .repositories
+            .iter()
+        {
+            document.insert(
+                name,
+                bson::to_bson(repository).context(SerializeRegistrySnafu {})?,
+            );
+        }
+        let mut buffer = Vec::new();
+        document
+            .to_writer(&mut buffer)
+            .context(EncodeRegistrySnafu {})?;
+        data::write_atomically(filepath, &buffer).context(WriteRegistrySnafu { path: filepath })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&RegisteredRepository> {
+        self.repositories
+            .get(name)
+    }
+
+    pub fn insert(&mut self, name: &str, repository: RegisteredRepository) {
+        self.repositories
+            .insert(name.to_string(), repository);
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<RegisteredRepository> {
+        self.repositories
+            .remove(name)
+    }
+}
+
+// region: ERRORS
+
+#[derive(Debug, Snafu)]
+#[snafu(visibility(pub(crate)))]
+#[non_exhaustive]
+pub enum Error {
+    #[non_exhaustive]
+    #[snafu(display("could not read the repository registry at {path:?}: {source}"))]
+    ReadRegistry {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("the repository registry at {path:?} is corrupt: {source}"))]
+    DecodeRegistry {
+        path: PathBuf,
+        source: bson::de::Error,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display(
+        "the entry of '{name}' in the repository registry at {path:?} has unexpected contents: \
+         {source}"
+    ))]
+    DeserializeRegistry {
+        path: PathBuf,
+        name: String,
+        source: bson::de::Error,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("could not serialize the repository registry: {source}"))]
+    SerializeRegistry {
+        #[snafu(source(from(bson::ser::Error, Box::new)))]
+        source: Box<bson::ser::Error>,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("could not encode the repository registry: {source}"))]
+    EncodeRegistry {
+        #[snafu(source(from(bson::ser::Error, Box::new)))]
+        source: Box<bson::ser::Error>,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("could not write the repository registry to {path:?}: {source}"))]
+    WriteRegistry {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+}
+
+// endregion: ERRORS
+
+// region: IMPORTS
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    fs::File,
+    path::{Path, PathBuf},
+};
+
+use bson::{Bson, Document};
+use serde::{Deserialize, Serialize};
+use snafu::{ResultExt, Snafu};
+use url::Url;
+
+use crate::data::{self, mirrors::MirrorOrder};
+
+// endregion: IMPORTS
+
+// region: TESTS
+
+#[cfg(test)]
+mod tests {
+    use bson::doc;
+
+    use super::*;
+
+    #[test]
+    fn read_entries_that_only_hold_the_url() {
+        let paxy_home = tempfile::tempdir().unwrap();
+        let filepath = Registry::filepath(paxy_home.path());
+        let mut buffer = Vec::new();
+        doc! {"old": "https://example.com/old.git"}
+            .to_writer(&mut buffer)
+            .unwrap();
+        std::fs::write(&filepath, buffer).unwrap();
+
+        let mut registry = Registry::load(&filepath).unwrap();
+        assert_eq!(
+            registry
+                .get("old")
+                .map(|repository| repository
+                    .url
+                    .as_str()),
+            Some("https://example.com/old.git")
+        );
+
+        registry.insert(
+            "new",
+            RegisteredRepository {
+                url: "https://example.com/new.git".to_string(),
+                kind: RepositoryKind::Git,
+                priority: 0,
+                mirrors: Vec::new(),
+                mirror_order: MirrorOrder::default(),
+                added_at: 1,
+                synced_at: Some(1),
+                pin: None,
+            },
+        );
+        registry
+            .save(&filepath)
+            .unwrap();
+        assert_eq!(Registry::load(&filepath).unwrap(), registry);
+    }
+}
+
+// endregion: TESTS
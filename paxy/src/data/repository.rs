@@ -0,0 +1,333 @@
+//! The layout of a package repository on disk. Every version of a package
+//! has its own directory holding its manifest, as in
+//! `<package name>/<version>/manifest.toml`. Directories that do not follow
+//! this layout, like `.git`, are ignored.
+//!
+//! At its top, a repository describes itself in `repository.toml` and lists
+//! its packages in a generated `index.toml`, as specified in [`layout`].
+
+/// A repository whose contents are available on disk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocalRepository {
+    pub name: String,
+    pub dirpath: PathBuf,
+    pub kind: RepositoryKind,
+}
+
+/// Lists the repositories under `~/.paxy/repos`, along with the local
+/// directories registered as repositories, sorted by name.
+pub fn local_repositories(paxy_home: &Path) -> Result<Vec<LocalRepository>, Error> {
+    let registry = Registry::load(&Registry::filepath(paxy_home)).context(RegistrySnafu {})?;
+    // Directories that are not registered can only have been cloned
+    let mut repositories: Vec<LocalRepository> =
+        subdirectories(&data::repositories_dirpath(paxy_home))?
+            .into_iter()
+            .filter_map(|dirpath| {
+                visible_file_name(&dirpath).map(|name| LocalRepository {
+                    kind: registry
+                        .get(&name)
+                        .map_or(RepositoryKind::Git, |registered_repository| {
+                            registered_repository.kind
+                        }),
+                    name,
+                    dirpath,
+                })
+            })
+            .collect();
+    repositories.extend(
+        registry
+            .repositories
+            .iter()
+            .filter(|(_, registered_repository)| {
+                registered_repository.kind == RepositoryKind::Local
+            })
+            .map(|(name, registered_repository)| LocalRepository {
+                name: name.clone(),
+                dirpath: registered_repository.dirpath(paxy_home, name),
+                kind: RepositoryKind::Local,
+            }),
+    );
+    repositories.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(repositories)
+}
+
+/// A package manifest found inside a repository.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestEntry {
+    pub name: String,
+    pub version: Version,
+    pub manifest_filepath: PathBuf,
+}
+
+/// Lists the manifests of every version of every package in the repository
+/// at the given path, sorted by package name and then by version.
+pub fn manifest_entries(repository_dirpath: &Path) -> Result<Vec<ManifestEntry>, Error> {
+    let mut entries = Vec::new();
+
+    for package_dirpath in subdirectories(repository_dirpath)? {
+        let Some(name) = visible_file_name(&package_dirpath) else {
+            continue;
+        };
+        entries.extend(version_entries(&package_dirpath, &name)?);
+    }
+
+    entries.sort_by(|a, b| {
+        a.name
+            .cmp(&b.name)
+            .then_with(|| {
+                a.version
+                    .cmp(&b.version)
+            })
+    });
+
+    Ok(entries)
+}
+
+/// Lists the manifests of every version of the named package in the
+/// repository at the given path, sorted by version. Only the directory of the
+/// package is read.
+pub fn package_manifest_entries(
+    repository_dirpath: &Path,
+    package_name: &str,
+) -> Result<Vec<ManifestEntry>, Error> {
+    // Anything else could point outside the directory of a package
+    if !manifest::is_valid_package_name(package_name) {
+        return Ok(Vec::new());
+    }
+
+    let mut entries = version_entries(&repository_dirpath.join(package_name), package_name)?;
+    entries.sort_by(|a, b| {
+        a.version
+            .cmp(&b.version)
+    });
+
+    Ok(entries)
+}
+
+/// Lists the manifests of the versions in the directory of a package, in no
+/// particular order.
+fn version_entries(package_dirpath: &Path, name: &str) -> Result<Vec<ManifestEntry>, Error> {
+    let mut entries = Vec::new();
+    for version_dirpath in subdirectories(package_dirpath)? {
+        let Some(version) =
+            visible_file_name(&version_dirpath).and_then(|version| Version::parse(&version).ok())
+        else {
+            continue;
+        };
+        if let Some(manifest_filepath) = manifest::manifest_filepath_in(&version_dirpath) {
+            entries.push(ManifestEntry {
+                name: name.to_string(),
+                version,
+                manifest_filepath,
+            });
+        }
+    }
+
+    Ok(entries)
+}
+
+/// The newest version of a package in a repository, described as it is when
+/// searching.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PackageSummary {
+    pub name: String,
+    pub latest_version: Version,
+    pub description: Option<String>,
+    pub tags: Vec<String>,
+}
+
+/// Summarizes every package in the repository at the given path from the
+/// manifest of its newest version, sorted by name. Packages whose newest
+/// manifest cannot be read are left out with a warning.
+pub fn package_summaries(repository_dirpath: &Path) -> Result<Vec<PackageSummary>, Error> {
+    // Entries are sorted by version, so the newest one of each package wins
+    let newest_entries: BTreeMap<String, ManifestEntry> = manifest_entries(repository_dirpath)?
+        .into_iter()
+        .map(|entry| (entry.name.clone(), entry))
+        .collect();
+
+    Ok(newest_entries
+        .into_values()
+        .filter_map(
+            |entry| match PackageManifest::from_path(&entry.manifest_filepath) {
+                Ok(manifest) => Some(PackageSummary {
+                    name: entry.name,
+                    latest_version: entry.version,
+                    description: manifest.description,
+                    tags: manifest.tags,
+                }),
+                Err(error) => {
+                    tracing::warn!(
+                        "Skipping the manifest at {:?}: {}",
+                        entry.manifest_filepath,
+                        error
+                    );
+                    None
+                }
+            },
+        )
+        .collect())
+}
+
+fn subdirectories(dirpath: &Path) -> Result<Vec<PathBuf>, Error> {
+    if !dirpath.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut subdirectories = Vec::new();
+    for entry in fs::read_dir(dirpath).context(ReadDirectorySnafu { path: dirpath })? {
+        let entry = entry.context(ReadDirectorySnafu { path: dirpath })?;
+        if entry
+            .file_type()
+            .context(ReadDirectorySnafu { path: dirpath })?
+            .is_dir()
+        {
+            subdirectories.push(entry.path());
+        }
+    }
+
+    Ok(subdirectories)
+}
+
+fn visible_file_name(path: &Path) -> Option<String> {
+    path.file_name()
+        .map(|name| {
+            name.to_string_lossy()
+                .into_owned()
+        })
+        .filter(|name| !name.starts_with('.'))
+}
+
+// region: ERRORS
+
+#[derive(Debug, Snafu)]
+#[snafu(visibility(pub(crate)))]
+#[non_exhaustive]
+pub enum Error {
+    #[non_exhaustive]
+    #[snafu(display("could not read the repository directory {path:?}: {source}"))]
+    ReadDirectory {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("in the repository registry: {source}"))]
+    Registry {
+        #[snafu(source(from(registry::Error, Box::new)))]
+        source: Box<registry::Error>,
+    },
+}
+
+// endregion: ERRORS
+
+// region: IMPORTS
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use snafu::{ResultExt, Snafu};
+
+use crate::data::{
+    self,
+    manifest::{self, PackageManifest},
+    registry::{self, Registry, RepositoryKind},
+};
+
+// endregion: IMPORTS
+
+// region: TESTS
+
+/// Fixtures shared by the tests that need a repository on disk.
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{
+        fs,
+        path::{Path, PathBuf},
+    };
+
+    use git2::Repository;
+
+    /// Writes the manifest of a package version into a repository, at
+    /// `<repository>/<name>/<version>/manifest.toml`, with `extra` appended
+    /// after its name and version, and returns its path.
+    pub(crate) fn write_manifest(
+        repository_dirpath: &Path,
+        name: &str,
+        version: &str,
+        extra: &str,
+    ) -> PathBuf {
+        let version_dirpath = repository_dirpath
+            .join(name)
+            .join(version);
+        fs::create_dir_all(&version_dirpath).unwrap();
+        let manifest_filepath = version_dirpath.join("manifest.toml");
+        fs::write(
+            &manifest_filepath,
+            format!("format_version = 1\nname = \"{name}\"\nversion = \"{version}\"\n{extra}\n"),
+        )
+        .unwrap();
+        manifest_filepath
+    }
+
+    /// Commits every change in the working directory of a git repository,
+    /// deleted files included, on top of its current commit if any.
+    pub(crate) fn commit_all(repository: &Repository, message: &str) {
+        let mut index = repository
+            .index()
+            .unwrap();
+        index
+            .add_all(["*"], git2::IndexAddOption::DEFAULT, None)
+            .unwrap();
+        // Deleted files have to be dropped from the index too
+        index
+            .update_all(["*"], None)
+            .unwrap();
+        index
+            .write()
+            .unwrap();
+        let tree = repository
+            .find_tree(
+                index
+                    .write_tree()
+                    .unwrap(),
+            )
+            .unwrap();
+        let signature = git2::Signature::now("paxy", "paxy@example.com").unwrap();
+        let parent = repository
+            .head()
+            .ok()
+            .and_then(|head| {
+                head.peel_to_commit()
+                    .ok()
+            });
+        repository
+            .commit(
+                Some("HEAD"),
+                &signature,
+                &signature,
+                message,
+                &tree,
+                &parent
+                    .iter()
+                    .collect::<Vec<_>>(),
+            )
+            .unwrap();
+    }
+}
+
+// endregion: TESTS
+
+// region: EXTERNAL-SUBMODULES
+
+pub mod history;
+pub mod http;
+pub mod layout;
+
+// endregion: EXTERNAL-SUBMODULES
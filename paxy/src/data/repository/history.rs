@@ -0,0 +1,265 @@
+//! The versions of packages found in the git history of a repository. A
+//! repository drops old versions of a package over time, but they remain in
+//! its earlier commits and tags, from which they can be extracted to be
+//! installed again.
+
+/// A version of a package found in the history of a repository.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistoricalVersion {
+    pub version: Version,
+    /// The id of the newest commit holding the version.
+    pub commit: String,
+}
+
+/// Lists the versions of the named package in the history of the repository
+/// at the given path, reachable from its `HEAD` or any of its tags, sorted by
+/// version. A repository that is not a git repository has no history.
+pub fn package_versions(
+    repository_dirpath: &Path,
+    package_name: &str,
+) -> Result<Vec<HistoricalVersion>, Error> {
+    let Ok(repository) = Repository::open(repository_dirpath) else {
+        return Ok(Vec::new());
+    };
+    let git_context = || GitSnafu {
+        path: repository_dirpath,
+    };
+
+    let mut revwalk = repository
+        .revwalk()
+        .with_context(|_| git_context())?;
+    revwalk
+        .set_sorting(Sort::TIME)
+        .with_context(|_| git_context())?;
+    // A repository without any commits yet has no HEAD
+    let _ = revwalk.push_head();
+    revwalk
+        .push_glob("refs/tags/*")
+        .with_context(|_| git_context())?;
+
+    let mut versions: BTreeMap<Version, String> = BTreeMap::new();
+    for commit_id in revwalk {
+        let commit_id = commit_id.with_context(|_| git_context())?;
+        let tree = repository
+            .find_commit(commit_id)
+            .and_then(|commit| commit.tree())
+            .with_context(|_| git_context())?;
+        let Some(package_tree) = subtree(&repository, &tree, package_name) else {
+            continue;
+        };
+        for entry in package_tree.iter() {
+            let Some(version) = entry
+                .name()
+                .and_then(|version| Version::parse(version).ok())
+            else {
+                continue;
+            };
+            if versions.contains_key(&version) {
+                continue;
+            }
+            let has_manifest = subtree(&repository, &package_tree, &version.to_string())
+                .is_some_and(|version_tree| manifest_file_name(&version_tree).is_some());
+            if has_manifest {
+                versions.insert(version, commit_id.to_string());
+            }
+        }
+    }
+
+    Ok(versions
+        .into_iter()
+        .map(|(version, commit)| HistoricalVersion { version, commit })
+        .collect())
+}
+
+/// Writes the directory of a package version as it was at the given commit
+/// into the destination directory, and returns the path of its manifest
+/// there.
+pub fn extract_package_version(
+    repository_dirpath: &Path,
+    commit: &str,
+    package_name: &str,
+    version: &Version,
+    destination_dirpath: &Path,
+) -> Result<PathBuf, Error> {
+    let git_context = || GitSnafu {
+        path: repository_dirpath,
+    };
+    let repository = Repository::open(repository_dirpath).with_context(|_| git_context())?;
+    let tree = Oid::from_str(commit)
+        .and_then(|commit_id| repository.find_commit(commit_id))
+        .and_then(|commit| commit.tree())
+        .with_context(|_| git_context())?;
+    let version_tree = subtree(&repository, &tree, package_name)
+        .and_then(|package_tree| subtree(&repository, &package_tree, &version.to_string()))
+        .context(VersionNotInCommitSnafu {
+            name: package_name,
+            version: version.to_string(),
+            commit,
+        })?;
+    let manifest_file_name =
+        manifest_file_name(&version_tree).context(VersionNotInCommitSnafu {
+            name: package_name,
+            version: version.to_string(),
+            commit,
+        })?;
+
+    write_tree(&repository, &version_tree, destination_dirpath).with_context(|_| WriteSnafu {
+        path: destination_dirpath,
+    })?;
+
+    Ok(destination_dirpath.join(manifest_file_name))
+}
+
+fn subtree<'r>(repository: &'r Repository, tree: &Tree<'r>, name: &str) -> Option<Tree<'r>> {
+    let entry = tree.get_name(name)?;
+    if entry.kind() != Some(ObjectType::Tree) {
+        return None;
+    }
+
+    repository
+        .find_tree(entry.id())
+        .ok()
+}
+
+fn manifest_file_name(version_tree: &Tree) -> Option<String> {
+    MANIFEST_FILE_EXTENSIONS
+        .iter()
+        .map(|extension| format!("manifest.{extension}"))
+        .find(|file_name| {
+            version_tree
+                .get_name(file_name)
+                .is_some_and(|entry| entry.kind() == Some(ObjectType::Blob))
+        })
+}
+
+fn write_tree(repository: &Repository, tree: &Tree, dirpath: &Path) -> io::Result<()> {
+    fs::create_dir_all(dirpath)?;
+    for entry in tree.iter() {
+        let Some(name) = entry.name() else {
+            continue;
+        };
+        let path = dirpath.join(name);
+        match entry.kind() {
+            Some(ObjectType::Tree) => {
+                let subtree = repository
+                    .find_tree(entry.id())
+                    .map_err(io::Error::other)?;
+                write_tree(repository, &subtree, &path)?;
+            }
+            Some(ObjectType::Blob) => {
+                let blob = repository
+                    .find_blob(entry.id())
+                    .map_err(io::Error::other)?;
+                fs::write(&path, blob.content())?;
+                #[cfg(unix)]
+                if entry.filemode() == 0o100755 {
+                    use std::os::unix::fs::PermissionsExt;
+                    fs::set_permissions(&path, fs::Permissions::from_mode(0o755))?;
+                }
+            }
+            // Submodules and the like are not part of a package
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+// region: ERRORS
+
+#[derive(Debug, Snafu)]
+#[snafu(visibility(pub(crate)))]
+#[non_exhaustive]
+pub enum Error {
+    #[non_exhaustive]
+    #[snafu(display("could not read the git history of the repository at {path:?}: {source}"))]
+    Git { path: PathBuf, source: git2::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("'{name}' {version} is not in commit {commit}"))]
+    VersionNotInCommit {
+        name: String,
+        version: String,
+        commit: String,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("could not write the package files to {path:?}: {source}"))]
+    Write { path: PathBuf, source: io::Error },
+}
+
+// endregion: ERRORS
+
+// region: IMPORTS
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    io,
+    path::{Path, PathBuf},
+};
+
+use git2::{ObjectType, Oid, Repository, Sort, Tree};
+use semver::Version;
+use snafu::{OptionExt, ResultExt, Snafu};
+
+use crate::data::manifest::MANIFEST_FILE_EXTENSIONS;
+
+// endregion: IMPORTS
+
+// region: TESTS
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::repository::tests::{commit_all, write_manifest};
+
+    #[test]
+    fn find_and_extract_versions_dropped_from_the_repository() {
+        let repository_dirpath = tempfile::tempdir().unwrap();
+        let repository = Repository::init(repository_dirpath.path()).unwrap();
+        let version_dirpath = write_manifest(repository_dirpath.path(), "hello", "1.0.0", "")
+            .parent()
+            .unwrap()
+            .to_path_buf();
+        fs::create_dir_all(version_dirpath.join("src")).unwrap();
+        fs::write(version_dirpath.join("src/hello"), "1.0.0").unwrap();
+        commit_all(&repository, "Add hello 1.0.0");
+        fs::remove_dir_all(&version_dirpath).unwrap();
+        write_manifest(repository_dirpath.path(), "hello", "2.0.0", "");
+        commit_all(&repository, "Replace hello 1.0.0 with 2.0.0");
+
+        let versions = package_versions(repository_dirpath.path(), "hello").unwrap();
+        assert_eq!(
+            versions
+                .iter()
+                .map(|historical_version| historical_version
+                    .version
+                    .to_string())
+                .collect::<Vec<_>>(),
+            ["1.0.0", "2.0.0"]
+        );
+
+        let destination_dirpath = tempfile::tempdir().unwrap();
+        let manifest_filepath = extract_package_version(
+            repository_dirpath.path(),
+            &versions[0].commit,
+            "hello",
+            &versions[0].version,
+            destination_dirpath.path(),
+        )
+        .unwrap();
+        assert!(manifest_filepath.ends_with("manifest.toml"));
+        assert_eq!(
+            fs::read_to_string(
+                destination_dirpath
+                    .path()
+                    .join("src/hello")
+            )
+            .unwrap(),
+            "1.0.0"
+        );
+    }
+}
+
+// endregion: TESTS
@@ -0,0 +1,639 @@
+//! Repositories served over plain HTTP(S) by a static file server, without
+//! git. The server serves the files of a repository as laid out on disk,
+//! including the compressed package index `index.toml.gz`. Syncing downloads
+//! the index, then only the manifests it lists whose checksums differ from
+//! the local copies. Since nothing but the manifests is downloaded, their
+//! sources cannot be given by a relative `path`.
+//!
+//! The local copy of such a repository has the same layout as a clone, so
+//! everything that reads repositories from disk works on it unchanged. Its
+//! `index.toml` is written last, and the checksum of that file serves as the
+//! revision of the repository, the way a commit does for a clone.
+
+/// Returns whether a package index is served under the given base URL,
+/// meaning the URL can be synced from without git.
+pub fn probe(base_url: &Url) -> bool {
+    let Ok(url) = file_url(base_url, layout::COMPRESSED_PACKAGE_INDEX_FILE_NAME) else {
+        return false;
+    };
+
+    client()
+        .and_then(|client| {
+            client
+                .head(url)
+                .send()
+        })
+        .is_ok_and(|response| {
+            response
+                .status()
+                .is_success()
+        })
+}
+
+/// Downloads the package index served under the given base URL and brings
+/// the repository at the given path in line with it: manifests that are new
+/// or differ are downloaded and checked against their checksums, and package
+/// versions no longer listed are removed. Returns the new revision.
+pub fn sync(base_url: &Url, repository_dirpath: &Path) -> Result<String, Error> {
+    let client = client().context(ClientSnafu {})?;
+
+    let index_url = file_url(base_url, layout::COMPRESSED_PACKAGE_INDEX_FILE_NAME)?;
+    let compressed = download(&client, &index_url)?.context(NoIndexSnafu {
+        url: base_url.as_str(),
+    })?;
+    let (index, text) = PackageIndex::parse_compressed(&compressed).context(InvalidIndexSnafu {
+        url: index_url.as_str(),
+    })?;
+    ensure!(
+        index.format_version == layout::REPOSITORY_FORMAT_VERSION,
+        UnsupportedFormatVersionSnafu {
+            url: index_url.as_str(),
+            found: index.format_version
+        }
+    );
+
+    let mut listed = BTreeSet::new();
+    for package in index
+        .packages
+        .iter()
+    {
+        let manifest_filepath = manifest_filepath(repository_dirpath, package)?;
+        listed.insert((
+            package.name.clone(),
+            package
+                .version
+                .clone(),
+        ));
+        if data::sha256_of_file(&manifest_filepath).is_ok_and(|sha256| sha256 == package.sha256) {
+            continue;
+        }
+
+        let manifest_url = file_url(base_url, &package.manifest)?;
+        let contents = download(&client, &manifest_url)?.context(MissingManifestSnafu {
+            url: manifest_url.as_str(),
+        })?;
+        let sha256 = format!("{:x}", Sha256::digest(&contents));
+        ensure!(
+            sha256 == package.sha256,
+            ChecksumMismatchSnafu {
+                url: manifest_url.as_str(),
+                expected: &package.sha256,
+                found: sha256
+            }
+        );
+        if let Some(path) = relative_source_path(&manifest_filepath, &contents) {
+            return RelativeSourcePathSnafu {
+                url: manifest_url.as_str(),
+                path,
+            }
+            .fail();
+        }
+        data::write_atomically(&manifest_filepath, &contents).context(WriteSnafu {
+            path: &manifest_filepath,
+        })?;
+    }
+
+    for entry in repository::manifest_entries(repository_dirpath).context(RepositorySnafu {})? {
+        if listed.contains(&(entry.name, entry.version)) {
+            continue;
+        }
+        let Some(version_dirpath) = entry
+            .manifest_filepath
+            .parent()
+        else {
+            continue;
+        };
+        fs::remove_dir_all(version_dirpath).context(RemoveSnafu {
+            path: version_dirpath,
+        })?;
+        if let Some(package_dirpath) = version_dirpath.parent() {
+            // Only succeeds once the last version of the package is gone
+            let _ = fs::remove_dir(package_dirpath);
+        }
+    }
+
+    let metadata_url = file_url(base_url, layout::METADATA_FILE_NAME)?;
+    let metadata_filepath = repository_dirpath.join(layout::METADATA_FILE_NAME);
+    match download(&client, &metadata_url)? {
+        Some(contents) => {
+            data::write_atomically(&metadata_filepath, &contents).context(WriteSnafu {
+                path: &metadata_filepath,
+            })?
+        }
+        None if metadata_filepath.exists() => {
+            fs::remove_file(&metadata_filepath).context(RemoveSnafu {
+                path: &metadata_filepath,
+            })?
+        }
+        None => {}
+    }
+
+    let index_filepath = repository_dirpath.join(layout::PACKAGE_INDEX_FILE_NAME);
+    data::write_atomically(&index_filepath, text.as_bytes()).context(WriteSnafu {
+        path: &index_filepath,
+    })?;
+
+    Ok(format!("{:x}", Sha256::digest(text.as_bytes())))
+}
+
+/// Returns the revision of the repository at the given path, or `None` if it
+/// has never been synced.
+pub fn revision(repository_dirpath: &Path) -> Option<String> {
+    data::sha256_of_file(&repository_dirpath.join(layout::PACKAGE_INDEX_FILE_NAME)).ok()
+}
+
+/// Returns whether the manifests in the repository at the given path differ
+/// from what its last synced index lists.
+pub fn is_modified(repository_dirpath: &Path) -> Result<bool, Error> {
+    let fingerprints = |index: PackageIndex| {
+        index
+            .packages
+            .into_iter()
+            .map(|package| ((package.name, package.version), package.sha256))
+            .collect::<BTreeMap<_, _>>()
+    };
+    let synced = PackageIndex::load(repository_dirpath).context(LocalIndexSnafu {})?;
+    let found = PackageIndex::generate(repository_dirpath).context(LocalIndexSnafu {})?;
+
+    Ok(synced.map(fingerprints) != Some(fingerprints(found)))
+}
+
+fn client() -> reqwest::Result<Client> {
+    Client::builder()
+        .user_agent(concat!("paxy/", env!("CARGO_PKG_VERSION")))
+        .connect_timeout(mirrors::CONNECT_TIMEOUT)
+        .timeout(mirrors::TRANSFER_TIMEOUT)
+        .build()
+}
+
+/// Downloads the file at the given URL, or returns `None` if the server does
+/// not have it.
+fn download(client: &Client, url: &Url) -> Result<Option<Vec<u8>>, Error> {
+    let response = client
+        .get(url.clone())
+        .send()
+        .context(DownloadSnafu { url: url.as_str() })?;
+    if response.status() == StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+
+    response
+        .error_for_status()
+        .and_then(|response| response.bytes())
+        .map(|bytes| Some(bytes.to_vec()))
+        .context(DownloadSnafu { url: url.as_str() })
+}
+
+/// Resolves a path relative to the root of the repository against the base
+/// URL, which is treated as a directory even without a trailing slash.
+fn file_url(base_url: &Url, relative_path: &str) -> Result<Url, Error> {
+    let mut base_url = base_url.clone();
+    if !base_url
+        .path()
+        .ends_with('/')
+    {
+        base_url.set_path(&format!("{}/", base_url.path()));
+    }
+
+    base_url
+        .join(relative_path)
+        .context(InvalidUrlSnafu {
+            url: base_url.as_str(),
+            path: relative_path,
+        })
+}
+
+/// Returns the first source of a downloaded manifest given by a relative
+/// path, if the manifest can be parsed. Such a path points next to the
+/// manifest on the server, where nothing else is downloaded from.
+fn relative_source_path(manifest_filepath: &Path, contents: &[u8]) -> Option<PathBuf> {
+    let format = ManifestFormat::from_path(manifest_filepath)?;
+    let text = std::str::from_utf8(contents).ok()?;
+    PackageManifest::from_str_with_format(text, format)
+        .ok()?
+        .sources
+        .into_iter()
+        .filter_map(|source| source.path)
+        .find(|path| path.is_relative())
+}
+
+/// Returns where the manifest of an indexed package goes locally. Since the
+/// index comes from the network, its package names are checked like those of
+/// manifests, and its manifest paths are only accepted if they follow the
+/// `<package name>/<version>/<file name>` layout, so that they cannot point
+/// outside the repository.
+fn manifest_filepath(
+    repository_dirpath: &Path,
+    package: &IndexedPackage,
+) -> Result<PathBuf, Error> {
+    ensure!(
+        manifest::is_valid_package_name(&package.name),
+        InvalidPackageNameSnafu {
+            name: &package.name
+        }
+    );
+    let components: Vec<&str> = package
+        .manifest
+        .split('/')
+        .collect();
+    let version = package
+        .version
+        .to_string();
+    match components.as_slice() {
+        [name, version_component, file_name]
+            if *name == package.name
+                && *version_component == version
+                && components
+                    .iter()
+                    .all(|component| !matches!(*component, "" | "." | ".."))
+                && !file_name.starts_with('.')
+                && !file_name.contains('\\') =>
+        {
+            Ok(repository_dirpath
+                .join(name)
+                .join(version_component)
+                .join(file_name))
+        }
+        _ => UnexpectedManifestPathSnafu {
+            path: &package.manifest,
+        }
+        .fail(),
+    }
+}
+
+// region: ERRORS
+
+#[derive(Debug, Snafu)]
+#[snafu(visibility(pub(crate)))]
+#[non_exhaustive]
+pub enum Error {
+    #[non_exhaustive]
+    #[snafu(display("could not set up the HTTP client: {source}"))]
+    Client { source: reqwest::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("could not download {url}: {source}"))]
+    Download { url: String, source: reqwest::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("could not resolve {path} against {url}: {source}"))]
+    InvalidUrl {
+        url: String,
+        path: String,
+        source: url::ParseError,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display(
+        "{url} does not serve a package index ({})",
+        layout::COMPRESSED_PACKAGE_INDEX_FILE_NAME
+    ))]
+    NoIndex { url: String },
+
+    #[non_exhaustive]
+    #[snafu(display("the package index at {url} is invalid: {source}"))]
+    InvalidIndex { url: String, source: layout::Error },
+
+    #[non_exhaustive]
+    #[snafu(display(
+        "the package index at {url} is in format version {found}, but only version {} is \
+         supported. Update paxy",
+        layout::REPOSITORY_FORMAT_VERSION
+    ))]
+    UnsupportedFormatVersion { url: String, found: u32 },
+
+    #[non_exhaustive]
+    #[snafu(display("the package index lists '{name}', which is not a valid package name"))]
+    InvalidPackageName { name: String },
+
+    #[non_exhaustive]
+    #[snafu(display(
+        "the package index lists the manifest '{path}', which is not laid out as <package \
+         name>/<version>/<file name>"
+    ))]
+    UnexpectedManifestPath { path: String },
+
+    #[non_exhaustive]
+    #[snafu(display("the package index lists {url}, but the server does not have it"))]
+    MissingManifest { url: String },
+
+    #[non_exhaustive]
+    #[snafu(display("the checksum of {url} is {found}, but the package index says {expected}"))]
+    ChecksumMismatch {
+        url: String,
+        expected: String,
+        found: String,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display(
+        "{url} takes a source from the relative path {path:?}, but only manifests are downloaded \
+         from repositories served over HTTP. Give the source by `url` or `git` instead"
+    ))]
+    RelativeSourcePath { url: String, path: PathBuf },
+
+    #[non_exhaustive]
+    #[snafu(display("could not write {path:?}: {source}"))]
+    Write {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("could not remove {path:?}: {source}"))]
+    Remove {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("in the local package index: {source}"))]
+    LocalIndex { source: layout::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("in the repository: {source}"))]
+    Repository { source: repository::Error },
+}
+
+impl Error {
+    /// Whether the server could not be reached or answered with an error,
+    /// so that another mirror may well do better.
+    pub fn is_mirror_fault(&self) -> bool {
+        matches!(
+            self,
+            Self::Download { .. } | Self::NoIndex { .. } | Self::MissingManifest { .. }
+        )
+    }
+}
+
+// endregion: ERRORS
+
+// region: IMPORTS
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use reqwest::{blocking::Client, StatusCode};
+use sha2::{Digest, Sha256};
+use snafu::{ensure, OptionExt, ResultExt, Snafu};
+use url::Url;
+
+use crate::data::{
+    self,
+    manifest::{self, ManifestFormat, PackageManifest},
+    mirrors,
+    repository::{
+        self,
+        layout::{self, IndexedPackage, PackageIndex},
+    },
+};
+
+// endregion: IMPORTS
+
+// region: TESTS
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        thread,
+    };
+
+    use super::*;
+
+    /// Serves the files under the given directory over HTTP on a local port,
+    /// for as long as the test process runs. Returns the base URL.
+    pub(crate) fn serve_directory(root: &Path) -> Url {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener
+            .local_addr()
+            .unwrap();
+        let root = root.to_path_buf();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else {
+                    continue;
+                };
+                let mut request_line = String::new();
+                let mut reader = BufReader::new(&stream);
+                if reader
+                    .read_line(&mut request_line)
+                    .is_err()
+                {
+                    continue;
+                }
+                let mut header = String::new();
+                while reader
+                    .read_line(&mut header)
+                    .is_ok_and(|read| read > 2)
+                {
+                    header.clear();
+                }
+
+                let mut parts = request_line.split_whitespace();
+                let method = parts
+                    .next()
+                    .unwrap_or_default();
+                let path = parts
+                    .next()
+                    .unwrap_or_default()
+                    .trim_start_matches('/');
+                let response = match fs::read(root.join(path)) {
+                    Ok(body) if !path.contains("..") => {
+                        let mut response = format!(
+                            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                            body.len()
+                        )
+                        .into_bytes();
+                        if method != "HEAD" {
+                            response.extend(body);
+                        }
+                        response
+                    }
+                    _ => {
+                        b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                            .to_vec()
+                    }
+                };
+                let _ = stream.write_all(&response);
+            }
+        });
+
+        Url::parse(&format!("http://{address}/")).unwrap()
+    }
+
+    /// Writes the given files under the given directory and indexes them.
+    pub(crate) fn publish(root: &Path, files: &[(&str, &str)]) {
+        for (path, contents) in files {
+            let filepath = root.join(path);
+            fs::create_dir_all(
+                filepath
+                    .parent()
+                    .unwrap(),
+            )
+            .unwrap();
+            fs::write(filepath, contents).unwrap();
+        }
+        PackageIndex::generate(root)
+            .unwrap()
+            .save(root)
+            .unwrap();
+    }
+
+    #[test]
+    fn sync_from_a_static_file_server() {
+        let served = tempfile::tempdir().unwrap();
+        let local = tempfile::tempdir().unwrap();
+        let manifest = |version: &str, description: &str| {
+            format!(
+                "format_version = 1\nname = \"hello\"\nversion = \"{version}\"\ndescription = \
+                 \"{description}\"\n"
+            )
+        };
+        publish(
+            served.path(),
+            &[
+                ("hello/1.0.0/manifest.toml", &manifest("1.0.0", "Hello")),
+                ("hello/1.1.0/manifest.toml", &manifest("1.1.0", "Hello")),
+            ],
+        );
+        let base_url = serve_directory(served.path());
+        assert!(probe(&base_url));
+        assert!(!probe(
+            &base_url
+                .join("elsewhere/")
+                .unwrap()
+        ));
+
+        let revision = sync(&base_url, local.path()).unwrap();
+        assert_eq!(super::revision(local.path()), Some(revision.clone()));
+        assert!(!is_modified(local.path()).unwrap());
+        let versions = |dirpath: &Path| {
+            repository::manifest_entries(dirpath)
+                .unwrap()
+                .into_iter()
+                .map(|entry| {
+                    entry
+                        .version
+                        .to_string()
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(versions(local.path()), ["1.0.0", "1.1.0"]);
+
+        // Changed manifests are downloaded again and removed versions go away
+        fs::remove_dir_all(
+            served
+                .path()
+                .join("hello/1.0.0"),
+        )
+        .unwrap();
+        publish(
+            served.path(),
+            &[("hello/1.1.0/manifest.toml", &manifest("1.1.0", "Hi"))],
+        );
+        fs::write(
+            local
+                .path()
+                .join("hello/1.1.0/manifest.toml"),
+            "edited",
+        )
+        .unwrap();
+        assert!(is_modified(local.path()).unwrap());
+        let new_revision = sync(&base_url, local.path()).unwrap();
+        assert_ne!(new_revision, revision);
+        assert_eq!(versions(local.path()), ["1.1.0"]);
+        assert!(fs::read_to_string(
+            local
+                .path()
+                .join("hello/1.1.0/manifest.toml")
+        )
+        .unwrap()
+        .contains("Hi"));
+        assert!(!is_modified(local.path()).unwrap());
+
+        // A manifest that does not match the index is refused
+        fs::write(
+            served
+                .path()
+                .join("hello/1.1.0/manifest.toml"),
+            manifest("1.1.0", "Tampered"),
+        )
+        .unwrap();
+        fs::remove_file(
+            local
+                .path()
+                .join("hello/1.1.0/manifest.toml"),
+        )
+        .unwrap();
+        assert!(matches!(
+            sync(&base_url, local.path()),
+            Err(Error::ChecksumMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn refuse_an_index_that_points_outside_the_repository() {
+        let served = tempfile::tempdir().unwrap();
+        let local = tempfile::tempdir().unwrap();
+        let local_dirpath = local
+            .path()
+            .join("main");
+        let base_url = serve_directory(served.path());
+        for (name, manifest) in [
+            ("..", "../1.0.0/manifest.toml"),
+            (".", "./1.0.0/manifest.toml"),
+            ("", "/1.0.0/manifest.toml"),
+        ] {
+            PackageIndex {
+                format_version: layout::REPOSITORY_FORMAT_VERSION,
+                packages: vec![IndexedPackage {
+                    name: name.to_string(),
+                    version: semver::Version::new(1, 0, 0),
+                    manifest: manifest.to_string(),
+                    sha256: "0".repeat(64),
+                }],
+            }
+            .save(served.path())
+            .unwrap();
+
+            assert!(matches!(
+                sync(&base_url, &local_dirpath),
+                Err(Error::InvalidPackageName { .. })
+            ));
+        }
+        assert!(!local
+            .path()
+            .join("1.0.0")
+            .exists());
+    }
+
+    #[test]
+    fn refuse_manifests_with_sources_next_to_them() {
+        let served = tempfile::tempdir().unwrap();
+        let local = tempfile::tempdir().unwrap();
+        publish(
+            served.path(),
+            &[(
+                "hello/1.0.0/manifest.toml",
+                "format_version = 1\nname = \"hello\"\nversion = \"1.0.0\"\n\n[[sources]]\npath \
+                 = \"src\"\n",
+            )],
+        );
+
+        assert!(matches!(
+            sync(&serve_directory(served.path()), local.path()),
+            Err(Error::RelativeSourcePath { ref path, .. }) if path == Path::new("src")
+        ));
+        assert!(!local
+            .path()
+            .join("hello/1.0.0/manifest.toml")
+            .exists());
+    }
+}
+
+// endregion: TESTS
@@ -0,0 +1,575 @@
+//! The files at the top of a package repository, next to its package
+//! directories:
+//!
+//! - `repository.toml` describes the repository, as in
+//!
+//!   ```toml
+//!   format_version = 1
+//!   name = "games"
+//!   description = "Games and emulators"
+//!   maintainers = ["Jane Doe <jane@example.com>"]
+//!   ```
+//!
+//! - `index.toml` lists every version of every package with the path and
+//!   SHA-256 checksum of its manifest. It is generated by `paxy repository
+//!   validate --write-index`, and has to be regenerated whenever a manifest
+//!   changes. A gzip-compressed copy is written next to it as `index.toml.gz`,
+//!   which is what paxy downloads from repositories served over HTTP.
+//!
+//! Repositories are checked against this layout with [`validate`].
+
+/// The name of the file describing a repository.
+pub const METADATA_FILE_NAME: &str = "repository.toml";
+
+/// The name of the generated index of the packages in a repository.
+pub const PACKAGE_INDEX_FILE_NAME: &str = "index.toml";
+
+/// The name of the compressed copy of the package index.
+pub const COMPRESSED_PACKAGE_INDEX_FILE_NAME: &str = "index.toml.gz";
+
+/// The version of the repository layout understood by this build of paxy.
+pub const REPOSITORY_FORMAT_VERSION: u32 = 1;
+
+/// The contents of `repository.toml`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RepositoryMetadata {
+    pub format_version: u32,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// The people responsible for the repository, like
+    /// `Jane Doe <jane@example.com>`.
+    #[serde(default)]
+    pub maintainers: Vec<String>,
+}
+
+impl RepositoryMetadata {
+    /// Reads `repository.toml` from the repository at the given path, if it
+    /// has one.
+    pub fn load(repository_dirpath: &Path) -> Result<Option<Self>, Error> {
+        let filepath = repository_dirpath.join(METADATA_FILE_NAME);
+        if !filepath.is_file() {
+            return Ok(None);
+        }
+        let text = fs::read_to_string(&filepath).context(ReadSnafu { path: &filepath })?;
+
+        toml::from_str(&text)
+            .context(ParseSnafu { path: &filepath })
+            .map(Some)
+    }
+}
+
+/// The contents of `index.toml`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PackageIndex {
+    pub format_version: u32,
+    #[serde(default)]
+    pub packages: Vec<IndexedPackage>,
+}
+
+/// A version of a package listed in the index of a repository.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IndexedPackage {
+    pub name: String,
+    pub version: Version,
+    /// The path of the manifest relative to the top of the repository, with
+    /// `/` as the separator.
+    pub manifest: String,
+    pub sha256: String,
+}
+
+impl PackageIndex {
+    /// Generates the index of the packages in the repository at the given
+    /// path.
+    pub fn generate(repository_dirpath: &Path) -> Result<Self, Error> {
+        let packages = repository::manifest_entries(repository_dirpath)
+            .context(RepositorySnafu {})?
+            .into_iter()
+            .map(|entry| {
+                let sha256 = data::sha256_of_file(&entry.manifest_filepath).context(ReadSnafu {
+                    path: &entry.manifest_filepath,
+                })?;
+                let manifest = entry
+                    .manifest_filepath
+                    .strip_prefix(repository_dirpath)
+                    .unwrap_or(&entry.manifest_filepath)
+                    .components()
+                    .map(|component| {
+                        component
+                            .as_os_str()
+                            .to_string_lossy()
+                            .into_owned()
+                    })
+                    .collect::<Vec<_>>()
+                    .join("/");
+
+                Ok(IndexedPackage {
+                    name: entry.name,
+                    version: entry.version,
+                    manifest,
+                    sha256,
+                })
+            })
+            .collect::<Result<_, Error>>()?;
+
+        Ok(Self {
+            format_version: REPOSITORY_FORMAT_VERSION,
+            packages,
+        })
+    }
+
+    /// Reads `index.toml` from the repository at the given path, if it has
+    /// one.
+    pub fn load(repository_dirpath: &Path) -> Result<Option<Self>, Error> {
+        let filepath = repository_dirpath.join(PACKAGE_INDEX_FILE_NAME);
+        if !filepath.is_file() {
+            return Ok(None);
+        }
+        let text = fs::read_to_string(&filepath).context(ReadSnafu { path: &filepath })?;
+
+        Self::parse(&text)
+            .context(ParseSnafu { path: &filepath })
+            .map(Some)
+    }
+
+    /// Parses the text of an index, such as one downloaded from a remote
+    /// repository.
+    pub fn parse(text: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(text)
+    }
+
+    /// Writes `index.toml` and its compressed copy into the repository at the
+    /// given path.
+    pub fn save(&self, repository_dirpath: &Path) -> Result<(), Error> {
+        let filepath = repository_dirpath.join(PACKAGE_INDEX_FILE_NAME);
+        let text = toml::to_string(self).context(SerializeSnafu {})?;
+        data::write_atomically(&filepath, text.as_bytes())
+            .context(WriteSnafu { path: &filepath })?;
+
+        let compressed_filepath = repository_dirpath.join(COMPRESSED_PACKAGE_INDEX_FILE_NAME);
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+        encoder
+            .write_all(text.as_bytes())
+            .and_then(|_| encoder.finish())
+            .and_then(|compressed| data::write_atomically(&compressed_filepath, &compressed))
+            .context(WriteSnafu {
+                path: &compressed_filepath,
+            })
+    }
+
+    /// Decompresses and parses the compressed copy of an index.
+    /// Returns the index along with its text.
+    pub fn parse_compressed(compressed: &[u8]) -> Result<(Self, String), Error> {
+        let mut text = String::new();
+        GzDecoder::new(compressed)
+            .read_to_string(&mut text)
+            .context(DecompressSnafu {})?;
+        let index = Self::parse(&text).context(ParseCompressedSnafu {})?;
+
+        Ok((index, text))
+    }
+}
+
+/// How bad a problem found in a repository is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// The repository works, but something is probably not as intended.
+    Warning,
+    /// The repository must not be published like this.
+    Error,
+}
+
+/// A problem found in a repository.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Problem {
+    pub severity: Severity,
+    /// The file or directory the problem is in, relative to the top of the
+    /// repository.
+    pub path: PathBuf,
+    pub message: String,
+}
+
+/// Checks the repository at the given path against the layout: its
+/// `repository.toml`, every manifest, and whether `index.toml` is up to date.
+/// Problems are returned rather than failing, so that all of them are found
+/// at once.
+pub fn validate(repository_dirpath: &Path) -> Result<Vec<Problem>, Error> {
+    let mut problems = Vec::new();
+    let mut report = |severity, path: &Path, message: String| {
+        problems.push(Problem {
+            severity,
+            path: path
+                .strip_prefix(repository_dirpath)
+                .unwrap_or(path)
+                .to_path_buf(),
+            message,
+        })
+    };
+
+    let metadata_filepath = repository_dirpath.join(METADATA_FILE_NAME);
+    match RepositoryMetadata::load(repository_dirpath) {
+        Ok(None) => report(
+            Severity::Error,
+            &metadata_filepath,
+            "is missing".to_string(),
+        ),
+        Ok(Some(metadata)) => {
+            if metadata.format_version != REPOSITORY_FORMAT_VERSION {
+                report(
+                    Severity::Error,
+                    &metadata_filepath,
+                    format!(
+                        "declares format version {}, but only version \
+                         {REPOSITORY_FORMAT_VERSION} is supported",
+                        metadata.format_version
+                    ),
+                );
+            }
+            if metadata
+                .name
+                .trim()
+                .is_empty()
+            {
+                report(
+                    Severity::Error,
+                    &metadata_filepath,
+                    "has an empty name".to_string(),
+                );
+            }
+            if metadata
+                .maintainers
+                .is_empty()
+            {
+                report(
+                    Severity::Warning,
+                    &metadata_filepath,
+                    "lists no maintainers".to_string(),
+                );
+            }
+        }
+        Err(error) => report(Severity::Error, &metadata_filepath, error.to_string()),
+    }
+
+    let entries = repository::manifest_entries(repository_dirpath).context(RepositorySnafu {})?;
+    if entries.is_empty() {
+        report(
+            Severity::Error,
+            repository_dirpath,
+            "has no packages. Each version of a package goes in \
+             `<package>/<version>/manifest.toml`"
+                .to_string(),
+        );
+    }
+    let package_names: BTreeSet<&str> = entries
+        .iter()
+        .map(|entry| entry.name.as_str())
+        .collect();
+    for entry in entries.iter() {
+        let manifest = match PackageManifest::from_path(&entry.manifest_filepath) {
+            Ok(manifest) => manifest,
+            Err(error) => {
+                report(Severity::Error, &entry.manifest_filepath, error.to_string());
+                continue;
+            }
+        };
+        if manifest.name != entry.name {
+            report(
+                Severity::Error,
+                &entry.manifest_filepath,
+                format!(
+                    "is named '{}', but is in the directory of '{}'",
+                    manifest.name, entry.name
+                ),
+            );
+        }
+        if manifest.version != entry.version {
+            report(
+                Severity::Error,
+                &entry.manifest_filepath,
+                format!(
+                    "is for version {}, but is in the directory of version {}",
+                    manifest.version, entry.version
+                ),
+            );
+        }
+        for dependency_name in manifest
+            .dependencies_with(
+                &manifest
+                    .features
+                    .keys()
+                    .cloned()
+                    .collect(),
+            )
+            .keys()
+        {
+            if !package_names.contains(dependency_name.as_str()) {
+                report(
+                    Severity::Warning,
+                    &entry.manifest_filepath,
+                    format!(
+                        "depends on '{dependency_name}', which is not in this repository and \
+                         has to come from another one"
+                    ),
+                );
+            }
+        }
+    }
+
+    for package_dirpath in subdirectories(repository_dirpath)? {
+        let version_dirpaths = subdirectories(&package_dirpath)?;
+        for version_dirpath in version_dirpaths.iter() {
+            let is_version = version_dirpath
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| Version::parse(name).is_ok());
+            if !is_version {
+                report(
+                    Severity::Warning,
+                    version_dirpath,
+                    "is not named after a version, and is ignored".to_string(),
+                );
+            } else if manifest::manifest_filepath_in(version_dirpath).is_none() {
+                report(
+                    Severity::Error,
+                    version_dirpath,
+                    "has no manifest".to_string(),
+                );
+            }
+        }
+    }
+
+    let index_filepath = repository_dirpath.join(PACKAGE_INDEX_FILE_NAME);
+    match PackageIndex::load(repository_dirpath) {
+        Ok(None) => report(
+            Severity::Error,
+            &index_filepath,
+            "is missing. Generate it with --write-index".to_string(),
+        ),
+        Ok(Some(index)) => {
+            if index != PackageIndex::generate(repository_dirpath)? {
+                report(
+                    Severity::Error,
+                    &index_filepath,
+                    "is out of date. Regenerate it with --write-index".to_string(),
+                );
+            }
+            let compressed_filepath = repository_dirpath.join(COMPRESSED_PACKAGE_INDEX_FILE_NAME);
+            match fs::read(&compressed_filepath) {
+                Ok(compressed) => {
+                    if PackageIndex::parse_compressed(&compressed)
+                        .map_or(true, |(compressed_index, _)| compressed_index != index)
+                    {
+                        report(
+                            Severity::Error,
+                            &compressed_filepath,
+                            "does not match index.toml. Regenerate it with --write-index"
+                                .to_string(),
+                        );
+                    }
+                }
+                Err(_) => report(
+                    Severity::Warning,
+                    &compressed_filepath,
+                    "is missing, so the repository cannot be served over HTTP. Generate it \
+                     with --write-index"
+                        .to_string(),
+                ),
+            }
+        }
+        Err(error) => report(Severity::Error, &index_filepath, error.to_string()),
+    }
+
+    problems.sort_by(|a, b| {
+        a.path
+            .cmp(&b.path)
+            .then_with(|| {
+                b.severity
+                    .cmp(&a.severity)
+            })
+    });
+
+    Ok(problems)
+}
+
+/// Lists the visible subdirectories of a directory, which the packages and
+/// their versions are kept in.
+fn subdirectories(dirpath: &Path) -> Result<Vec<PathBuf>, Error> {
+    let mut subdirectories = Vec::new();
+    for entry in fs::read_dir(dirpath).context(ReadSnafu { path: dirpath })? {
+        let path = entry
+            .context(ReadSnafu { path: dirpath })?
+            .path();
+        let is_hidden = path
+            .file_name()
+            .is_some_and(|name| {
+                name.to_string_lossy()
+                    .starts_with('.')
+            });
+        if path.is_dir() && !is_hidden {
+            subdirectories.push(path);
+        }
+    }
+    subdirectories.sort();
+
+    Ok(subdirectories)
+}
+
+// region: ERRORS
+
+#[derive(Debug, Snafu)]
+#[snafu(visibility(pub(crate)))]
+#[non_exhaustive]
+pub enum Error {
+    #[non_exhaustive]
+    #[snafu(display("could not read {path:?}: {source}"))]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("could not parse {path:?}: {source}"))]
+    Parse {
+        path: PathBuf,
+        #[snafu(source(from(toml::de::Error, Box::new)))]
+        source: Box<toml::de::Error>,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("could not decompress the package index: {source}"))]
+    Decompress { source: std::io::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("could not parse the compressed package index: {source}"))]
+    ParseCompressed {
+        #[snafu(source(from(toml::de::Error, Box::new)))]
+        source: Box<toml::de::Error>,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("could not serialize the package index: {source}"))]
+    Serialize { source: toml::ser::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("could not write {path:?}: {source}"))]
+    Write {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("in the repository: {source}"))]
+    Repository { source: repository::Error },
+}
+
+// endregion: ERRORS
+
+// region: IMPORTS
+
+use std::{
+    collections::BTreeSet,
+    fs,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use snafu::{ResultExt, Snafu};
+
+use crate::data::{
+    self,
+    manifest::{self, PackageManifest},
+    repository,
+};
+
+// endregion: IMPORTS
+
+// region: TESTS
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::repository::tests::write_manifest;
+
+    #[test]
+    fn validate_a_repository_and_its_index() {
+        let repository_dirpath = tempfile::tempdir().unwrap();
+        let repository_dirpath = repository_dirpath.path();
+        write_manifest(repository_dirpath, "hello", "1.0.0", "");
+        for (file, contents) in [
+            (
+                "hello/1.1.0/manifest.toml",
+                "format_version = 1\nname = \"hello\"\nversion = \"1.2.0\"\n[dependencies]\nlibc \
+                 = \"^2\"\n",
+            ),
+            ("hello/latest/README", "not a version"),
+        ] {
+            let filepath = repository_dirpath.join(file);
+            fs::create_dir_all(
+                filepath
+                    .parent()
+                    .unwrap(),
+            )
+            .unwrap();
+            fs::write(filepath, contents).unwrap();
+        }
+        let problems_in = |repository_dirpath: &Path| {
+            validate(repository_dirpath)
+                .unwrap()
+                .into_iter()
+                .map(|problem| {
+                    (
+                        problem.severity,
+                        problem
+                            .path
+                            .to_string_lossy()
+                            .into_owned(),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            problems_in(repository_dirpath),
+            [
+                (Severity::Error, "hello/1.1.0/manifest.toml".to_string()),
+                (Severity::Warning, "hello/1.1.0/manifest.toml".to_string()),
+                (Severity::Warning, "hello/latest".to_string()),
+                (Severity::Error, "index.toml".to_string()),
+                (Severity::Error, "repository.toml".to_string()),
+            ]
+        );
+
+        fs::write(
+            repository_dirpath.join(METADATA_FILE_NAME),
+            "format_version = 1\nname = \"main\"\nmaintainers = [\"Jane Doe\"]\n",
+        )
+        .unwrap();
+        write_manifest(repository_dirpath, "hello", "1.1.0", "");
+        fs::remove_dir_all(repository_dirpath.join("hello/latest")).unwrap();
+        PackageIndex::generate(repository_dirpath)
+            .unwrap()
+            .save(repository_dirpath)
+            .unwrap();
+        assert!(problems_in(repository_dirpath).is_empty());
+
+        let index = PackageIndex::load(repository_dirpath)
+            .unwrap()
+            .unwrap();
+        assert_eq!(index.packages[1].manifest, "hello/1.1.0/manifest.toml");
+
+        write_manifest(repository_dirpath, "hello", "1.0.0", "description = \"Hi\"");
+        assert_eq!(
+            problems_in(repository_dirpath),
+            [(Severity::Error, "index.toml".to_string())]
+        );
+    }
+}
+
+// endregion: TESTS
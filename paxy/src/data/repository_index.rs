@@ -0,0 +1,313 @@
+//! The index of known repositories, a curated TOML file listing third-party
+//! repositories that can be installed, such as
+//!
+//! ```toml
+//! format_version = 1
+//!
+//! [[repositories]]
+//! name = "games"
+//! url = "https://example.com/paxy-games.git"
+//! description = "Games and emulators"
+//! tags = ["games"]
+//! ```
+//!
+//! It is fetched from a configurable URL and cached in
+//! `~/.paxy/index/repositories.toml`, so that searching it works offline.
+
+/// Where the index of known repositories is fetched from, unless the
+/// configuration says otherwise.
+pub const DEFAULT_REPOSITORY_INDEX_URL: &str =
+    "https://raw.githubusercontent.com/Pax-Hub/paxy-pkg-repository/main/repositories.toml";
+
+/// The version of the index format understood by this build of paxy.
+pub const REPOSITORY_INDEX_FORMAT_VERSION: u32 = 1;
+
+/// How long a cached index is used before it is fetched again.
+pub const MAX_CACHE_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The index of known repositories.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RepositoryIndex {
+    pub format_version: u32,
+    #[serde(default)]
+    pub repositories: Vec<KnownRepository>,
+}
+
+/// A repository listed in the index of known repositories.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KnownRepository {
+    pub name: String,
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Keywords the repository can be found by when searching.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+}
+
+impl RepositoryIndex {
+    /// Returns the path of the cached index inside the given paxy home.
+    pub fn cache_filepath(paxy_home: &Path) -> PathBuf {
+        paxy_home
+            .join("index")
+            .join("repositories.toml")
+    }
+
+    /// Returns the index, from the cache if it is younger than the maximum
+    /// age and a refresh was not asked for, or else fetched from the given
+    /// URL and cached. If fetching fails, a stale cache is used instead.
+    pub fn load(paxy_home: &Path, url: &Url, refresh: bool) -> Result<Self, Error> {
+        let cache_filepath = Self::cache_filepath(paxy_home);
+        let cache_age = fs::metadata(&cache_filepath)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| {
+                modified
+                    .elapsed()
+                    .ok()
+            });
+        if !refresh && cache_age.is_some_and(|cache_age| cache_age < MAX_CACHE_AGE) {
+            if let Ok(index) = Self::load_cache(&cache_filepath) {
+                return Ok(index);
+            }
+        }
+
+        match fetch(url).and_then(|text| Ok((Self::parse(&text)?, text))) {
+            Ok((index, text)) => {
+                if let Err(error) = data::write_atomically(&cache_filepath, text.as_bytes()) {
+                    tracing::warn!("Could not cache the index of repositories: {error}");
+                }
+                Ok(index)
+            }
+            Err(error) if cache_age.is_some() => {
+                tracing::warn!("{error}. Using the index of repositories cached earlier");
+                Self::load_cache(&cache_filepath)
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    fn load_cache(cache_filepath: &Path) -> Result<Self, Error> {
+        let text = fs::read_to_string(cache_filepath).context(ReadCacheSnafu {
+            path: cache_filepath,
+        })?;
+        Self::parse(&text)
+    }
+
+    /// Parses and checks the text of an index.
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        let index: Self = toml::from_str(text).context(ParseSnafu {})?;
+        ensure!(
+            index.format_version == REPOSITORY_INDEX_FORMAT_VERSION,
+            UnsupportedFormatVersionSnafu {
+                found: index.format_version
+            }
+        );
+
+        Ok(index)
+    }
+
+    /// Returns the repositories matching all the given terms by name,
+    /// description or tags, those matching by name first. Without any terms,
+    /// every repository matches.
+    pub fn search(&self, terms: &[String]) -> Vec<&KnownRepository> {
+        let terms: Vec<String> = terms
+            .iter()
+            .map(|term| term.to_lowercase())
+            .collect();
+
+        let mut matches: Vec<(&KnownRepository, bool)> = self
+            .repositories
+            .iter()
+            .filter_map(|repository| {
+                let name = repository
+                    .name
+                    .to_lowercase();
+                let text = repository
+                    .description
+                    .iter()
+                    .chain(
+                        repository
+                            .tags
+                            .iter(),
+                    )
+                    .map(|text| text.to_lowercase())
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                terms
+                    .iter()
+                    .all(|term| name.contains(term) || text.contains(term))
+                    .then(|| {
+                        (
+                            repository,
+                            terms
+                                .iter()
+                                .any(|term| name.contains(term)),
+                        )
+                    })
+            })
+            .collect();
+        matches.sort_by(|(a, a_by_name), (b, b_by_name)| {
+            b_by_name
+                .cmp(a_by_name)
+                .then_with(|| a.name.cmp(&b.name))
+        });
+
+        matches
+            .into_iter()
+            .map(|(repository, _)| repository)
+            .collect()
+    }
+}
+
+/// Reads the text at a `file://` URL, or downloads it from any other.
+fn fetch(url: &Url) -> Result<String, Error> {
+    if url.scheme() == "file" {
+        let filepath = url
+            .to_file_path()
+            .ok()
+            .context(UnsupportedUrlSnafu { url: url.as_str() })?;
+        return fs::read_to_string(&filepath).context(ReadSnafu { url: url.as_str() });
+    }
+
+    reqwest::blocking::get(url.clone())
+        .and_then(|response| response.error_for_status())
+        .and_then(|response| response.text())
+        .context(DownloadSnafu { url: url.as_str() })
+}
+
+// region: ERRORS
+
+#[derive(Debug, Snafu)]
+#[snafu(visibility(pub(crate)))]
+#[non_exhaustive]
+pub enum Error {
+    #[non_exhaustive]
+    #[snafu(display("could not download the index of repositories from {url}: {source}"))]
+    Download { url: String, source: reqwest::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("could not read the index of repositories at {url}: {source}"))]
+    Read { url: String, source: std::io::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("{url} does not point to a file"))]
+    UnsupportedUrl { url: String },
+
+    #[non_exhaustive]
+    #[snafu(display("could not read the cached index of repositories at {path:?}: {source}"))]
+    ReadCache {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("the index of repositories is invalid: {source}"))]
+    Parse { source: toml::de::Error },
+
+    #[non_exhaustive]
+    #[snafu(display(
+        "the index of repositories is in format version {found}, but only version \
+         {REPOSITORY_INDEX_FORMAT_VERSION} is supported. Update paxy"
+    ))]
+    UnsupportedFormatVersion { found: u32 },
+}
+
+// endregion: ERRORS
+
+// region: IMPORTS
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use snafu::{ensure, OptionExt, ResultExt, Snafu};
+use url::Url;
+
+use crate::data;
+
+// endregion: IMPORTS
+
+// region: TESTS
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INDEX: &str = r#"
+format_version = 1
+
+[[repositories]]
+name = "games"
+url = "https://example.com/games.git"
+description = "Games and emulators"
+
+[[repositories]]
+name = "retro-tools"
+url = "https://example.com/retro.git"
+tags = ["emulators", "games"]
+
+[[repositories]]
+name = "science"
+url = "https://example.com/science.git"
+"#;
+
+    #[test]
+    fn search_a_cached_index() {
+        let paxy_home = tempfile::tempdir().unwrap();
+        let source = tempfile::tempdir().unwrap();
+        let source_filepath = source
+            .path()
+            .join("repositories.toml");
+        fs::write(&source_filepath, INDEX).unwrap();
+        let url = Url::from_file_path(&source_filepath).unwrap();
+
+        let index = RepositoryIndex::load(paxy_home.path(), &url, false).unwrap();
+        let names = |index: &RepositoryIndex, terms: &[&str]| {
+            index
+                .search(
+                    &terms
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>(),
+                )
+                .iter()
+                .map(|repository| {
+                    repository
+                        .name
+                        .clone()
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(&index, &["GAMES"]), ["games", "retro-tools"]);
+        assert_eq!(names(&index, &["emulators", "retro"]), ["retro-tools"]);
+        assert_eq!(names(&index, &[]).len(), 3);
+
+        // The cache is used while it is fresh, and when the source is gone
+        fs::write(&source_filepath, "format_version = 1\n").unwrap();
+        let index = RepositoryIndex::load(paxy_home.path(), &url, false).unwrap();
+        assert_eq!(
+            index
+                .repositories
+                .len(),
+            3
+        );
+        let index = RepositoryIndex::load(paxy_home.path(), &url, true).unwrap();
+        assert!(index
+            .repositories
+            .is_empty());
+        fs::remove_file(&source_filepath).unwrap();
+        assert!(RepositoryIndex::load(paxy_home.path(), &url, true).is_ok());
+
+        assert!(matches!(
+            RepositoryIndex::parse("format_version = 2\n"),
+            Err(Error::UnsupportedFormatVersion { found: 2 })
+        ));
+    }
+}
+
+// endregion: TESTS
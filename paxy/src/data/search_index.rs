@@ -0,0 +1,529 @@
+//! The search index of a repository, kept at `~/.paxy/index/<repository
+//! name>.bson`. It summarizes the newest version of every package in the
+//! repository, and maps every word in their names, tags and descriptions to
+//! where it occurs, so that searching reads a single file instead of every
+//! manifest.
+//!
+//! The index records a fingerprint of the repository it was built from, and is
+//! rebuilt when that changes. Git and HTTP repositories only change when they
+//! are updated, which rebuilds their index, so they are fingerprinted by the
+//! commit checked out or the package index synced. Local repositories are
+//! edited in place, so the paths, sizes and modification times of their
+//! manifests are fingerprinted instead. Words
+//! are matched exactly, by prefix, by substring, and with a typo or two, so
+//! that `ripgrp` still finds `ripgrep`.
+
+/// Where in a package summary a word occurs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Field {
+    Name,
+    Tag,
+    Description,
+}
+
+impl Field {
+    /// How much a match in the field counts for, relative to the others.
+    fn weight(self) -> u32 {
+        match self {
+            Self::Name => 10,
+            Self::Tag => 3,
+            Self::Description => 1,
+        }
+    }
+}
+
+/// An occurrence of a word in the package summary at the given position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Posting {
+    pub package: u32,
+    pub field: Field,
+}
+
+/// The search index of a single repository.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SearchIndex {
+    /// The fingerprint of the repository the index was built from.
+    pub fingerprint: String,
+    pub packages: Vec<PackageSummary>,
+    /// Every lowercase word in the package summaries, mapped to where it
+    /// occurs.
+    pub words: BTreeMap<String, Vec<Posting>>,
+}
+
+impl SearchIndex {
+    /// Returns the path of the index of the named repository inside the given
+    /// paxy home.
+    pub fn filepath(paxy_home: &Path, repository_name: &str) -> PathBuf {
+        paxy_home
+            .join("index")
+            .join(format!("{repository_name}.bson"))
+    }
+
+    /// Returns the index of a repository, rebuilding it first if it is
+    /// missing or out of date. An index that cannot be saved is still used.
+    pub fn open(paxy_home: &Path, local_repository: &LocalRepository) -> Result<Self, Error> {
+        let filepath = Self::filepath(paxy_home, &local_repository.name);
+        let fingerprint = fingerprint(local_repository)?;
+        let index = Self::load(&filepath).unwrap_or_else(|error| {
+            tracing::warn!("Rebuilding the search index: {}", error);
+            Self::default()
+        });
+        if index.fingerprint == fingerprint {
+            return Ok(index);
+        }
+
+        let index = Self::build(local_repository)?;
+        if let Err(error) = index.save(&filepath) {
+            tracing::warn!("Could not save the search index: {}", error);
+        }
+
+        Ok(index)
+    }
+
+    /// Rebuilds and saves the index of a repository.
+    pub fn rebuild(paxy_home: &Path, local_repository: &LocalRepository) -> Result<Self, Error> {
+        let index = Self::build(local_repository)?;
+        index.save(&Self::filepath(paxy_home, &local_repository.name))?;
+
+        Ok(index)
+    }
+
+    /// Builds the index of a repository.
+    pub fn build(local_repository: &LocalRepository) -> Result<Self, Error> {
+        let fingerprint = fingerprint(local_repository)?;
+        let packages =
+            repository::package_summaries(&local_repository.dirpath).context(RepositorySnafu {})?;
+
+        let mut words: BTreeMap<String, Vec<Posting>> = BTreeMap::new();
+        for (package, summary) in (0..).zip(packages.iter()) {
+            let texts = std::iter::once((
+                Field::Name,
+                summary
+                    .name
+                    .as_str(),
+            ))
+            .chain(
+                summary
+                    .tags
+                    .iter()
+                    .map(|tag| (Field::Tag, tag.as_str())),
+            )
+            .chain(
+                summary
+                    .description
+                    .iter()
+                    .map(|description| (Field::Description, description.as_str())),
+            );
+            for (field, text) in texts {
+                for word in words_of(text, field != Field::Description) {
+                    let postings = words
+                        .entry(word)
+                        .or_default();
+                    let posting = Posting { package, field };
+                    if !postings.contains(&posting) {
+                        postings.push(posting);
+                    }
+                }
+            }
+        }
+
+        Ok(Self {
+            fingerprint,
+            packages,
+            words,
+        })
+    }
+
+    /// Loads the index from the given file. A missing file is an empty index,
+    /// which is out of date for any repository.
+    pub fn load(filepath: &Path) -> Result<Self, Error> {
+        if !filepath.is_file() {
+            return Ok(Self::default());
+        }
+
+        let file = File::open(filepath).context(ReadIndexSnafu { path: filepath })?;
+        let document = Document::from_reader(file).context(DecodeIndexSnafu { path: filepath })?;
+        bson::from_document(document).context(DeserializeIndexSnafu { path: filepath })
+    }
+
+    /// Writes the index to the given file atomically.
+    pub fn save(&self, filepath: &Path) -> Result<(), Error> {
+        let document = bson::to_document(self).context(SerializeIndexSnafu {})?;
+        let mut buffer = Vec::new();
+        document
+            .to_writer(&mut buffer)
+            .context(EncodeIndexSnafu {})?;
+        data::write_atomically(filepath, &buffer).context(WriteIndexSnafu { path: filepath })
+    }
+
+    /// Finds the packages matching every one of the search terms, along with
+    /// how well they match. Without any terms, every package matches with a
+    /// score of zero.
+    pub fn search(&self, terms: &[String]) -> Vec<(&PackageSummary, u32)> {
+        let mut scores: BTreeMap<u32, u32> = (0..)
+            .zip(self.packages.iter())
+            .map(|(package, _)| (package, 0))
+            .collect();
+
+        for term in terms {
+            let term = term.to_lowercase();
+            // The best match of the term in each field of each package
+            let mut best_matches: BTreeMap<(u32, Field), u32> = BTreeMap::new();
+            for (word, postings) in self.words.iter() {
+                let Some(quality) = match_quality(&term, word) else {
+                    continue;
+                };
+                for posting in postings {
+                    let best_match = best_matches
+                        .entry((posting.package, posting.field))
+                        .or_default();
+                    *best_match = (*best_match).max(quality);
+                }
+            }
+
+            let mut term_scores: BTreeMap<u32, u32> = BTreeMap::new();
+            for ((package, field), quality) in best_matches {
+                *term_scores
+                    .entry(package)
+                    .or_default() += quality * field.weight();
+            }
+            scores = scores
+                .into_iter()
+                .filter_map(|(package, score)| {
+                    term_scores
+                        .get(&package)
+                        .map(|term_score| (package, score + term_score))
+                })
+                .collect();
+        }
+
+        scores
+            .into_iter()
+            .filter_map(|(package, score)| {
+                self.packages
+                    .get(package as usize)
+                    .map(|summary| (summary, score))
+            })
+            .collect()
+    }
+}
+
+/// Splits a text into lowercase words. Names and tags are also kept whole, so
+/// that `gnu-grep` can be found as it is written.
+fn words_of(text: &str, keep_whole: bool) -> Vec<String> {
+    let text = text.to_lowercase();
+    let mut words: Vec<String> = text
+        .split(|character: char| !character.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_string)
+        .collect();
+    if keep_whole && !words.contains(&text) && !text.is_empty() {
+        words.push(text);
+    }
+
+    words
+}
+
+/// Rates how well a lowercase search term matches a word, from 10 for the
+/// same word down, or returns `None` if it does not match. A term matches a
+/// word it starts or is part of, or one it is a typo or two away from.
+fn match_quality(term: &str, word: &str) -> Option<u32> {
+    if term == word {
+        return Some(10);
+    }
+    if word.starts_with(term) {
+        return Some(6);
+    }
+
+    let term_length = term.chars().count();
+    let allowed_typos = match term_length {
+        0..=3 => 0,
+        4..=7 => 1,
+        _ => 2,
+    };
+    let word_length = word.chars().count();
+    if allowed_typos > 0 && term_length.abs_diff(word_length) <= allowed_typos {
+        match typo_distance(term, word) {
+            1 => return Some(5),
+            2 if allowed_typos >= 2 => return Some(3),
+            _ => {}
+        }
+    }
+    if word.contains(term) {
+        return Some(4);
+    }
+
+    None
+}
+
+/// Returns the number of inserted, deleted, substituted or swapped adjacent
+/// characters it takes to turn one string into the other.
+fn typo_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut distances: Vec<Vec<usize>> = (0..=a.len())
+        .map(|i| {
+            let mut row = vec![0; b.len() + 1];
+            row[0] = i;
+            row
+        })
+        .collect();
+    distances[0] = (0..=b.len()).collect();
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let substitution_cost = usize::from(a[i - 1] != b[j - 1]);
+            distances[i][j] = (distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1)
+                .min(distances[i - 1][j - 1] + substitution_cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distances[i][j] = distances[i][j].min(distances[i - 2][j - 2] + 1);
+            }
+        }
+    }
+
+    distances[a.len()][b.len()]
+}
+
+/// Fingerprints a repository by the commit checked out or the package index
+/// synced, depending on its kind. Local repositories, and those with neither
+/// to go by, are fingerprinted by their manifests.
+fn fingerprint(local_repository: &LocalRepository) -> Result<String, Error> {
+    let dirpath = &local_repository.dirpath;
+    let revision = match local_repository.kind {
+        RepositoryKind::Git => head_commit(dirpath).map(|commit| format!("git {commit}")),
+        RepositoryKind::Http => http::revision(dirpath).map(|revision| format!("http {revision}")),
+        RepositoryKind::Local => None,
+    };
+    match revision {
+        Some(revision) => Ok(revision),
+        None => manifests_fingerprint(dirpath),
+    }
+}
+
+/// Returns the id of the commit checked out in the git repository at the
+/// given path, if there is one.
+fn head_commit(repository_dirpath: &Path) -> Option<String> {
+    Repository::open(repository_dirpath)
+        .ok()?
+        .head()
+        .ok()?
+        .peel_to_commit()
+        .ok()
+        .map(|commit| {
+            commit
+                .id()
+                .to_string()
+        })
+}
+
+/// Fingerprints the manifests in a repository by their paths, sizes and
+/// modification times.
+fn manifests_fingerprint(repository_dirpath: &Path) -> Result<String, Error> {
+    let mut hasher = Sha256::new();
+    for entry in repository::manifest_entries(repository_dirpath).context(RepositorySnafu {})? {
+        let metadata = fs::metadata(&entry.manifest_filepath).context(FingerprintSnafu {
+            path: &entry.manifest_filepath,
+        })?;
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| {
+                modified
+                    .duration_since(UNIX_EPOCH)
+                    .ok()
+            })
+            .unwrap_or_default();
+        hasher.update(format!(
+            "{}\t{}\t{}\n",
+            entry
+                .manifest_filepath
+                .display(),
+            metadata.len(),
+            modified.as_nanos()
+        ));
+    }
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+// region: ERRORS
+
+#[derive(Debug, Snafu)]
+#[snafu(visibility(pub(crate)))]
+#[non_exhaustive]
+pub enum Error {
+    #[non_exhaustive]
+    #[snafu(display("in the repository: {source}"))]
+    Repository { source: repository::Error },
+
+    #[non_exhaustive]
+    #[snafu(display("could not read the manifest at {path:?}: {source}"))]
+    Fingerprint {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("could not read the search index at {path:?}: {source}"))]
+    ReadIndex {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("the search index at {path:?} is corrupt: {source}"))]
+    DecodeIndex {
+        path: PathBuf,
+        source: bson::de::Error,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("the search index at {path:?} has unexpected contents: {source}"))]
+    DeserializeIndex {
+        path: PathBuf,
+        source: bson::de::Error,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("could not serialize the search index: {source}"))]
+    SerializeIndex {
+        #[snafu(source(from(bson::ser::Error, Box::new)))]
+        source: Box<bson::ser::Error>,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("could not encode the search index: {source}"))]
+    EncodeIndex {
+        #[snafu(source(from(bson::ser::Error, Box::new)))]
+        source: Box<bson::ser::Error>,
+    },
+
+    #[non_exhaustive]
+    #[snafu(display("could not write the search index to {path:?}: {source}"))]
+    WriteIndex {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+}
+
+// endregion: ERRORS
+
+// region: IMPORTS
+
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use bson::Document;
+use git2::Repository;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use snafu::{ResultExt, Snafu};
+
+use crate::data::{
+    self,
+    registry::RepositoryKind,
+    repository::{self, http, LocalRepository, PackageSummary},
+};
+
+// endregion: IMPORTS
+
+// region: TESTS
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::repository::tests::{commit_all, write_manifest};
+
+    fn names<'a>(hits: &[(&'a PackageSummary, u32)]) -> Vec<&'a str> {
+        let mut hits = hits.to_vec();
+        hits.sort_by(|a, b| {
+            b.1.cmp(&a.1)
+                .then_with(|| {
+                    a.0.name
+                        .cmp(&b.0.name)
+                })
+        });
+        hits.iter()
+            .map(|(summary, _)| {
+                summary
+                    .name
+                    .as_str()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn find_packages_despite_typos() {
+        let paxy_home = tempfile::tempdir().unwrap();
+        let local_repository = LocalRepository {
+            name: "main".to_string(),
+            dirpath: data::repositories_dirpath(paxy_home.path()).join("main"),
+            kind: RepositoryKind::Local,
+        };
+        write_manifest(
+            &local_repository.dirpath,
+            "ripgrep",
+            "1.0.0",
+            "description = \"Recursively search directories for a regex pattern\"",
+        );
+        write_manifest(
+            &local_repository.dirpath,
+            "fd",
+            "1.0.0",
+            "tags = [\"find\"]",
+        );
+
+        let index = SearchIndex::open(paxy_home.path(), &local_repository).unwrap();
+        assert_eq!(names(&index.search(&["ripgrp".to_string()])), ["ripgrep"]);
+        assert_eq!(names(&index.search(&["REGEX".to_string()])), ["ripgrep"]);
+        assert_eq!(names(&index.search(&["fidn".to_string()])), ["fd"]);
+        assert!(index
+            .search(&["ripgrep".to_string(), "find".to_string()])
+            .is_empty());
+
+        // The saved index is rebuilt once the repository changes
+        write_manifest(&local_repository.dirpath, "ripgrep-all", "1.0.0", "");
+        let index = SearchIndex::open(paxy_home.path(), &local_repository).unwrap();
+        assert_eq!(
+            names(&index.search(&["ripgrep".to_string()])),
+            ["ripgrep", "ripgrep-all"]
+        );
+    }
+
+    #[test]
+    fn rebuild_the_index_of_a_clone_for_new_commits_only() {
+        let paxy_home = tempfile::tempdir().unwrap();
+        let local_repository = LocalRepository {
+            name: "main".to_string(),
+            dirpath: data::repositories_dirpath(paxy_home.path()).join("main"),
+            kind: RepositoryKind::Git,
+        };
+        let repository = Repository::init(&local_repository.dirpath).unwrap();
+        write_manifest(&local_repository.dirpath, "ripgrep", "1.0.0", "");
+        commit_all(&repository, "Add ripgrep");
+        let search = || {
+            names(
+                &SearchIndex::open(paxy_home.path(), &local_repository)
+                    .unwrap()
+                    .search(&["ripgrep".to_string()]),
+            )
+            .into_iter()
+            .map(str::to_string)
+            .collect::<Vec<_>>()
+        };
+        assert_eq!(search(), ["ripgrep"]);
+
+        // The manifests of a clone are not read again until a new commit
+        write_manifest(&local_repository.dirpath, "ripgrep-all", "1.0.0", "");
+        assert_eq!(search(), ["ripgrep"]);
+        commit_all(&repository, "Add ripgrep-all");
+        assert_eq!(search(), ["ripgrep", "ripgrep-all"]);
+    }
+}
+
+// endregion: TESTS